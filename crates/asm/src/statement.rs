@@ -1,4 +1,4 @@
-pub use self::{assembler::*, parser::*};
+pub use self::parser::*;
 use hack::{Comp, Dest, InstC, Jump};
 use std::{borrow::Cow, fmt, hash::Hash};
 
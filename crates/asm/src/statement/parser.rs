@@ -50,7 +50,7 @@ struct Parts<'a> {
     jump: &'a str,
 }
 
-fn split_into_parts(s: &str) -> Parts {
+fn split_into_parts(s: &str) -> Parts<'_> {
     let mut dest = "";
     let comp;
     let mut jump = "";
@@ -88,7 +88,7 @@ enum Token<'a> {
     Punct(char),
 }
 
-fn read_token(s: &str) -> Option<(Token, &str)> {
+fn read_token(s: &str) -> Option<(Token<'_>, &str)> {
     let s = s.trim();
     if s.is_empty() {
         return None;
@@ -111,26 +111,33 @@ impl DirOrFileReader {
         path: impl Into<PathBuf>,
         extension: impl AsRef<OsStr>,
     ) -> Result<Self, DirOrFileReaderOpenError> {
+        let paths = Self::list(path, extension)?.into_iter();
+        Ok(Self { paths })
+    }
+
+    /// Returns the paths of the files `open` reads: the files of the directory with the extension,
+    /// or the path itself if it is not a directory.
+    pub fn list(
+        path: impl Into<PathBuf>,
+        extension: impl AsRef<OsStr>,
+    ) -> Result<Vec<PathBuf>, DirOrFileReaderOpenError> {
         let path = path.into();
-        let paths = if path.is_dir() {
-            path.read_dir()
-                .map_err(|e| DirOrFileReaderOpenError::ReadDir(path.clone(), e))?
-                .filter_map(|entry| {
-                    entry
-                        .map_err(|e| DirOrFileReaderOpenError::ReadDirEntry(path.clone(), e))
-                        .map(|entry| {
-                            let path = entry.path();
-                            (path.is_file() && path.extension() == Some(extension.as_ref()))
-                                .then(|| path)
-                        })
-                        .transpose()
-                })
-                .collect::<Result<_, _>>()?
-        } else {
-            vec![path]
+        if !path.is_dir() {
+            return Ok(vec![path]);
         }
-        .into_iter();
-        Ok(Self { paths })
+        path.read_dir()
+            .map_err(|e| DirOrFileReaderOpenError::ReadDir(path.clone(), e))?
+            .filter_map(|entry| {
+                entry
+                    .map_err(|e| DirOrFileReaderOpenError::ReadDirEntry(path.clone(), e))
+                    .map(|entry| {
+                        let path = entry.path();
+                        (path.is_file() && path.extension() == Some(extension.as_ref()))
+                            .then_some(path)
+                    })
+                    .transpose()
+            })
+            .collect()
     }
 }
 
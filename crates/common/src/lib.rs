@@ -1,2 +1,4 @@
 pub mod fs;
 pub mod iter;
+pub mod thread;
+pub mod watch;
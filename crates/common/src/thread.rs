@@ -0,0 +1,94 @@
+use std::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Returns the number of threads to run by default, one per available CPU.
+pub fn default_jobs() -> NonZeroUsize {
+    thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Maps the items on a pool of `jobs` threads, which take the items in order as they become free.
+/// The results are returned in the order of the items, however long each item takes.
+pub fn map_parallel<T, R, F>(items: &[T], jobs: NonZeroUsize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = jobs.get().min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(index) {
+                            Some(item) => results.push((index, f(item))),
+                            None => break results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| match worker.join() {
+                Ok(results) => results,
+                Err(payload) => std::panic::resume_unwind(payload),
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn jobs(n: usize) -> NonZeroUsize {
+        NonZeroUsize::new(n).unwrap()
+    }
+
+    #[test]
+    fn order_is_preserved() {
+        // earlier items take longer, so that they finish after later ones
+        let items = (0..16).collect::<Vec<u64>>();
+        let results = map_parallel(&items, jobs(4), |item| {
+            thread::sleep(Duration::from_millis((16 - item) * 5));
+            item * 10
+        });
+        assert_eq!(
+            results,
+            items.iter().map(|item| item * 10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn single_job() {
+        let main = thread::current().id();
+        let items = [3, 1, 2];
+        let results = map_parallel(&items, jobs(1), |item| {
+            assert_eq!(thread::current().id(), main);
+            item + 1
+        });
+        assert_eq!(results, [4, 2, 3]);
+    }
+
+    #[test]
+    fn more_jobs_than_items() {
+        let items = ["a", "b"];
+        let results = map_parallel(&items, jobs(8), |item| item.to_uppercase());
+        assert_eq!(results, ["A", "B"]);
+        assert!(map_parallel(&[] as &[u8], jobs(8), |item| *item).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::fs::DirOrFileReader;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt, fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Watches the files [`DirOrFileReader::open`] reads from the paths, by polling their metadata
+/// so that it also works where file system notifications are unavailable, e.g. in containers.
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    extension: OsString,
+    stamps: BTreeMap<PathBuf, Stamp>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Watcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>, extension: impl Into<OsString>) -> Self {
+        let mut watcher = Self {
+            paths: paths.into_iter().collect(),
+            extension: extension.into(),
+            stamps: BTreeMap::new(),
+        };
+        watcher.stamps = watcher.poll();
+        watcher
+    }
+
+    /// Returns the files currently watched.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.stamps.keys().cloned().collect()
+    }
+
+    fn poll(&self) -> BTreeMap<PathBuf, Stamp> {
+        self.paths
+            .iter()
+            // a directory which cannot be read is watched until it can
+            .flat_map(|path| DirOrFileReader::list(path, &self.extension).unwrap_or_default())
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let stamp = Stamp {
+                    modified: metadata.modified().ok(),
+                    len: metadata.len(),
+                };
+                Some((path, stamp))
+            })
+            .collect()
+    }
+
+    /// Blocks until files are added, modified or removed, and returns their paths. Changes are
+    /// collected until a poll finds no more, so that a file being written is seen once.
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        let mut changed = BTreeSet::new();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let stamps = self.poll();
+            if stamps == self.stamps {
+                if !changed.is_empty() {
+                    return changed.into_iter().collect();
+                }
+                continue;
+            }
+            for path in self.stamps.keys().chain(stamps.keys()) {
+                if self.stamps.get(path) != stamps.get(path) {
+                    changed.insert(path.clone());
+                }
+            }
+            self.stamps = stamps;
+        }
+    }
+
+    /// Runs `build` on every file, then again on the changed files whenever they change. Build
+    /// errors are printed instead of ending the loop.
+    pub fn run<E>(&mut self, mut build: impl FnMut(&[PathBuf]) -> Result<(), E>) -> !
+    where
+        E: fmt::Debug,
+    {
+        let mut changed = self.files();
+        loop {
+            if let Err(e) = build(&changed) {
+                eprintln!("Error: {:?}", e);
+            }
+            eprintln!("watching for changes...");
+            changed = self.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wait() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("Main.jack");
+        let point = dir.path().join("Point.jack");
+        fs::write(&main, "class Main {}").unwrap();
+        fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let mut watcher = Watcher::new([dir.path().to_owned()], "jack");
+        assert_eq!(watcher.files(), vec![main.clone()]);
+
+        // files with another extension are not watched
+        fs::write(&point, "class Point {}").unwrap();
+        fs::write(dir.path().join("notes.txt"), "changed").unwrap();
+        assert_eq!(watcher.wait(), vec![point.clone()]);
+
+        fs::write(&main, "class Main { }").unwrap();
+        assert_eq!(watcher.wait(), vec![main.clone()]);
+
+        fs::remove_file(&main).unwrap();
+        assert_eq!(watcher.wait(), [main]);
+        assert_eq!(watcher.files(), [point]);
+    }
+}
@@ -1,22 +1,35 @@
 use asm::{hack::Instruction, Executable};
-use color_eyre::eyre::{ensure, Context, Result};
-use common::fs::{FileReader, FileWriter};
+use color_eyre::eyre::{bail, ensure, eyre, Context, Result};
+use common::{
+    fs::{FileReader, FileWriter},
+    watch::Watcher,
+};
 use std::{env, io::prelude::*, path::PathBuf};
 
 #[derive(Debug)]
 struct Params {
     input_path: PathBuf,
     output_path: PathBuf,
+    watch: bool,
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let params = parse_args()?;
+    if params.watch {
+        Watcher::new([params.input_path.clone()], "asm").run(|_| build(&params));
+    }
+    build(&params)
+}
+
+fn build(params: &Params) -> Result<()> {
     let Params {
         input_path,
         output_path,
-    } = parse_args()?;
+        watch: _,
+    } = params;
 
-    let mut reader = FileReader::open(&input_path)
+    let mut reader = FileReader::open(input_path)
         .wrap_err_with(|| format!("failed to open input file: {}", input_path.display()))?;
 
     let exec = Executable::from_reader(reader.reader())
@@ -25,7 +38,7 @@ fn main() -> Result<()> {
         .assemble()
         .wrap_err_with(|| format!("failed to assemble file: {}", input_path.display()))?;
 
-    let mut writer = FileWriter::open(&output_path)
+    let mut writer = FileWriter::open(output_path)
         .wrap_err_with(|| format!("failed to create output file: {}", output_path.display()))?;
     write_output_file(writer.writer(), &insts)
         .wrap_err_with(|| format!("failed to write output file: {}", output_path.display()))?;
@@ -37,15 +50,29 @@ fn main() -> Result<()> {
 }
 
 fn parse_args() -> Result<Params> {
-    let args = env::args().collect::<Vec<_>>();
-    ensure!(args.len() == 2, "Usage: {} <file>", args[0]);
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "hasm".to_string());
+    let usage = || format!("Usage: {} [--watch] <file>", program);
 
-    let input_path = PathBuf::from(&args[1]);
+    let mut input_path = None;
+    let mut watch = false;
+    for arg in args {
+        match arg.as_str() {
+            "--watch" => watch = true,
+            _ if arg.starts_with('-') => bail!(usage()),
+            _ => {
+                ensure!(input_path.is_none(), usage());
+                input_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+    let input_path = input_path.ok_or_else(|| eyre!(usage()))?;
     let output_path = input_path.with_extension("hack");
 
     Ok(Params {
         input_path,
         output_path,
+        watch,
     })
 }
 
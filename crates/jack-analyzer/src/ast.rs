@@ -8,6 +8,9 @@ impl WriteXml for Class {
             Keyword::Class.write_xml(indent, writer)?;
             self.name.write_xml(indent, writer)?;
             Symbol::OpenBrace.write_xml(indent, writer)?;
+            for constant in &self.consts {
+                constant.write_xml(indent, writer)?;
+            }
             for var in &self.vars {
                 var.write_xml(indent, writer)?;
             }
@@ -37,6 +40,20 @@ impl WriteXml for ClassVarDec {
     }
 }
 
+impl WriteXml for ConstDec {
+    fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
+        writer.write_multi(indent, "constDec", |indent, writer| {
+            Keyword::Const.write_xml(indent, writer)?;
+            self.ty.write_xml(indent, writer)?;
+            self.name.write_xml(indent, writer)?;
+            Symbol::Equal.write_xml(indent, writer)?;
+            self.expr.write_xml(indent, writer)?;
+            Symbol::Semicolon.write_xml(indent, writer)?;
+            Ok(())
+        })
+    }
+}
+
 impl WriteXml for ClassVarKind {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
         let kw = match self {
@@ -153,6 +170,17 @@ impl WriteXml for Statement {
             Statement::While(stmt) => stmt.write_xml(indent, writer),
             Statement::Do(stmt) => stmt.write_xml(indent, writer),
             Statement::Return(stmt) => stmt.write_xml(indent, writer),
+            Statement::For(stmt) => stmt.write_xml(indent, writer),
+            Statement::Break => writer.write_multi(indent, "breakStatement", |indent, writer| {
+                Keyword::Break.write_xml(indent, writer)?;
+                Symbol::Semicolon.write_xml(indent, writer)
+            }),
+            Statement::Continue => {
+                writer.write_multi(indent, "continueStatement", |indent, writer| {
+                    Keyword::Continue.write_xml(indent, writer)?;
+                    Symbol::Semicolon.write_xml(indent, writer)
+                })
+            }
         }
     }
 }
@@ -160,21 +188,27 @@ impl WriteXml for Statement {
 impl WriteXml for LetStatement {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
         writer.write_multi(indent, "letStatement", |indent, writer| {
-            Keyword::Let.write_xml(indent, writer)?;
-            self.target.write_xml(indent, writer)?;
-            if let Some(index) = &self.target_index {
-                Symbol::OpenBracket.write_xml(indent, writer)?;
-                index.write_xml(indent, writer)?;
-                Symbol::CloseBracket.write_xml(indent, writer)?;
-            }
-            Symbol::Equal.write_xml(indent, writer)?;
-            self.expr.write_xml(indent, writer)?;
+            write_let_clause(self, indent, writer)?;
             Symbol::Semicolon.write_xml(indent, writer)?;
             Ok(())
         })
     }
 }
 
+/// Writes the tokens of a let statement without the trailing `;`.
+fn write_let_clause(stmt: &LetStatement, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
+    Keyword::Let.write_xml(indent, writer)?;
+    stmt.target.write_xml(indent, writer)?;
+    if let Some(index) = &stmt.target_index {
+        Symbol::OpenBracket.write_xml(indent, writer)?;
+        index.write_xml(indent, writer)?;
+        Symbol::CloseBracket.write_xml(indent, writer)?;
+    }
+    Symbol::Equal.write_xml(indent, writer)?;
+    stmt.expr.write_xml(indent, writer)?;
+    Ok(())
+}
+
 impl WriteXml for IfStatement {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
         writer.write_multi(indent, "ifStatement", |indent, writer| {
@@ -211,6 +245,35 @@ impl WriteXml for WhileStatement {
     }
 }
 
+impl WriteXml for ForStatement {
+    fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
+        writer.write_multi(indent, "forStatement", |indent, writer| {
+            Keyword::For.write_xml(indent, writer)?;
+            Symbol::OpenParen.write_xml(indent, writer)?;
+            if let Some(init) = &self.init {
+                writer.write_multi(indent, "letClause", |indent, writer| {
+                    write_let_clause(&init.data, indent, writer)
+                })?;
+            }
+            Symbol::Semicolon.write_xml(indent, writer)?;
+            if let Some(cond) = &self.cond {
+                cond.write_xml(indent, writer)?;
+            }
+            Symbol::Semicolon.write_xml(indent, writer)?;
+            if let Some(step) = &self.step {
+                writer.write_multi(indent, "letClause", |indent, writer| {
+                    write_let_clause(&step.data, indent, writer)
+                })?;
+            }
+            Symbol::CloseParen.write_xml(indent, writer)?;
+            Symbol::OpenBrace.write_xml(indent, writer)?;
+            self.stmts.write_xml(indent, writer)?;
+            Symbol::CloseBrace.write_xml(indent, writer)?;
+            Ok(())
+        })
+    }
+}
+
 impl WriteXml for DoStatement {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
         writer.write_multi(indent, "doStatement", |indent, writer| {
@@ -254,8 +317,15 @@ impl WriteXml for Term {
         writer.write_multi(indent, "term", |indent, writer| match self {
             Term::IntConstant(n) => n.write_xml(indent, writer),
             Term::StringConstant(s) => s.write_xml(indent, writer),
+            Term::CharConstant(ch) => ch.write_xml(indent, writer),
             Term::KeywordConstant(k) => k.write_xml(indent, writer),
             Term::Variable(v) => v.write_xml(indent, writer),
+            Term::ClassConstant(class_name, name) => {
+                class_name.write_xml(indent, writer)?;
+                Symbol::Dot.write_xml(indent, writer)?;
+                name.write_xml(indent, writer)?;
+                Ok(())
+            }
             Term::Index(var, index) => {
                 var.write_xml(indent, writer)?;
                 Symbol::OpenBracket.write_xml(indent, writer)?;
@@ -0,0 +1,207 @@
+use crate::{cli::Params, Error};
+use common::fs::FileWriter;
+use jack::{diagnostic::Diagnostic, lint::Lint, symbol_table::GlobalSymbolTable, token::Ident};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Hashes with 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds, so that the
+/// hashes recorded in the cache stay comparable.
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Hashes the options which change the VM code or the diagnostics of a class, along with the
+/// version of the compiler.
+pub(crate) fn options_hash(params: &Params) -> u64 {
+    let lint_levels = Lint::ALL.map(|lint| params.lint_levels.level(lint));
+    let options = format!(
+        "{} {:?} {:?} {} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        params.parse_options,
+        params.cfg_options,
+        params.optimize,
+        lint_levels
+    );
+    hash(options.as_bytes())
+}
+
+/// What the VM code of a class is compiled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ClassKey {
+    /// The hash of the source of the class.
+    source: u64,
+    /// The hash of the interfaces of the classes named in the source.
+    interfaces: u64,
+}
+
+impl ClassKey {
+    /// Computes the key of a class once every class is in the symbol table. An identifier of the
+    /// source which is not a class still matters, as it may name a class added later.
+    pub(crate) fn new<'a>(
+        source: &[u8],
+        idents: impl IntoIterator<Item = &'a Ident>,
+        symbol_table: &GlobalSymbolTable,
+    ) -> Self {
+        let mut idents = idents.into_iter().map(Ident::as_str).collect::<Vec<_>>();
+        idents.sort_unstable();
+        idents.dedup();
+        let interfaces = idents
+            .into_iter()
+            .filter_map(|ident| {
+                symbol_table
+                    .class_interface(ident)
+                    .map(|interface| format!("class {}\n{}\n", ident, interface))
+            })
+            .collect::<String>();
+        Self {
+            source: hash(source),
+            interfaces: hash(interfaces.as_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    key: ClassKey,
+    /// The hash of the VM code written for the class.
+    vm: u64,
+    /// The warnings of the class, reported again when it is reused.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The classes compiled by previous runs, so that a class can reuse its VM file as long as
+/// neither its source nor the interfaces of the classes it uses change.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Cache {
+    /// The hash of the options the classes were compiled with.
+    options: u64,
+    classes: BTreeMap<PathBuf, Entry>,
+}
+
+impl Cache {
+    /// Loads the cache file. A missing or unreadable cache, or one written with other options,
+    /// is treated as empty.
+    pub(crate) fn load(path: &Path, options: u64) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Self>(&bytes).ok())
+            .filter(|cache| cache.options == options)
+            .unwrap_or_else(|| Self::new(options))
+    }
+
+    pub(crate) fn new(options: u64) -> Self {
+        Self {
+            options,
+            classes: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the warnings of the class if its VM file was compiled from the same key, and is
+    /// unchanged since.
+    pub(crate) fn lookup(
+        &self,
+        input_path: &Path,
+        key: ClassKey,
+        vm_path: &Path,
+    ) -> Option<&[Diagnostic]> {
+        let entry = self
+            .classes
+            .get(input_path)
+            .filter(|entry| entry.key == key)?;
+        let vm = fs::read(vm_path).ok()?;
+        (hash(&vm) == entry.vm).then_some(entry.diagnostics.as_slice())
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        input_path: &Path,
+        key: ClassKey,
+        vm: &str,
+        diagnostics: Vec<Diagnostic>,
+    ) {
+        let entry = Entry {
+            key,
+            vm: hash(vm.as_bytes()),
+            diagnostics,
+        };
+        self.classes.insert(input_path.to_owned(), entry);
+    }
+
+    /// Forgets the classes which are no longer compiled.
+    pub(crate) fn retain(&mut self, input_paths: &[&Path]) {
+        self.classes
+            .retain(|path, _| input_paths.contains(&path.as_path()));
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = FileWriter::open(path)
+            .map_err(|e| Error::CreateOutputFile(path.to_owned(), e.into()))?;
+        serde_json::to_writer_pretty(writer.writer(), self)
+            .map_err(|e| Error::WriteCache(path.to_owned(), e.into()))?;
+        writer
+            .persist()
+            .map_err(|e| Error::PersistOutputFile(path.to_owned(), e.into()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jack::{
+        ast::Class,
+        token::{Token, Tokens},
+    };
+
+    fn class_key(main: &str, foo: &str) -> ClassKey {
+        let mut table = GlobalSymbolTable::with_builtin();
+        for (name, source) in [("Main", main), ("Foo", foo)] {
+            let ast = Class::from_tokens(Tokens::from_reader(source.as_bytes()))
+                .into_result()
+                .unwrap();
+            table
+                .extend_with_class(format!("{}.jack", name), &ast.data)
+                .unwrap();
+        }
+        let idents = Tokens::from_reader(main.as_bytes())
+            .filter_map(|token| match token.unwrap().data {
+                Token::Ident(ident) => Some(ident),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        ClassKey::new(main.as_bytes(), &idents, &table)
+    }
+
+    #[test]
+    fn class_key_interfaces() {
+        let main = "class Main { function int f() { return Foo.g(); } }";
+        let foo = "class Foo { function int g() { return 1; } }";
+        let key = class_key(main, foo);
+        assert_eq!(
+            class_key(main, "class Foo { function int g() { return 2; } }"),
+            key
+        );
+        assert_ne!(
+            class_key(main, "class Foo { function int g(int x) { return x; } }"),
+            key
+        );
+        assert_ne!(
+            class_key("class Main { function int f() { return Foo.g() ; } }", foo),
+            key
+        );
+    }
+
+    #[test]
+    fn fnv1a() {
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{
+    output::{OutputFormat, OutputFormats, Stage},
+    ErrorFormat,
+};
+use color_eyre::eyre::{bail, eyre, Result};
+use jack::{
+    ast::ParseOptions,
+    lint::{Level, Lint, LintLevels},
+    typed_ast::CfgOptions,
+};
+use std::{env, num::NonZeroUsize, path::PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    Tokenize,
+    Parse,
+    Check,
+    DumpCfg,
+    Compile,
+}
+
+impl Command {
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "tokenize" => Some(Self::Tokenize),
+            "parse" => Some(Self::Parse),
+            "check" => Some(Self::Check),
+            "dump-cfg" => Some(Self::DumpCfg),
+            "compile" => Some(Self::Compile),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tokenize => "tokenize",
+            Self::Parse => "parse",
+            Self::Check => "check",
+            Self::DumpCfg => "dump-cfg",
+            Self::Compile => "compile",
+        }
+    }
+
+    /// Returns the last stage the command runs.
+    pub(crate) fn last_stage(self, optimize: bool) -> Stage {
+        match (self, optimize) {
+            (Self::Tokenize, _) => Stage::Token,
+            (Self::Parse, _) => Stage::Ast,
+            (Self::Check | Self::DumpCfg, true) => Stage::CfgOptimized,
+            (Self::Check | Self::DumpCfg, false) => Stage::Cfg,
+            (Self::Compile, _) => Stage::Vm,
+        }
+    }
+
+    /// Returns the stages emitted without `--emit`.
+    fn default_emit(self, optimize: bool) -> Vec<Stage> {
+        match (self, optimize) {
+            (Self::Tokenize, _) => vec![Stage::Token],
+            (Self::Parse, _) => vec![Stage::Ast],
+            (Self::Check, _) => vec![],
+            (Self::DumpCfg, true) => vec![Stage::Cfg, Stage::CfgOptimized],
+            (Self::DumpCfg, false) => vec![Stage::Cfg],
+            (Self::Compile, _) => vec![Stage::Vm],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Params {
+    pub(crate) command: Command,
+    pub(crate) input_paths: Vec<PathBuf>,
+    /// Paths of classes which the inputs may use, e.g. JackOS, without being compiled.
+    pub(crate) lib_paths: Vec<PathBuf>,
+    pub(crate) out_dir: Option<PathBuf>,
+    pub(crate) emit: Vec<Stage>,
+    pub(crate) optimize: bool,
+    pub(crate) error_format: ErrorFormat,
+    pub(crate) lint_levels: LintLevels,
+    pub(crate) parse_options: ParseOptions,
+    pub(crate) cfg_options: CfgOptions,
+    pub(crate) output_formats: OutputFormats,
+    pub(crate) dot: bool,
+    /// Path of the cache of the classes compiled by previous runs.
+    pub(crate) cache_path: Option<PathBuf>,
+    /// Number of threads compiling the classes.
+    pub(crate) jobs: NonZeroUsize,
+    pub(crate) watch: bool,
+}
+
+fn usage(program: &str) -> String {
+    format!(
+        "\
+Usage: {} <command> [options] <path>...
+
+Commands:
+    tokenize    Write the tokens of the classes
+    parse       Write the syntax trees of the classes
+    check       Check the classes without writing any file
+    dump-cfg    Write the control-flow graphs of the subroutines
+    compile     Compile the classes to VM code
+
+Options:
+    -o, --out-dir <dir>     Write the files to <dir> instead of next to the sources
+    --emit <stage>,...      Write the results of the stages: token, ast, typed-ast, cfg,
+                            cfg-optimized, vm
+    -O0, -O1                Disable or enable the optimization of control-flow graphs
+                            (default: -O1)
+    -L <path>               Use the classes in <path> without compiling them
+    -j, --jobs <n>          Compile <n> classes at a time (default: the number of CPUs)
+    --output-format [<stage>=]<xml|json|sexp>
+                            Set the format of the files of a stage, or of all stages
+    --dot                   Also write the control-flow graphs in Graphviz DOT format
+    --cache <file>          Reuse the VM files of the classes unchanged since the previous
+                            compilation recorded in <file> (compile only)
+    --watch                 Run the command again whenever the inputs or libraries change
+    --error-format <human|json>
+    --precedence            Use the conventional operator precedence
+    --extensions            Enable the language extensions
+    --short-circuit         Evaluate `&` and `|` conditions lazily
+    -A|-W|-D <lint|all>     Allow, warn or deny a lint",
+        program
+    )
+}
+
+pub(crate) fn parse_args() -> Result<Params> {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "jack-analyzer".to_string());
+    let usage = || usage(&program);
+
+    let command = args
+        .next()
+        .and_then(|arg| Command::from_str(&arg))
+        .ok_or_else(|| eyre!(usage()))?;
+    let mut input_paths = vec![];
+    let mut lib_paths = vec![];
+    let mut out_dir = None;
+    let mut emit = None;
+    let mut optimize = true;
+    let mut error_format = ErrorFormat::Human;
+    let mut lint_levels = LintLevels::default();
+    let mut parse_options = ParseOptions::default();
+    let mut cfg_options = CfgOptions::default();
+    let mut output_formats = OutputFormats::default();
+    let mut dot = false;
+    let mut cache_path = None;
+    let mut jobs = common::thread::default_jobs();
+    let mut watch = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-A" | "-W" | "-D" => {
+                let level = match arg.as_str() {
+                    "-A" => Level::Allow,
+                    "-W" => Level::Warn,
+                    _ => Level::Deny,
+                };
+                match args.next().as_deref() {
+                    Some("all") => lint_levels.set_all(level),
+                    Some(id) => {
+                        let lint =
+                            Lint::from_id(id).ok_or_else(|| eyre!("unknown lint: {}", id))?;
+                        lint_levels.set(lint, level);
+                    }
+                    None => bail!(usage()),
+                }
+            }
+            "-o" | "--out-dir" => {
+                out_dir = Some(PathBuf::from(args.next().ok_or_else(|| eyre!(usage()))?));
+            }
+            "--emit" => {
+                let stages = args.next().ok_or_else(|| eyre!(usage()))?;
+                let stages = stages
+                    .split(',')
+                    .map(|stage| {
+                        Stage::from_str(stage).ok_or_else(|| eyre!("unknown stage: {}", stage))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                emit = Some(stages);
+            }
+            "-O0" => optimize = false,
+            "-O1" => optimize = true,
+            "-L" => lib_paths.push(PathBuf::from(args.next().ok_or_else(|| eyre!(usage()))?)),
+            "--precedence" => parse_options.precedence = true,
+            "--extensions" => parse_options.extensions = true,
+            "--short-circuit" => cfg_options.short_circuit = true,
+            "--dot" => dot = true,
+            "-j" | "--jobs" => {
+                let n = args.next().ok_or_else(|| eyre!(usage()))?;
+                jobs = n
+                    .parse()
+                    .map_err(|_| eyre!("invalid number of jobs: {}", n))?;
+            }
+            "--watch" => watch = true,
+            "--cache" => {
+                cache_path = Some(PathBuf::from(args.next().ok_or_else(|| eyre!(usage()))?));
+            }
+            "--output-format" => {
+                let arg = args.next().ok_or_else(|| eyre!(usage()))?;
+                let (stage, format) = match arg.split_once('=') {
+                    Some((stage, format)) => (Some(stage), format),
+                    None => (None, arg.as_str()),
+                };
+                let format = OutputFormat::from_str(format)
+                    .ok_or_else(|| eyre!("unknown output format: {}", format))?;
+                match stage {
+                    Some(stage) => {
+                        let stage = Stage::from_str(stage)
+                            .filter(|stage| *stage != Stage::Vm)
+                            .ok_or_else(|| eyre!("unknown stage: {}", stage))?;
+                        output_formats.set(stage, format);
+                    }
+                    None => output_formats.set_all(format),
+                }
+            }
+            "--error-format" => {
+                error_format = match args.next().as_deref() {
+                    Some("human") => ErrorFormat::Human,
+                    Some("json") => ErrorFormat::Json,
+                    _ => bail!(usage()),
+                }
+            }
+            _ if arg.starts_with('-') => bail!(usage()),
+            _ => input_paths.push(PathBuf::from(arg)),
+        }
+    }
+    if input_paths.is_empty() {
+        bail!(usage());
+    }
+
+    let emit = emit.unwrap_or_else(|| command.default_emit(optimize));
+    check_emit(command, optimize, &emit)?;
+    if cache_path.is_some() {
+        check_cache(command, &emit)?;
+    }
+
+    Ok(Params {
+        command,
+        input_paths,
+        lib_paths,
+        out_dir,
+        emit,
+        optimize,
+        error_format,
+        lint_levels,
+        parse_options,
+        cfg_options,
+        output_formats,
+        dot,
+        cache_path,
+        jobs,
+        watch,
+    })
+}
+
+/// Checks that the command runs every emitted stage.
+fn check_emit(command: Command, optimize: bool, emit: &[Stage]) -> Result<()> {
+    let last_stage = command.last_stage(optimize);
+    for stage in emit {
+        if *stage > last_stage || (*stage == Stage::CfgOptimized && !optimize) {
+            bail!(
+                "`{}` does not produce `{}`{}",
+                command.as_str(),
+                stage.as_str(),
+                if optimize { "" } else { " with -O0" }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the VM files are the only results which a cached class may skip.
+pub(crate) fn check_cache(command: Command, emit: &[Stage]) -> Result<()> {
+    if command != Command::Compile {
+        bail!("`--cache` is only supported by `compile`");
+    }
+    if !emit.contains(&Stage::Vm) {
+        bail!("`--cache` requires emitting `vm`");
+    }
+    if let Some(stage) = emit
+        .iter()
+        .find(|stage| matches!(stage, Stage::TypedAst | Stage::Cfg | Stage::CfgOptimized))
+    {
+        bail!(
+            "`--cache` cannot be used when emitting `{}`",
+            stage.as_str()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn emit() {
+        for command in [
+            Command::Tokenize,
+            Command::Parse,
+            Command::Check,
+            Command::DumpCfg,
+            Command::Compile,
+        ] {
+            for optimize in [false, true] {
+                assert!(check_emit(command, optimize, &command.default_emit(optimize)).is_ok());
+            }
+        }
+        assert!(check_emit(Command::Compile, true, &[Stage::Token, Stage::Vm]).is_ok());
+        assert!(check_emit(Command::Parse, true, &[Stage::TypedAst]).is_err());
+        assert!(check_emit(Command::Check, true, &[Stage::Vm]).is_err());
+        assert!(check_emit(Command::Compile, false, &[Stage::CfgOptimized]).is_err());
+    }
+
+    #[test]
+    fn cache() {
+        assert!(check_cache(Command::Compile, &[Stage::Token, Stage::Ast, Stage::Vm]).is_ok());
+        assert!(check_cache(Command::Compile, &[Stage::TypedAst, Stage::Vm]).is_err());
+        assert!(check_cache(Command::Compile, &[Stage::Ast]).is_err());
+        assert!(check_cache(Command::DumpCfg, &[Stage::Cfg]).is_err());
+    }
+}
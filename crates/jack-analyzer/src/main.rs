@@ -1,21 +1,31 @@
-use crate::xml::XmlWriter;
-use color_eyre::eyre::{ensure, Result};
-use common::{
-    fs::{DirOrFileReader, FileWriter},
-    iter::{IteratorExt, TryIterator},
+use crate::{
+    cache::{Cache, ClassKey},
+    cli::{Command, Params},
+    output::{Outputs, Stage},
 };
+use color_eyre::eyre::Result;
+use common::{fs::DirOrFileReader, iter::TryIterator, thread, watch::Watcher};
 use jack::{
-    ast::{Class, FromTokens},
+    ast::{Class, ParseOptions},
+    diagnostic::{Diagnostic, Severity, ToDiagnostic},
+    lint::{self, LintLevels},
     symbol_table::GlobalSymbolTable,
-    token::Tokens,
+    token::{Ident, Token, Tokens, WithLoc},
     typed_ast::ToControlFlowGraph,
 };
-use std::{env, io::prelude::*, path::PathBuf};
+use std::{
+    fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
-use token::TokenWriter;
 
 mod ast;
+mod cache;
+mod cli;
 mod control_flow_graph;
+mod output;
+mod sexp;
 mod token;
 mod typed_ast;
 mod xml;
@@ -26,121 +36,373 @@ type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
 pub enum Error {
     #[error("failed to open input file: {}", _0.display())]
     OpenInputFile(PathBuf, #[source] StdError),
+    #[error("failed to create output directory: {}", _0.display())]
+    CreateOutputDir(PathBuf, #[source] StdError),
     #[error("failed to create output file: {}", _0.display())]
     CreateOutputFile(PathBuf, #[source] StdError),
+    #[error("failed to read input file: {}", _0.display())]
+    ReadInputFile(PathBuf, #[source] StdError),
     #[error("failed to read token from file: {}", _0.display())]
     ReadToken(PathBuf, #[source] StdError),
-    #[error("failed to parse file: {}", _0.display())]
-    Parse(PathBuf, #[source] StdError),
-    #[error("failed to register symbols from file: {}", _0.display())]
-    ExtendSymbolTable(PathBuf, #[source] StdError),
+    #[error("failed to compile file: {} ({} errors)", _0.display(), _1)]
+    Compile(PathBuf, usize),
     #[error("failed to write xml to file: {}", _0.display())]
     WriteXml(PathBuf, #[source] StdError),
+    #[error("failed to write output to file: {}", _0.display())]
+    WriteOutput(PathBuf, #[source] StdError),
     #[error("failed to persist output file: {}", _0.display())]
     PersistOutputFile(PathBuf, #[source] StdError),
-    #[error("failed to resolve symbols in file: {}", _0.display())]
-    Resolve(PathBuf, #[source] StdError),
-    #[error("failed to convert to control flow graph file: {}", _0.display())]
-    ToCfg(PathBuf, #[source] StdError),
-    #[error("failed to optimize to control flow graph file: {}", _0.display())]
-    Optimize(PathBuf, #[source] StdError),
     #[error("failed to write VM command to file: {}", _0.display())]
     WriteVmCommand(PathBuf, #[source] StdError),
+    #[error("failed to write graph to file: {}", _0.display())]
+    WriteGraph(PathBuf, #[source] StdError),
+    #[error("failed to write cache to file: {}", _0.display())]
+    WriteCache(PathBuf, #[source] StdError),
 }
 
-#[derive(Debug)]
-struct Params {
-    input_path: PathBuf,
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ErrorFormat {
+    Human,
+    Json,
 }
 
-fn main() -> Result<()> {
-    color_eyre::install()?;
-    let Params { input_path } = parse_args()?;
+/// Prints the diagnostics of the file to stderr, with snippets of `source` as it was compiled.
+fn emit(format: ErrorFormat, path: &Path, source: &[u8], diagnostics: &[Diagnostic]) {
+    let source = String::from_utf8_lossy(source);
+    for diagnostic in diagnostics {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", diagnostic.render(path, &source)),
+            ErrorFormat::Json => {
+                let _ = diagnostic.write_json(path, &source, io::stderr().lock());
+            }
+        }
+    }
+}
 
-    let mut symbol_table = GlobalSymbolTable::with_builtin();
-    let mut asts = vec![];
-    let mut token_writers = vec![];
-    let mut xml_writers = vec![];
-    let mut file_writers = vec![];
-
-    DirOrFileReader::open(&input_path, "jack")?.try_for_each(|reader| {
-        let (input_path, reader) = reader
-            .map_err(|e| Error::OpenInputFile(input_path.clone(), e.into()))?
-            .into_parts();
-        let token_output_path = input_path.with_extension("token.xml");
-        let ast_output_path = input_path.with_extension("ast.xml");
-
-        let mut token_writer = TokenWriter::open(token_output_path)?;
-        let mut ast_writer = XmlWriter::open(ast_output_path)?;
-
-        let tokens = Tokens::from_reader(reader)
-            .map(|res| res.map_err(|e| Error::ReadToken(input_path.to_owned(), e.into())))
-            .try_inspect_ok(|token| token_writer.write(&token.data));
-
-        let ast = Class::from_tokens(&mut tokens.prependable())
-            .map_err(|e| Error::Parse(input_path.to_owned(), e.into()))?;
-        ast_writer.write(&ast)?;
-
-        symbol_table
-            .extend_with_class(&input_path, &ast.data)
-            .map_err(|e| Error::ExtendSymbolTable(input_path.to_owned(), e.into()))?;
-
-        asts.push((input_path, ast));
-        token_writers.push(token_writer);
-        xml_writers.push(ast_writer);
-        Ok::<(), Error>(())
-    })?;
-
-    for (input_path, ast) in asts {
-        let typed_ast_output_path = input_path.with_extension("typed-ast.xml");
-        let mut typed_ast_writer = XmlWriter::open(&typed_ast_output_path)?;
-        let typed_ast = ast
-            .resolve(&symbol_table)
-            .map_err(|e| Error::Resolve(input_path.clone(), e.into()))?;
-        typed_ast_writer.write(&typed_ast)?;
-        xml_writers.push(typed_ast_writer);
-
-        let cfg_output_path = input_path.with_extension("cfg.xml");
-        let mut cfg_writer = XmlWriter::open(&cfg_output_path)?;
-        let mut cfg = typed_ast
-            .to_control_flow_graph()
-            .map_err(|e| Error::ToCfg(input_path.clone(), e.into()))?;
-        cfg_writer.write(&cfg)?;
-        xml_writers.push(cfg_writer);
-
-        let cfg_opt_output_path = input_path.with_extension("cfg-optimized.xml");
-        let mut cfg_opt_writer = XmlWriter::open(&cfg_opt_output_path)?;
-        cfg.optimize()
-            .map_err(|e| Error::Optimize(input_path.clone(), e.into()))?;
-        cfg_opt_writer.write(&cfg)?;
-        xml_writers.push(cfg_opt_writer);
-
-        let vm_output_path = input_path.with_extension("vm");
-        let mut vm_writer = FileWriter::open(&vm_output_path)?;
-        let commands = cfg.to_vm();
-        for command in &commands {
-            writeln!(vm_writer.writer(), "{}", command)
-                .map_err(|e| Error::WriteVmCommand(input_path.clone(), e.into()))?;
+/// Collects diagnostics of the file, and returns the error to abort the compilation.
+fn diagnose<'a, E>(
+    path: &Path,
+    errors: impl IntoIterator<Item = &'a E>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Error
+where
+    E: ToDiagnostic + 'a,
+{
+    let len = diagnostics.len();
+    diagnostics.extend(errors.into_iter().map(|e| e.to_diagnostic()));
+    Error::Compile(path.to_owned(), diagnostics.len() - len)
+}
+
+/// Prints diagnostics of the file to stderr, and returns the error to abort the compilation.
+fn report<'a, E>(
+    format: ErrorFormat,
+    path: &Path,
+    source: &[u8],
+    errors: impl IntoIterator<Item = &'a E>,
+) -> Error
+where
+    E: ToDiagnostic + 'a,
+{
+    let mut diagnostics = vec![];
+    let error = diagnose(path, errors, &mut diagnostics);
+    emit(format, path, source, &diagnostics);
+    error
+}
+
+fn read_source(path: &Path, mut reader: impl BufRead) -> Result<Vec<u8>, Error> {
+    let mut source = vec![];
+    reader
+        .read_to_end(&mut source)
+        .map_err(|e| Error::ReadInputFile(path.to_owned(), e.into()))?;
+    Ok(source)
+}
+
+/// Reports the tokenize errors of the file, for the commands which do not parse it.
+fn tokenize(
+    path: &Path,
+    reader: impl BufRead,
+    params: &Params,
+    outputs: &mut Outputs,
+) -> Result<(), Error> {
+    let source = read_source(path, reader)?;
+    let mut token_writer = outputs.token_writer(path)?;
+    for token in
+        Tokens::from_reader(source.as_slice()).with_extensions(params.parse_options.extensions)
+    {
+        let token = token.map_err(|e| report(params.error_format, path, &source, [&e]))?;
+        if let Some(writer) = &mut token_writer {
+            writer.write(&token)?;
         }
-        file_writers.push(vm_writer);
     }
+    outputs.push_token_writer(token_writer);
+    Ok(())
+}
+
+/// A parsed input file.
+struct Parsed {
+    path: PathBuf,
+    source: Vec<u8>,
+    ast: WithLoc<Class>,
+    /// The lint levels, with the directives of the file applied.
+    lint_levels: LintLevels,
+    /// The identifiers of the file, which may name the classes it uses.
+    idents: Vec<Ident>,
+}
+
+/// Parses the file, with the lint levels set by its directives.
+fn parse(
+    path: PathBuf,
+    reader: impl BufRead,
+    params: &Params,
+    outputs: &mut Outputs,
+) -> Result<Parsed, Error> {
+    let source = read_source(&path, reader)?;
+    let path = path.as_path();
+
+    let mut token_writer = outputs.token_writer(path)?;
+    let mut idents = vec![];
+    let mut tokens =
+        Tokens::from_reader(source.as_slice()).with_extensions(params.parse_options.extensions);
+    let tokens_with_writer = tokens
+        .by_ref()
+        .map(|res| res.map_err(|e| Error::ReadToken(path.to_owned(), e.into())))
+        .try_inspect_ok(|token| {
+            if let Token::Ident(ident) = &token.data {
+                idents.push(ident.clone());
+            }
+            match &mut token_writer {
+                Some(writer) => writer.write(token),
+                None => Ok(()),
+            }
+        });
+
+    let ast = Class::from_tokens_with(tokens_with_writer, params.parse_options)
+        .into_result()
+        .map_err(|e| report(params.error_format, path, &source, &e.0))?;
+    outputs.push_token_writer(token_writer);
+    outputs.write(path, Stage::Ast, &ast)?;
+    let lint_levels = params
+        .lint_levels
+        .with_directives(tokens.comments())
+        .map_err(|e| report(params.error_format, path, &source, [&e]))?;
+    Ok(Parsed {
+        path: path.to_owned(),
+        source,
+        ast,
+        lint_levels,
+        idents,
+    })
+}
 
-    for token_writer in token_writers {
-        token_writer.persist()?;
+/// Adds the classes of the library paths to the symbol table. A class already defined by the
+/// inputs or by a previous library path is skipped.
+fn load_libraries(
+    lib_paths: &[PathBuf],
+    options: ParseOptions,
+    error_format: ErrorFormat,
+    symbol_table: &mut GlobalSymbolTable,
+) -> Result<()> {
+    for lib_path in lib_paths {
+        for reader in DirOrFileReader::open(lib_path, "jack")? {
+            let (path, reader) = reader
+                .map_err(|e| Error::OpenInputFile(lib_path.clone(), e.into()))?
+                .into_parts();
+            let source = read_source(&path, reader)?;
+            let tokens = Tokens::from_reader(source.as_slice())
+                .with_extensions(options.extensions)
+                .map(|res| res.map_err(|e| Error::ReadToken(path.clone(), e.into())));
+            let ast = Class::from_tokens_with(tokens, options)
+                .into_result()
+                .map_err(|e| report(error_format, &path, &source, &e.0))?;
+            if symbol_table.class(ast.data.name.data.as_str()).is_some() {
+                continue;
+            }
+            symbol_table
+                .extend_with_class(&path, &ast.data)
+                .map_err(|e| report(error_format, &path, &source, [&e]))?;
+        }
     }
-    for xml_writer in xml_writers {
-        xml_writer.persist()?;
+    Ok(())
+}
+
+/// The result of compiling a class on a worker thread.
+struct Compiled {
+    outputs: Outputs,
+    /// The diagnostics of the class, printed once the classes before it are reported.
+    diagnostics: Vec<Diagnostic>,
+    /// The key and the VM code to record in the cache, unless the class was reused from it.
+    result: Result<Option<(ClassKey, String)>, Error>,
+}
+
+/// Compiles a class once every class is in the symbol table. The diagnostics are collected
+/// rather than printed, as the classes are compiled concurrently.
+fn compile(
+    class: &Parsed,
+    symbol_table: &GlobalSymbolTable,
+    params: &Params,
+    cache: Option<&Cache>,
+    outputs: &mut Outputs,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Option<(ClassKey, String)>, Error> {
+    let path = class.path.as_path();
+    let key = cache.map(|_| ClassKey::new(&class.source, &class.idents, symbol_table));
+    if let (Some(cache), Some(key)) = (cache, key) {
+        if let Some(cached) = cache.lookup(path, key, &outputs.vm_path(path)) {
+            diagnostics.extend_from_slice(cached);
+            return Ok(None);
+        }
     }
-    for file_writer in file_writers {
-        file_writer.persist()?;
+
+    let typed_ast = class
+        .ast
+        .resolve(symbol_table)
+        .map_err(|e| diagnose(path, [&e], diagnostics))?;
+    outputs.write(path, Stage::TypedAst, &typed_ast)?;
+
+    let mut cfg = typed_ast
+        .to_control_flow_graph_with(&params.cfg_options)
+        .map_err(|e| diagnose(path, [&e], diagnostics))?;
+    outputs.write_cfg(path, Stage::Cfg, &cfg)?;
+
+    let warnings = lint::check(&class.ast.data, &typed_ast.data, &cfg.data);
+    diagnostics.extend(class.lint_levels.apply(&warnings));
+    let denied = diagnostics
+        .iter()
+        .filter(|diag| diag.severity == Severity::Error)
+        .count();
+    if denied > 0 {
+        return Err(Error::Compile(path.to_owned(), denied));
     }
 
-    Ok(())
+    if params.optimize {
+        cfg.optimize()
+            .map_err(|e| diagnose(path, [&e], diagnostics))?;
+        outputs.write_cfg(path, Stage::CfgOptimized, &cfg)?;
+    }
+    if params.command.last_stage(params.optimize) != Stage::Vm {
+        return Ok(None);
+    }
+    let vm = cfg
+        .to_vm()
+        .iter()
+        .map(|command| format!("{}\n", command))
+        .collect::<String>();
+    outputs.write_vm(path, &vm)?;
+    Ok(key.map(|key| (key, vm)))
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let params = cli::parse_args()?;
+    let options_hash = cache::options_hash(&params);
+    let mut cache = match &params.cache_path {
+        Some(path) => Some(Cache::load(path, options_hash)),
+        // when watching, the classes a change does not affect are reused from the previous build
+        None if params.watch && cli::check_cache(params.command, &params.emit).is_ok() => {
+            Some(Cache::new(options_hash))
+        }
+        None => None,
+    };
+
+    if params.watch {
+        let paths = params.input_paths.iter().chain(&params.lib_paths).cloned();
+        Watcher::new(paths, "jack").run(|changed| build(&params, Some(changed), &mut cache));
+    }
+    build(&params, None, &mut cache)
 }
 
-fn parse_args() -> Result<Params> {
-    let args = env::args().collect::<Vec<_>>();
-    ensure!(args.len() == 2, "Usage: {} <file>", args[0]);
-    let input_path = PathBuf::from(&args[1]);
-    Ok(Params { input_path })
+/// Runs the command on the input files. The commands which do not resolve the classes only
+/// process the files which `changed`, if given.
+fn build(params: &Params, changed: Option<&[PathBuf]>, cache: &mut Option<Cache>) -> Result<()> {
+    let last_stage = params.command.last_stage(params.optimize);
+    let error_format = params.error_format;
+
+    if let Some(out_dir) = &params.out_dir {
+        fs::create_dir_all(out_dir)
+            .map_err(|e| Error::CreateOutputDir(out_dir.clone(), e.into()))?;
+    }
+    let mut outputs = Outputs::new(
+        params.out_dir.clone(),
+        params.output_formats,
+        params.emit.clone(),
+        params.dot,
+    );
+
+    let mut symbol_table = GlobalSymbolTable::with_builtin();
+    let mut classes = vec![];
+    for input_path in &params.input_paths {
+        for reader in DirOrFileReader::open(input_path, "jack")? {
+            let (path, reader) = reader
+                .map_err(|e| Error::OpenInputFile(input_path.clone(), e.into()))?
+                .into_parts();
+            if last_stage <= Stage::Ast && changed.is_some_and(|changed| !changed.contains(&path)) {
+                continue;
+            }
+            if params.command == Command::Tokenize {
+                tokenize(&path, reader, params, &mut outputs)?;
+                continue;
+            }
+            let parsed = parse(path, reader, params, &mut outputs)?;
+            if last_stage > Stage::Ast {
+                symbol_table
+                    .extend_with_class(&parsed.path, &parsed.ast.data)
+                    .map_err(|e| report(error_format, &parsed.path, &parsed.source, [&e]))?;
+            }
+            classes.push(parsed);
+        }
+    }
+    if last_stage <= Stage::Ast {
+        outputs.persist()?;
+        return Ok(());
+    }
+    load_libraries(
+        &params.lib_paths,
+        params.parse_options,
+        error_format,
+        &mut symbol_table,
+    )?;
+
+    let compiled = thread::map_parallel(&classes, params.jobs, |class| {
+        let mut class_outputs = outputs.fork();
+        let mut diagnostics = vec![];
+        let result = compile(
+            class,
+            &symbol_table,
+            params,
+            cache.as_ref(),
+            &mut class_outputs,
+            &mut diagnostics,
+        );
+        Compiled {
+            outputs: class_outputs,
+            diagnostics,
+            result,
+        }
+    });
+    // reported in the order of the classes, up to the first error as when compiled one by one
+    for (class, compiled) in classes.iter().zip(compiled) {
+        emit(
+            error_format,
+            &class.path,
+            &class.source,
+            &compiled.diagnostics,
+        );
+        let cache_entry = compiled.result?;
+        outputs.append(compiled.outputs);
+        if let (Some(cache), Some((key, vm))) = (cache.as_mut(), cache_entry) {
+            cache.insert(&class.path, key, &vm, compiled.diagnostics);
+        }
+    }
+
+    outputs.persist()?;
+    if let (Some(cache), Some(cache_path)) = (cache.as_mut(), &params.cache_path) {
+        cache.retain(
+            &classes
+                .iter()
+                .map(|class| class.path.as_path())
+                .collect::<Vec<_>>(),
+        );
+        cache.save(cache_path)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,344 @@
+use crate::{
+    sexp::Sexp,
+    token::TokenWriter,
+    xml::{WriteXml, XmlWriter},
+    Error, StdError,
+};
+use common::fs::FileWriter;
+use jack::{control_flow_graph::CfgClass, token::WithLoc};
+use serde::Serialize;
+use std::{
+    io::prelude::*,
+    path::{Path, PathBuf},
+};
+
+/// The format of the output files of a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    Xml,
+    Json,
+    Sexp,
+}
+
+impl OutputFormat {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "xml" => Some(Self::Xml),
+            "json" => Some(Self::Json),
+            "sexp" => Some(Self::Sexp),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Xml => "xml",
+            Self::Json => "json",
+            Self::Sexp => "sexp",
+        }
+    }
+}
+
+/// A compilation stage whose result can be written to a file, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Stage {
+    Token,
+    Ast,
+    TypedAst,
+    Cfg,
+    CfgOptimized,
+    Vm,
+}
+
+impl Stage {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "token" => Some(Self::Token),
+            "ast" => Some(Self::Ast),
+            "typed-ast" => Some(Self::TypedAst),
+            "cfg" => Some(Self::Cfg),
+            "cfg-optimized" => Some(Self::CfgOptimized),
+            "vm" => Some(Self::Vm),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Token => "token",
+            Self::Ast => "ast",
+            Self::TypedAst => "typed-ast",
+            Self::Cfg => "cfg",
+            Self::CfgOptimized => "cfg-optimized",
+            Self::Vm => "vm",
+        }
+    }
+}
+
+/// The output format of each stage. VM code is always written as is.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OutputFormats([OutputFormat; 6]);
+
+impl Default for OutputFormats {
+    fn default() -> Self {
+        Self([OutputFormat::Xml; 6])
+    }
+}
+
+impl OutputFormats {
+    pub(crate) fn get(&self, stage: Stage) -> OutputFormat {
+        self.0[stage as usize]
+    }
+
+    pub(crate) fn set(&mut self, stage: Stage, format: OutputFormat) {
+        self.0[stage as usize] = format;
+    }
+
+    pub(crate) fn set_all(&mut self, format: OutputFormat) {
+        self.0 = [format; 6];
+    }
+
+    /// Returns the path of the output file of the stage, e.g. `Main.ast.json`.
+    pub(crate) fn output_path(&self, input_path: &Path, stage: Stage) -> PathBuf {
+        if stage == Stage::Vm {
+            return input_path.with_extension("vm");
+        }
+        input_path.with_extension(format!(
+            "{}.{}",
+            stage.as_str(),
+            self.get(stage).extension()
+        ))
+    }
+}
+
+/// Writes the result of a stage in its output format.
+#[derive(Debug)]
+pub(crate) enum OutputWriter {
+    Xml(XmlWriter),
+    Serde(SerdeWriter),
+}
+
+impl OutputWriter {
+    pub(crate) fn open(
+        input_path: &Path,
+        stage: Stage,
+        formats: &OutputFormats,
+    ) -> Result<Self, Error> {
+        let path = formats.output_path(input_path, stage);
+        let writer = match formats.get(stage) {
+            OutputFormat::Xml => Self::Xml(XmlWriter::open(path)?),
+            format @ (OutputFormat::Json | OutputFormat::Sexp) => {
+                Self::Serde(SerdeWriter::open(path, format)?)
+            }
+        };
+        Ok(writer)
+    }
+
+    pub(crate) fn write(&mut self, value: &(impl WriteXml + Serialize)) -> Result<(), Error> {
+        match self {
+            Self::Xml(writer) => writer.write(value),
+            Self::Serde(writer) => writer.write(value),
+        }
+    }
+
+    pub(crate) fn persist(self) -> Result<(), Error> {
+        match self {
+            Self::Xml(writer) => writer.persist(),
+            Self::Serde(writer) => writer.persist(),
+        }
+    }
+}
+
+/// Writes values through their `Serialize` implementation, as JSON or S-expressions.
+#[derive(Debug)]
+pub(crate) struct SerdeWriter {
+    path: PathBuf,
+    format: OutputFormat,
+    writer: FileWriter,
+}
+
+impl SerdeWriter {
+    pub(crate) fn open(path: PathBuf, format: OutputFormat) -> Result<Self, Error> {
+        let writer = FileWriter::open(&path)
+            .map_err(|e| Error::CreateOutputFile(path.to_owned(), e.into()))?;
+        Ok(Self {
+            path,
+            format,
+            writer,
+        })
+    }
+
+    pub(crate) fn write(&mut self, value: &impl Serialize) -> Result<(), Error> {
+        self.write_value(value)
+            .map_err(|e| Error::WriteOutput(self.path.to_owned(), e))
+    }
+
+    fn write_value(&mut self, value: &impl Serialize) -> Result<(), StdError> {
+        let writer = self.writer.writer();
+        match self.format {
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut *writer, value)?;
+                writeln!(writer)?;
+            }
+            OutputFormat::Sexp => writeln!(writer, "{}", Sexp::from(serde_json::to_value(value)?))?,
+            OutputFormat::Xml => unreachable!("XML is written by XmlWriter"),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn persist(self) -> Result<(), Error> {
+        self.writer
+            .persist()
+            .map_err(|e| Error::PersistOutputFile(self.path, e.into()))?;
+        Ok(())
+    }
+}
+
+/// The output files of the emitted stages, persisted once every file is compiled.
+#[derive(Debug)]
+pub(crate) struct Outputs {
+    out_dir: Option<PathBuf>,
+    formats: OutputFormats,
+    emit: Vec<Stage>,
+    /// Whether the control-flow graphs are also written in Graphviz DOT format.
+    dot: bool,
+    token_writers: Vec<TokenWriter>,
+    writers: Vec<OutputWriter>,
+    files: Vec<(PathBuf, FileWriter)>,
+}
+
+impl Outputs {
+    pub(crate) fn new(
+        out_dir: Option<PathBuf>,
+        formats: OutputFormats,
+        emit: Vec<Stage>,
+        dot: bool,
+    ) -> Self {
+        Self {
+            out_dir,
+            formats,
+            emit,
+            dot,
+            token_writers: vec![],
+            writers: vec![],
+            files: vec![],
+        }
+    }
+
+    /// Returns empty outputs with the same settings, to write the files of a class on another
+    /// thread.
+    pub(crate) fn fork(&self) -> Self {
+        Self::new(
+            self.out_dir.clone(),
+            self.formats,
+            self.emit.clone(),
+            self.dot,
+        )
+    }
+
+    /// Takes over the files written by forked outputs.
+    pub(crate) fn append(&mut self, other: Self) {
+        self.token_writers.extend(other.token_writers);
+        self.writers.extend(other.writers);
+        self.files.extend(other.files);
+    }
+
+    pub(crate) fn emits(&self, stage: Stage) -> bool {
+        self.emit.contains(&stage)
+    }
+
+    /// Returns the path from which the output paths of the input file are made.
+    fn base_path(&self, input_path: &Path) -> PathBuf {
+        match (&self.out_dir, input_path.file_name()) {
+            (Some(dir), Some(file_name)) => dir.join(file_name),
+            _ => input_path.to_owned(),
+        }
+    }
+
+    /// Opens the token output of the input file, if the tokens are emitted.
+    pub(crate) fn token_writer(&self, input_path: &Path) -> Result<Option<TokenWriter>, Error> {
+        if !self.emits(Stage::Token) {
+            return Ok(None);
+        }
+        TokenWriter::open(&self.base_path(input_path), &self.formats).map(Some)
+    }
+
+    pub(crate) fn push_token_writer(&mut self, writer: Option<TokenWriter>) {
+        self.token_writers.extend(writer);
+    }
+
+    /// Writes the result of the stage, if it is emitted.
+    pub(crate) fn write(
+        &mut self,
+        input_path: &Path,
+        stage: Stage,
+        value: &(impl WriteXml + Serialize),
+    ) -> Result<(), Error> {
+        if !self.emits(stage) {
+            return Ok(());
+        }
+        let mut writer = OutputWriter::open(&self.base_path(input_path), stage, &self.formats)?;
+        writer.write(value)?;
+        self.writers.push(writer);
+        Ok(())
+    }
+
+    /// Writes the control-flow graphs, also in DOT format if requested.
+    pub(crate) fn write_cfg(
+        &mut self,
+        input_path: &Path,
+        stage: Stage,
+        cfg: &WithLoc<CfgClass>,
+    ) -> Result<(), Error> {
+        self.write(input_path, stage, cfg)?;
+        if !self.dot || !self.emits(stage) {
+            return Ok(());
+        }
+        let path = self
+            .base_path(input_path)
+            .with_extension(format!("{}.dot", stage.as_str()));
+        let mut writer =
+            FileWriter::open(&path).map_err(|e| Error::CreateOutputFile(path.clone(), e.into()))?;
+        cfg.data
+            .write_dot(writer.writer())
+            .map_err(|e| Error::WriteGraph(path.clone(), e.into()))?;
+        self.files.push((path, writer));
+        Ok(())
+    }
+
+    /// Returns the path of the VM file of the input file.
+    pub(crate) fn vm_path(&self, input_path: &Path) -> PathBuf {
+        self.formats
+            .output_path(&self.base_path(input_path), Stage::Vm)
+    }
+
+    /// Writes the VM code, if it is emitted.
+    pub(crate) fn write_vm(&mut self, input_path: &Path, vm: &str) -> Result<(), Error> {
+        if !self.emits(Stage::Vm) {
+            return Ok(());
+        }
+        let path = self.vm_path(input_path);
+        let mut writer =
+            FileWriter::open(&path).map_err(|e| Error::CreateOutputFile(path.clone(), e.into()))?;
+        writer
+            .writer()
+            .write_all(vm.as_bytes())
+            .map_err(|e| Error::WriteVmCommand(path.clone(), e.into()))?;
+        self.files.push((path, writer));
+        Ok(())
+    }
+
+    pub(crate) fn persist(self) -> Result<(), Error> {
+        for token_writer in self.token_writers {
+            token_writer.persist()?;
+        }
+        for writer in self.writers {
+            writer.persist()?;
+        }
+        for (path, file) in self.files {
+            file.persist()
+                .map_err(|e| Error::PersistOutputFile(path, e.into()))?;
+        }
+        Ok(())
+    }
+}
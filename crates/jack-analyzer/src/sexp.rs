@@ -0,0 +1,115 @@
+use serde_json::{Map, Value};
+use std::fmt;
+
+const MAX_WIDTH: usize = 80;
+
+/// An S-expression, converted from the JSON value of a serialized item.
+///
+/// Struct fields become `:name value` pairs and enum variants become lists headed by the variant
+/// name, e.g. `(Var :name (:data "x" :loc (...)) :segment "local" :slot 0)`.
+#[derive(Debug, Clone)]
+pub(crate) enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+impl From<Value> for Sexp {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Atom("nil".to_owned()),
+            Value::Bool(b) => Self::Atom(b.to_string()),
+            Value::Number(n) => Self::Atom(n.to_string()),
+            Value::String(s) => Self::Atom(format!("{:?}", s)),
+            Value::Array(values) => Self::List(values.into_iter().map(Self::from).collect()),
+            Value::Object(map) if is_variant(&map) => {
+                let (name, value) = map.into_iter().next().unwrap();
+                let mut items = vec![Self::Atom(name)];
+                match value {
+                    Value::Array(values) => items.extend(values.into_iter().map(Self::from)),
+                    Value::Object(fields) if !is_variant(&fields) => {
+                        items.extend(Self::fields(fields))
+                    }
+                    value => items.push(Self::from(value)),
+                }
+                Self::List(items)
+            }
+            Value::Object(fields) => Self::List(Self::fields(fields)),
+        }
+    }
+}
+
+/// Returns whether the object is an externally tagged enum variant, e.g. `{"Let": {...}}`.
+fn is_variant(map: &Map<String, Value>) -> bool {
+    map.len() == 1 && map.keys().all(|key| key.starts_with(char::is_uppercase))
+}
+
+impl Sexp {
+    fn fields(fields: Map<String, Value>) -> Vec<Self> {
+        fields
+            .into_iter()
+            .flat_map(|(key, value)| [Self::Atom(format!(":{}", key)), Self::from(value)])
+            .collect()
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            Self::Atom(atom) => atom.len(),
+            Self::List(items) => items.iter().map(|item| item.width() + 1).sum::<usize>() + 1,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let items = match self {
+            Self::Atom(atom) => return f.write_str(atom),
+            Self::List(items) => items,
+        };
+        let flat = indent + self.width() <= MAX_WIDTH;
+        f.write_str("(")?;
+        let mut items = items.iter().peekable();
+        while let Some(item) = items.next() {
+            item.write(f, indent + 1)?;
+            if items.peek().is_none() {
+                break;
+            }
+            // keep `:name value` pairs on one line
+            if flat || matches!(item, Self::Atom(atom) if atom.starts_with(':')) {
+                f.write_str(" ")?;
+            } else {
+                write!(f, "\n{:indent$}", "", indent = indent + 1)?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn from_json() {
+        let value = json!({
+            "stmts": [
+                {"Let": {"target": "x", "expr": {"Int": 1}}},
+                {"Goto": "LOOP0"},
+                {"If": [true, null]},
+            ],
+            "exit": "Unreachable",
+        });
+        assert_eq!(
+            Sexp::from(value).to_string(),
+            concat!(
+                r#"(:stmts ((Let :target "x" :expr (Int 1)) (Goto "LOOP0") (If true nil))"#,
+                "\n",
+                r#" :exit "Unreachable")"#,
+            )
+        );
+    }
+}
@@ -1,41 +1,68 @@
 use crate::{
+    output::{OutputFormat, OutputFormats, SerdeWriter, Stage},
     xml::{WriteXml, XmlWriter},
     Error,
 };
-use jack::token::{Ident, Keyword, Symbol, Token};
-use std::{io, path::PathBuf};
+use jack::token::{Ident, Keyword, Symbol, Token, WithLoc};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug)]
-pub(crate) struct TokenWriter {
-    path: PathBuf,
-    writer: XmlWriter,
+pub(crate) enum TokenWriter {
+    /// Writes the tokens as they are read.
+    Xml { path: PathBuf, writer: XmlWriter },
+    /// Writes the tokens with their locations once all of them are read.
+    Serde {
+        writer: SerdeWriter,
+        tokens: Vec<WithLoc<Token>>,
+    },
 }
 
 impl TokenWriter {
-    pub(crate) fn open(path: PathBuf) -> Result<Self, Error> {
-        let mut writer = XmlWriter::open(path.clone())
-            .map_err(|e| Error::CreateOutputFile(path.to_owned(), e.into()))?;
+    pub(crate) fn open(input_path: &Path, formats: &OutputFormats) -> Result<Self, Error> {
+        let path = formats.output_path(input_path, Stage::Token);
+        let format = formats.get(Stage::Token);
+        if format != OutputFormat::Xml {
+            let writer = SerdeWriter::open(path, format)?;
+            return Ok(Self::Serde {
+                writer,
+                tokens: vec![],
+            });
+        }
 
+        let mut writer = XmlWriter::open(path.clone())?;
         writer
             .write_open(0, "tokens")
             .map_err(|e| Error::WriteXml(path.clone(), e.into()))?;
-
-        Ok(Self { path, writer })
+        Ok(Self::Xml { path, writer })
     }
 
-    pub(crate) fn write(&mut self, token: &Token) -> Result<(), Error> {
-        token
-            .write_xml(0, &mut self.writer)
-            .map_err(|e| Error::WriteXml(self.path.clone(), e.into()))?;
+    pub(crate) fn write(&mut self, token: &WithLoc<Token>) -> Result<(), Error> {
+        match self {
+            Self::Xml { path, writer } => token
+                .data
+                .write_xml(0, writer)
+                .map_err(|e| Error::WriteXml(path.clone(), e.into()))?,
+            Self::Serde { tokens, .. } => tokens.push(token.clone()),
+        }
         Ok(())
     }
 
-    pub(crate) fn persist(mut self) -> Result<(), Error> {
-        self.writer
-            .write_close(0, "tokens")
-            .map_err(|e| Error::WriteXml(self.path.clone(), e.into()))?;
-        self.writer.persist()?;
-        Ok(())
+    pub(crate) fn persist(self) -> Result<(), Error> {
+        match self {
+            Self::Xml { path, mut writer } => {
+                writer
+                    .write_close(0, "tokens")
+                    .map_err(|e| Error::WriteXml(path, e.into()))?;
+                writer.persist()
+            }
+            Self::Serde { mut writer, tokens } => {
+                writer.write(&tokens)?;
+                writer.persist()
+            }
+        }
     }
 }
 
@@ -46,6 +73,7 @@ impl WriteXml for Token {
             Token::Symbol(sym) => sym.write_xml(indent, writer),
             Token::Int(n) => n.write_xml(indent, writer),
             Token::String(s) => s.write_xml(indent, writer),
+            Token::Char(ch) => ch.write_xml(indent, writer),
             Token::Ident(ident) => ident.write_xml(indent, writer),
         }
     }
@@ -63,6 +91,12 @@ impl WriteXml for Symbol {
     }
 }
 
+impl WriteXml for char {
+    fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
+        writer.write_single(indent, "charConstant", &self.to_string())
+    }
+}
+
 impl WriteXml for u16 {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
         writer.write_single(indent, "integerConstant", &self.to_string())
@@ -141,6 +141,9 @@ impl WriteXml for TypedStatement {
             Self::While(stmt) => stmt.write_xml(indent, writer),
             Self::Do(stmt) => stmt.write_xml(indent, writer),
             Self::Return(stmt) => stmt.write_xml(indent, writer),
+            Self::For(stmt) => stmt.write_xml(indent, writer),
+            Self::Break => writer.write_multi(indent, "breakStatement", |_, _| Ok(())),
+            Self::Continue => writer.write_multi(indent, "continueStatement", |_, _| Ok(())),
         }
     }
 }
@@ -193,9 +196,27 @@ impl WriteXml for TypedWhileStatement {
     }
 }
 
+impl WriteXml for TypedForStatement {
+    fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
+        let Self {
+            init,
+            cond,
+            step,
+            stmts,
+        } = self;
+        writer.write_multi(indent, "forStatement", |indent, writer| {
+            writer.write_opt(indent, "init", init)?;
+            writer.write_opt(indent, "condition", cond)?;
+            writer.write_opt(indent, "step", step)?;
+            writer.write_list(indent, "statementList", stmts)?;
+            Ok(())
+        })
+    }
+}
+
 impl WriteXml for TypedDoStatement {
     fn write_xml(&self, indent: usize, writer: &mut XmlWriter) -> io::Result<()> {
-        let Self { sub_call } = self;
+        let Self { sub_call, .. } = self;
         writer.write_labeled(indent, "doStatement", sub_call)
     }
 }
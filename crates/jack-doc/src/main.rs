@@ -0,0 +1,149 @@
+use color_eyre::eyre::{bail, eyre, Result};
+use common::fs::{DirOrFileReader, FileWriter};
+use jack::{
+    ast::ParseOptions,
+    doc::{render, ClassDoc, DocFormat},
+};
+use std::{
+    env,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+    process,
+};
+use thiserror::Error;
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to open input file: {}", _0.display())]
+    OpenInputFile(PathBuf, #[source] StdError),
+    #[error("failed to read input file: {}", _0.display())]
+    ReadInputFile(PathBuf, #[source] StdError),
+    #[error("failed to create output file: {}", _0.display())]
+    CreateOutputFile(PathBuf, #[source] StdError),
+    #[error("failed to write output file: {}", _0.display())]
+    WriteOutputFile(PathBuf, #[source] StdError),
+    #[error("failed to persist output file: {}", _0.display())]
+    PersistOutputFile(PathBuf, #[source] StdError),
+}
+
+#[derive(Debug)]
+struct Params {
+    input_paths: Vec<PathBuf>,
+    output_path: Option<PathBuf>,
+    format: DocFormat,
+    parse_options: ParseOptions,
+}
+
+/// Extracts the documentation of the file, or returns `None` if it does not parse.
+fn document_file(
+    path: &Path,
+    mut reader: impl Read,
+    options: ParseOptions,
+) -> Result<Option<ClassDoc>, Error> {
+    let mut source = String::new();
+    reader
+        .read_to_string(&mut source)
+        .map_err(|e| Error::ReadInputFile(path.to_owned(), e.into()))?;
+
+    match ClassDoc::from_source(&source, options) {
+        Ok(doc) => Ok(Some(doc)),
+        Err(e) => {
+            for diagnostic in e.diagnostics() {
+                eprintln!("{}", diagnostic.render(path, &source));
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn write_output(path: &Path, output: &str) -> Result<(), Error> {
+    let mut writer =
+        FileWriter::open(path).map_err(|e| Error::CreateOutputFile(path.to_owned(), e.into()))?;
+    writer
+        .writer()
+        .write_all(output.as_bytes())
+        .map_err(|e| Error::WriteOutputFile(path.to_owned(), e.into()))?;
+    writer
+        .persist()
+        .map_err(|e| Error::PersistOutputFile(path.to_owned(), e.into()))?;
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let Params {
+        input_paths,
+        output_path,
+        format,
+        parse_options,
+    } = parse_args()?;
+
+    let mut ok = true;
+    let mut classes = vec![];
+    for input_path in input_paths {
+        for reader in DirOrFileReader::open(&input_path, "jack")? {
+            let (path, reader) = reader
+                .map_err(|e| Error::OpenInputFile(input_path.clone(), e.into()))?
+                .into_parts();
+            match document_file(&path, reader, parse_options)? {
+                Some(class) => classes.push(class),
+                None => ok = false,
+            }
+        }
+    }
+
+    let output = render(&classes, format);
+    match output_path {
+        Some(path) => write_output(&path, &output)?,
+        None => io::stdout().write_all(output.as_bytes())?,
+    }
+    if !ok {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn parse_args() -> Result<Params> {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "jack-doc".to_string());
+    let usage = || {
+        format!(
+            "Usage: {} [--format <markdown|html>] [--extensions] [-o <output>] <file>...",
+            program
+        )
+    };
+
+    let mut input_paths = vec![];
+    let mut output_path = None;
+    let mut format = DocFormat::Markdown;
+    let mut parse_options = ParseOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("markdown") => DocFormat::Markdown,
+                    Some("html") => DocFormat::Html,
+                    _ => bail!(usage()),
+                }
+            }
+            "--extensions" => parse_options.extensions = true,
+            "-o" => match args.next() {
+                Some(path) => output_path = Some(PathBuf::from(path)),
+                None => bail!(usage()),
+            },
+            _ if arg.starts_with('-') => bail!(usage()),
+            _ => input_paths.push(PathBuf::from(arg)),
+        }
+    }
+    if input_paths.is_empty() {
+        return Err(eyre!(usage()));
+    }
+    Ok(Params {
+        input_paths,
+        output_path,
+        format,
+        parse_options,
+    })
+}
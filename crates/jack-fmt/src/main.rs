@@ -0,0 +1,140 @@
+use color_eyre::eyre::{bail, eyre, Result};
+use common::fs::{DirOrFileReader, FileWriter};
+use jack::{ast::ParseOptions, formatter::format_source_with};
+use std::{
+    env,
+    io::prelude::*,
+    path::{Path, PathBuf},
+    process,
+};
+use thiserror::Error;
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to open input file: {}", _0.display())]
+    OpenInputFile(PathBuf, #[source] StdError),
+    #[error("failed to read input file: {}", _0.display())]
+    ReadInputFile(PathBuf, #[source] StdError),
+    #[error("failed to create output file: {}", _0.display())]
+    CreateOutputFile(PathBuf, #[source] StdError),
+    #[error("failed to write output file: {}", _0.display())]
+    WriteOutputFile(PathBuf, #[source] StdError),
+    #[error("failed to persist output file: {}", _0.display())]
+    PersistOutputFile(PathBuf, #[source] StdError),
+}
+
+#[derive(Debug)]
+struct Params {
+    input_paths: Vec<PathBuf>,
+    check: bool,
+    parse_options: ParseOptions,
+}
+
+/// Formats the file in place, or returns `false` if it is not formatted in check mode.
+fn format_file(
+    path: &Path,
+    mut reader: impl Read,
+    check: bool,
+    options: ParseOptions,
+) -> Result<bool, Error> {
+    let mut source = String::new();
+    reader
+        .read_to_string(&mut source)
+        .map_err(|e| Error::ReadInputFile(path.to_owned(), e.into()))?;
+
+    let formatted = match format_source_with(&source, options) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            for diagnostic in e.diagnostics() {
+                eprintln!("{}", diagnostic.render(path, &source));
+            }
+            return Ok(false);
+        }
+    };
+    if formatted == source {
+        return Ok(true);
+    }
+    if check {
+        println!("{}", path.display());
+        return Ok(false);
+    }
+
+    let mut writer =
+        FileWriter::open(path).map_err(|e| Error::CreateOutputFile(path.to_owned(), e.into()))?;
+    writer
+        .writer()
+        .write_all(formatted.as_bytes())
+        .map_err(|e| Error::WriteOutputFile(path.to_owned(), e.into()))?;
+    writer
+        .persist()
+        .map_err(|e| Error::PersistOutputFile(path.to_owned(), e.into()))?;
+    Ok(true)
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let Params {
+        input_paths,
+        check,
+        parse_options,
+    } = parse_args()?;
+
+    let mut ok = true;
+    for input_path in input_paths {
+        for reader in DirOrFileReader::open(&input_path, "jack")? {
+            let (path, reader) = reader
+                .map_err(|e| Error::OpenInputFile(input_path.clone(), e.into()))?
+                .into_parts();
+            ok &= format_file(&path, reader, check, parse_options)?;
+        }
+    }
+    if !ok {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn parse_args() -> Result<Params> {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "jack-fmt".to_string());
+    let usage = || format!("Usage: {} [--check] [--extensions] <file>...", program);
+
+    let mut input_paths = vec![];
+    let mut check = false;
+    let mut parse_options = ParseOptions::default();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--extensions" => parse_options.extensions = true,
+            _ if arg.starts_with('-') => bail!(usage()),
+            _ => input_paths.push(PathBuf::from(arg)),
+        }
+    }
+    if input_paths.is_empty() {
+        return Err(eyre!(usage()));
+    }
+    Ok(Params {
+        input_paths,
+        check,
+        parse_options,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_extensions() {
+        let source = "class Main {\n    function void main() {\n        for (;;) {\n            break;\n        }\n        return;\n    }\n}\n";
+        let path = Path::new("Main.jack");
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        assert!(format_file(path, source.as_bytes(), true, options).unwrap());
+        assert!(!format_file(path, source.as_bytes(), true, ParseOptions::default()).unwrap());
+    }
+}
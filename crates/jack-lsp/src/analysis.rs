@@ -0,0 +1,491 @@
+use crate::{
+    document::Document,
+    protocol::{completion_kind, symbol_kind, CompletionItem, DocumentSymbol},
+};
+use jack::{
+    ast::{
+        Class, ClassVarKind, Expression, LetStatement, Statement, StatementList, Subroutine,
+        SubroutineCall, SubroutineKind, Term, Type,
+    },
+    symbol_table::GlobalSymbolTable,
+    token::{Ident, Location, WithLoc},
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Static,
+    Field,
+    Parameter,
+    Local,
+}
+
+impl VarKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Static => "static",
+            Self::Field => "field",
+            Self::Parameter => "parameter",
+            Self::Local => "var",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VarDef {
+    pub kind: VarKind,
+    pub name: WithLoc<Ident>,
+    pub ty: Type,
+}
+
+/// The symbol an identifier refers to.
+#[derive(Debug, Clone)]
+pub enum Target {
+    Class(Ident),
+    /// A subroutine of the class.
+    Subroutine(Ident, Ident),
+    Variable(VarDef),
+}
+
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub loc: Location,
+    pub target: Target,
+}
+
+/// Variables visible from a position of a class.
+#[derive(Debug, Clone)]
+struct Scope {
+    vars: Vec<VarDef>,
+}
+
+impl Scope {
+    fn class(class: &Class) -> Self {
+        let vars = class
+            .vars
+            .iter()
+            .flat_map(|var| {
+                let kind = match var.data.kind.data {
+                    ClassVarKind::Static => VarKind::Static,
+                    ClassVarKind::Field => VarKind::Field,
+                };
+                var.data.names.iter().map(move |name| VarDef {
+                    kind,
+                    name: name.clone(),
+                    ty: var.data.ty.data.clone(),
+                })
+            })
+            .collect();
+        Self { vars }
+    }
+
+    fn subroutine(class: &Class, sub: &Subroutine) -> Self {
+        let mut scope = Self::class(class);
+        scope
+            .vars
+            .extend(sub.params.data.0.iter().map(|param| VarDef {
+                kind: VarKind::Parameter,
+                name: param.data.name.clone(),
+                ty: param.data.ty.data.clone(),
+            }));
+        for var in &sub.body.data.vars {
+            scope.vars.extend(var.data.names.iter().map(|name| VarDef {
+                kind: VarKind::Local,
+                name: name.clone(),
+                ty: var.data.ty.data.clone(),
+            }));
+        }
+        scope
+    }
+
+    /// Returns the scope at `offset` of the source of the class.
+    fn at(class: &Class, offset: usize) -> Self {
+        class
+            .subs
+            .iter()
+            .find(|sub| contains(sub.loc, offset))
+            .map(|sub| Self::subroutine(class, &sub.data))
+            .unwrap_or_else(|| Self::class(class))
+    }
+
+    fn lookup(&self, name: &str) -> Option<&VarDef> {
+        // local variables and parameters shadow class variables
+        self.vars
+            .iter()
+            .rev()
+            .find(|var| var.name.data.as_str() == name)
+    }
+}
+
+fn contains(loc: Location, offset: usize) -> bool {
+    loc.start as usize <= offset && offset <= loc.end as usize
+}
+
+/// Collects the identifiers of the class and the symbols they refer to.
+pub fn occurrences(class: &Class) -> Vec<Occurrence> {
+    let mut collector = Collector {
+        class_name: &class.name.data,
+        scope: Scope::class(class),
+        occurrences: vec![],
+    };
+    collector.push(class.name.loc, Target::Class(class.name.data.clone()));
+    for var in &class.vars {
+        collector.ty(&var.data.ty);
+    }
+    collector.declare_vars();
+    for sub in &class.subs {
+        collector.scope = Scope::subroutine(class, &sub.data);
+        collector.subroutine(&sub.data);
+    }
+    collector.occurrences
+}
+
+/// Returns the occurrence of an identifier at `offset`.
+pub fn find(occurrences: &[Occurrence], offset: usize) -> Option<&Occurrence> {
+    occurrences.iter().find(|occ| contains(occ.loc, offset))
+}
+
+struct Collector<'a> {
+    class_name: &'a Ident,
+    scope: Scope,
+    occurrences: Vec<Occurrence>,
+}
+
+impl Collector<'_> {
+    fn push(&mut self, loc: Location, target: Target) {
+        self.occurrences.push(Occurrence { loc, target });
+    }
+
+    /// Registers the declarations of the variables in the scope which are not registered yet.
+    fn declare_vars(&mut self) {
+        for var in self.scope.vars.clone() {
+            let declared = self
+                .occurrences
+                .iter()
+                .any(|occ| occ.loc.start == var.name.loc.start);
+            if !declared {
+                self.push(var.name.loc, Target::Variable(var));
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &WithLoc<Type>) {
+        if let Type::Class(name) = &ty.data {
+            self.push(ty.loc, Target::Class(name.clone()));
+        }
+    }
+
+    fn variable(&mut self, name: &WithLoc<Ident>) {
+        if let Some(var) = self.scope.lookup(name.data.as_str()) {
+            let target = Target::Variable(var.clone());
+            self.push(name.loc, target);
+        }
+    }
+
+    fn subroutine(&mut self, sub: &Subroutine) {
+        if let jack::ast::ReturnType::Type(ty) = &sub.return_type.data {
+            self.ty(ty);
+        }
+        self.push(
+            sub.name.loc,
+            Target::Subroutine(self.class_name.clone(), sub.name.data.clone()),
+        );
+        for param in &sub.params.data.0 {
+            self.ty(&param.data.ty);
+        }
+        for var in &sub.body.data.vars {
+            self.ty(&var.data.ty);
+        }
+        self.declare_vars();
+        self.statements(&sub.body.data.stmts.data);
+    }
+
+    fn statements(&mut self, stmts: &StatementList) {
+        for stmt in &stmts.0 {
+            match &stmt.data {
+                Statement::Let(stmt) => self.let_statement(&stmt.data),
+                Statement::If(stmt) => {
+                    self.expression(&stmt.data.cond.data);
+                    self.statements(&stmt.data.then_stmts.data);
+                    if let Some(stmts) = &stmt.data.else_stmts {
+                        self.statements(&stmts.data);
+                    }
+                }
+                Statement::While(stmt) => {
+                    self.expression(&stmt.data.cond.data);
+                    self.statements(&stmt.data.stmts.data);
+                }
+                Statement::For(stmt) => {
+                    if let Some(init) = &stmt.data.init {
+                        self.let_statement(&init.data);
+                    }
+                    if let Some(cond) = &stmt.data.cond {
+                        self.expression(&cond.data);
+                    }
+                    if let Some(step) = &stmt.data.step {
+                        self.let_statement(&step.data);
+                    }
+                    self.statements(&stmt.data.stmts.data);
+                }
+                Statement::Do(stmt) => self.call(&stmt.data.sub_call.data),
+                Statement::Return(stmt) => {
+                    if let Some(expr) = &stmt.data.expr {
+                        self.expression(&expr.data);
+                    }
+                }
+                Statement::Break | Statement::Continue => {}
+            }
+        }
+    }
+
+    fn let_statement(&mut self, stmt: &LetStatement) {
+        self.variable(&stmt.target);
+        if let Some(index) = &stmt.target_index {
+            self.expression(&index.data);
+        }
+        self.expression(&stmt.expr.data);
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        self.term(&expr.term.data);
+        for (_, term) in &expr.binary_ops {
+            self.term(&term.data);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::IntConstant(_)
+            | Term::StringConstant(_)
+            | Term::CharConstant(_)
+            | Term::KeywordConstant(_) => {}
+            Term::Variable(name) => self.variable(name),
+            Term::ClassConstant(class, _) => {
+                self.push(class.loc, Target::Class(class.data.clone()))
+            }
+            Term::Index(name, index) => {
+                self.variable(name);
+                self.expression(&index.data);
+            }
+            Term::SubroutineCall(call) => self.call(&call.data),
+            Term::Expression(expr) => self.expression(&expr.data),
+            Term::UnaryOp(_, term) => self.term(&term.data),
+        }
+    }
+
+    fn call(&mut self, call: &SubroutineCall) {
+        let args = match call {
+            SubroutineCall::SubroutineCall(name, args) => {
+                self.push(
+                    name.loc,
+                    Target::Subroutine(self.class_name.clone(), name.data.clone()),
+                );
+                args
+            }
+            SubroutineCall::PropertyCall(receiver, name, args) => {
+                let class = match self.scope.lookup(receiver.data.as_str()) {
+                    Some(var) => {
+                        let class = var.ty.to_class().cloned();
+                        self.variable(receiver);
+                        class
+                    }
+                    None => {
+                        self.push(receiver.loc, Target::Class(receiver.data.clone()));
+                        Some(receiver.data.clone())
+                    }
+                };
+                if let Some(class) = class {
+                    self.push(name.loc, Target::Subroutine(class, name.data.clone()));
+                }
+                args
+            }
+        };
+        for arg in &args.data.0 {
+            self.expression(&arg.data);
+        }
+    }
+}
+
+/// Returns the file and the location where the target is defined.
+pub fn definition(
+    target: &Target,
+    table: &GlobalSymbolTable,
+    path: &Path,
+) -> Option<(PathBuf, Location)> {
+    let (path, loc) = match target {
+        Target::Class(name) => {
+            let class = table.class(name.as_str())?;
+            (class.path().to_owned(), class.name().loc)
+        }
+        Target::Subroutine(class, name) => {
+            let class = table.class(class.as_str())?;
+            let sub = class.subroutine(name.as_str())?;
+            (class.path().to_owned(), sub.name.loc)
+        }
+        Target::Variable(var) => (path.to_owned(), var.name.loc),
+    };
+    (!loc.is_builtin()).then_some((path, loc))
+}
+
+/// Returns the description of the target in Markdown.
+pub fn hover(target: &Target, table: &GlobalSymbolTable) -> Option<String> {
+    let decl = match target {
+        Target::Class(name) => {
+            table.class(name.as_str())?;
+            format!("class {}", name.as_str())
+        }
+        Target::Subroutine(class, name) => table
+            .class(class.as_str())?
+            .subroutine(name.as_str())?
+            .to_string(),
+        Target::Variable(var) => format!(
+            "{} {} {}",
+            var.kind.as_str(),
+            var.ty.as_str(),
+            var.name.data.as_str()
+        ),
+    };
+    Some(format!("```jack\n{}\n```", decl))
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Returns the completion candidates at `offset` of the document.
+///
+/// After `receiver.`, the candidates are the methods of the type of the receiver variable, or the
+/// constructors and functions of the receiver class. Otherwise, the candidates are the variables
+/// in the scope, the subroutines of the class and the classes.
+pub fn completions(
+    doc: &Document,
+    table: &GlobalSymbolTable,
+    offset: usize,
+) -> Vec<CompletionItem> {
+    let class = doc.ast.as_ref().map(|ast| &ast.data);
+    let scope = class
+        .map(|class| Scope::at(class, offset))
+        .unwrap_or(Scope { vars: vec![] });
+
+    let before = &doc.text[..offset];
+    let before = before.trim_end_matches(is_ident_char).trim_end();
+    if let Some(before) = before.strip_suffix('.') {
+        let before = before.trim_end();
+        let receiver = &before[before.trim_end_matches(is_ident_char).len()..];
+        let (class_name, methods) = match scope.lookup(receiver) {
+            Some(var) => match var.ty.to_class() {
+                Some(class) => (class.as_str(), true),
+                None => return vec![],
+            },
+            None => (receiver, false),
+        };
+        let class = match table.class(class_name) {
+            Some(class) => class,
+            None => return vec![],
+        };
+        return class
+            .subroutines()
+            .into_iter()
+            .filter(|sub| matches!(sub.kind, SubroutineKind::Method) == methods)
+            .map(|sub| CompletionItem {
+                label: sub.name.data.as_str().to_owned(),
+                kind: match sub.kind {
+                    SubroutineKind::Constructor => completion_kind::CONSTRUCTOR,
+                    SubroutineKind::Function => completion_kind::FUNCTION,
+                    SubroutineKind::Method => completion_kind::METHOD,
+                },
+                detail: Some(sub.to_string()),
+            })
+            .collect();
+    }
+
+    let mut items = scope
+        .vars
+        .iter()
+        .rev()
+        .map(|var| CompletionItem {
+            label: var.name.data.as_str().to_owned(),
+            kind: match var.kind {
+                VarKind::Static | VarKind::Field => completion_kind::FIELD,
+                VarKind::Parameter | VarKind::Local => completion_kind::VARIABLE,
+            },
+            detail: Some(format!("{} {}", var.kind.as_str(), var.ty.as_str())),
+        })
+        .collect::<Vec<_>>();
+    if let Some(class) = class {
+        items.extend(class.subs.iter().map(|sub| CompletionItem {
+            label: sub.data.name.data.as_str().to_owned(),
+            kind: match sub.data.kind.data {
+                SubroutineKind::Constructor => completion_kind::CONSTRUCTOR,
+                SubroutineKind::Function => completion_kind::FUNCTION,
+                SubroutineKind::Method => completion_kind::METHOD,
+            },
+            detail: Some(sub.data.kind.data.as_str().to_owned()),
+        }));
+    }
+    items.extend(table.classes().into_iter().map(|class| CompletionItem {
+        label: class.name().data.as_str().to_owned(),
+        kind: completion_kind::CLASS,
+        detail: None,
+    }));
+    items
+}
+
+/// Returns the outline of the class.
+pub fn document_symbols(doc: &Document) -> Vec<DocumentSymbol> {
+    let class = match &doc.ast {
+        Some(class) => class,
+        None => return vec![],
+    };
+    let mut children = vec![];
+    for var in &class.data.vars {
+        let kind = var.data.kind.data;
+        for name in &var.data.names {
+            children.push(DocumentSymbol {
+                name: name.data.as_str().to_owned(),
+                detail: Some(format!(
+                    "{} {}",
+                    match kind {
+                        ClassVarKind::Static => "static",
+                        ClassVarKind::Field => "field",
+                    },
+                    var.data.ty.data.as_str()
+                )),
+                kind: match kind {
+                    ClassVarKind::Static => symbol_kind::VARIABLE,
+                    ClassVarKind::Field => symbol_kind::FIELD,
+                },
+                range: doc.range(var.loc),
+                selection_range: doc.range(name.loc),
+                children: vec![],
+            });
+        }
+    }
+    for sub in &class.data.subs {
+        let sub_kind = sub.data.kind.data;
+        children.push(DocumentSymbol {
+            name: sub.data.name.data.as_str().to_owned(),
+            detail: Some(format!(
+                "{} {}",
+                sub_kind.as_str(),
+                sub.data.return_type.data.as_str()
+            )),
+            kind: match sub_kind {
+                SubroutineKind::Constructor => symbol_kind::CONSTRUCTOR,
+                SubroutineKind::Function => symbol_kind::FUNCTION,
+                SubroutineKind::Method => symbol_kind::METHOD,
+            },
+            range: doc.range(sub.loc),
+            selection_range: doc.range(sub.data.name.loc),
+            children: vec![],
+        });
+    }
+    vec![DocumentSymbol {
+        name: class.data.name.data.as_str().to_owned(),
+        detail: None,
+        kind: symbol_kind::CLASS,
+        range: doc.range(class.loc),
+        selection_range: doc.range(class.data.name.loc),
+        children,
+    }]
+}
@@ -0,0 +1,151 @@
+use crate::protocol::{Position, Range};
+use jack::{
+    ast::{Class, ParseOptions},
+    diagnostic::{Diagnostic, ToDiagnostic},
+    lint::LintLevels,
+    token::{Location, Tokens, WithLoc},
+};
+use std::path::PathBuf;
+
+/// A Jack source file, either opened in the editor or read from the disk.
+#[derive(Debug)]
+pub struct Document {
+    pub uri: String,
+    pub path: PathBuf,
+    pub text: String,
+    pub open: bool,
+    pub ast: Option<WithLoc<Class>>,
+    pub parse_errors: Vec<Diagnostic>,
+    /// The lint levels set by the directives in the comments.
+    pub lint_levels: LintLevels,
+    pub options: ParseOptions,
+}
+
+impl Document {
+    pub fn new(
+        uri: String,
+        path: PathBuf,
+        text: String,
+        open: bool,
+        options: ParseOptions,
+    ) -> Self {
+        let mut doc = Self {
+            uri,
+            path,
+            text: String::new(),
+            open,
+            ast: None,
+            parse_errors: vec![],
+            lint_levels: LintLevels::default(),
+            options,
+        };
+        doc.set_text(text);
+        doc
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        let mut tokens =
+            Tokens::from_reader(text.as_bytes()).with_extensions(self.options.extensions);
+        let result = Class::from_tokens_with(&mut tokens, self.options);
+        self.ast = result.ast;
+        self.parse_errors = result.errors.iter().map(|e| e.to_diagnostic()).collect();
+        self.lint_levels = LintLevels::default()
+            .with_directives(tokens.comments())
+            .unwrap_or_else(|e| {
+                self.parse_errors.push(e.to_diagnostic());
+                LintLevels::default()
+            });
+        self.text = text;
+    }
+
+    /// Replaces `range` of the text with `text`.
+    pub fn edit(&mut self, range: Range, text: &str) {
+        let start = self.offset(range.start);
+        let end = usize::max(start, self.offset(range.end));
+        let mut new_text = self.text.clone();
+        new_text.replace_range(start..end, text);
+        self.set_text(new_text);
+    }
+
+    /// Converts a position, whose character counts UTF-16 code units, to a byte offset.
+    pub fn offset(&self, pos: Position) -> usize {
+        let mut line_start = 0;
+        for _ in 0..pos.line {
+            match self.text[line_start..].find('\n') {
+                Some(i) => line_start += i + 1,
+                None => return self.text.len(),
+            }
+        }
+        let mut units = 0;
+        for (i, ch) in self.text[line_start..].char_indices() {
+            if units >= pos.character as usize || ch == '\n' {
+                return line_start + i;
+            }
+            units += ch.len_utf16();
+        }
+        self.text.len()
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = usize::min(offset, self.text.len());
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position {
+            line: before.matches('\n').count() as u32,
+            character: before[line_start..].encode_utf16().count() as u32,
+        }
+    }
+
+    pub fn range(&self, loc: Location) -> Range {
+        Range {
+            start: self.position(loc.start as usize),
+            end: self.position(loc.end as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let mut doc = Document::new(
+            "file:///Main.jack".into(),
+            "/Main.jack".into(),
+            "class Main {\n  // \u{3042}\u{1f600}x\n}\n".into(),
+            true,
+            ParseOptions::default(),
+        );
+        let x = doc.text.find('x').unwrap();
+        let pos = Position {
+            line: 1,
+            character: 8,
+        };
+        assert_eq!(doc.position(x), pos);
+        assert_eq!(doc.offset(pos), x);
+        assert_eq!(
+            doc.offset(Position {
+                line: 1,
+                character: 100
+            }),
+            doc.text.find("\n}").unwrap()
+        );
+        assert!(doc.ast.is_some());
+
+        doc.edit(
+            Range {
+                start: Position {
+                    line: 2,
+                    character: 0,
+                },
+                end: Position {
+                    line: 2,
+                    character: 0,
+                },
+            },
+            "  field int y;\n",
+        );
+        assert_eq!(doc.ast.unwrap().data.vars.len(), 1);
+    }
+}
@@ -0,0 +1,32 @@
+use crate::server::Server;
+use color_eyre::eyre::{bail, Result};
+use std::{env, io, process};
+
+mod analysis;
+mod document;
+mod protocol;
+mod rpc;
+mod server;
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    parse_args()?;
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let clean = Server::new(stdout.lock()).run(stdin.lock())?;
+    process::exit(if clean { 0 } else { 1 });
+}
+
+fn parse_args() -> Result<()> {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "jack-lsp".to_string());
+    for arg in args {
+        match arg.as_str() {
+            // the server only supports stdio, which editors may request explicitly
+            "--stdio" => {}
+            _ => bail!("Usage: {} [--stdio]", program),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! The subset of the Language Server Protocol types used by the server.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeParams {
+    #[serde(default)]
+    pub initialization_options: Option<InitializationOptions>,
+}
+
+/// Server settings sent by the client in the `initialize` request.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InitializationOptions {
+    /// Enables the language extensions, as `--extensions` of `jack-analyzer`.
+    #[serde(default)]
+    pub extensions: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextDocumentIdentifier {
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextDocumentItem {
+    pub uri: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidOpenTextDocumentParams {
+    pub text_document: TextDocumentItem,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidChangeTextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+    pub content_changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextDocumentContentChangeEvent {
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentPositionParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub source: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub related_information: Vec<DiagnosticRelatedInformation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticRelatedInformation {
+    pub location: Location,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkupContent {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hover {
+    pub contents: MarkupContent,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub kind: u8,
+    pub range: Range,
+    pub selection_range: Range,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DocumentSymbol>,
+}
+
+pub mod severity {
+    pub const ERROR: u8 = 1;
+    pub const WARNING: u8 = 2;
+}
+
+pub mod completion_kind {
+    pub const METHOD: u8 = 2;
+    pub const FUNCTION: u8 = 3;
+    pub const CONSTRUCTOR: u8 = 4;
+    pub const FIELD: u8 = 5;
+    pub const VARIABLE: u8 = 6;
+    pub const CLASS: u8 = 7;
+}
+
+pub mod symbol_kind {
+    pub const CLASS: u8 = 5;
+    pub const METHOD: u8 = 6;
+    pub const FIELD: u8 = 8;
+    pub const CONSTRUCTOR: u8 = 9;
+    pub const FUNCTION: u8 = 12;
+    pub const VARIABLE: u8 = 13;
+}
+
+pub mod error_code {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const INVALID_REQUEST: i32 = -32600;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+}
+
+/// Converts a `file://` URI to a path.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = path.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// Converts a path to a `file://` URI.
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn uri() {
+        let path = Path::new("/home/user/My Project/Main.jack");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/user/My%20Project/Main.jack");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+        assert_eq!(uri_to_path("file:///a%2"), None);
+    }
+}
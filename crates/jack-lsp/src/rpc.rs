@@ -0,0 +1,83 @@
+use serde_json::Value;
+use std::io::{self, prelude::*};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("IO error")]
+    Io(#[from] io::Error),
+    #[error("invalid header: {}", _0)]
+    InvalidHeader(String),
+    #[error("missing Content-Length header")]
+    MissingContentLength,
+    #[error("invalid JSON message")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Reads a message framed with the `Content-Length` header, or returns `None` at EOF.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, RpcError> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end_matches(&['\r', '\n'][..]);
+        if header.is_empty() {
+            if content_length.is_none() {
+                // skip empty lines between messages
+                continue;
+            }
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| RpcError::InvalidHeader(header.to_owned()))?;
+        if name.trim().eq_ignore_ascii_case("Content-Length") {
+            let len = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| RpcError::InvalidHeader(header.to_owned()))?;
+            content_length = Some(len);
+        }
+    }
+    let len = content_length.ok_or(RpcError::MissingContentLength)?;
+    let mut content = vec![0; len];
+    reader.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = serde_json::to_string(message)?;
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_trip() {
+        let messages = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ];
+        let mut buf = vec![];
+        for message in &messages {
+            write_message(&mut buf, message).unwrap();
+        }
+        let mut reader = &buf[..];
+        for message in &messages {
+            assert_eq!(read_message(&mut reader).unwrap().as_ref(), Some(message));
+        }
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
@@ -0,0 +1,711 @@
+use crate::{
+    analysis,
+    document::Document,
+    protocol::{self, *},
+    rpc::{self, RpcError},
+};
+use jack::{
+    ast::{Class, ParseOptions},
+    diagnostic::{self, ToDiagnostic},
+    lint::{self, LintLevels},
+    symbol_table::GlobalSymbolTable,
+    token::WithLoc,
+    typed_ast::ToControlFlowGraph,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+
+type RequestError = (i32, String);
+
+/// A language server holding the Jack files of the directories of the opened documents.
+///
+/// As `jack-analyzer` compiles a directory as a program, the classes of the files in the same
+/// directory as a document are visible from it.
+#[derive(Debug)]
+pub struct Server<W> {
+    writer: W,
+    documents: BTreeMap<PathBuf, Document>,
+    loaded_dirs: HashSet<PathBuf>,
+    options: ParseOptions,
+    shutdown: bool,
+}
+
+impl<W> Server<W>
+where
+    W: Write,
+{
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            documents: BTreeMap::new(),
+            loaded_dirs: HashSet::new(),
+            options: ParseOptions::default(),
+            shutdown: false,
+        }
+    }
+
+    /// Serves requests until the `exit` notification or EOF.
+    ///
+    /// Returns `true` if the client requested `shutdown` before exiting.
+    pub fn run(&mut self, mut reader: impl BufRead) -> Result<bool, RpcError> {
+        loop {
+            let message = match rpc::read_message(&mut reader) {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(RpcError::Json(e)) => {
+                    let response = json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": {"code": error_code::PARSE_ERROR, "message": e.to_string()},
+                    });
+                    rpc::write_message(&mut self.writer, &response)?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !self.handle(message)? {
+                break;
+            }
+        }
+        Ok(self.shutdown)
+    }
+
+    /// Handles a message, and returns `false` if the server should exit.
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        let method = match message.get("method").and_then(Value::as_str) {
+            Some(method) => method.to_owned(),
+            // responses to requests from the server are not used
+            None => return Ok(true),
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                if method == "exit" {
+                    return Ok(false);
+                }
+                self.notification(&method, params)?;
+                return Ok(true);
+            }
+        };
+        let response = match self.request(&method, params) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        };
+        rpc::write_message(&mut self.writer, &response)?;
+        Ok(true)
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, RequestError> {
+        if self.shutdown {
+            return Err((
+                error_code::INVALID_REQUEST,
+                "server is shutting down".into(),
+            ));
+        }
+        match method {
+            "initialize" => {
+                let params: InitializeParams = parse_params(params)?;
+                let options = params.initialization_options.unwrap_or_default();
+                self.options.extensions = options.extensions;
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                        "completionProvider": {"triggerCharacters": ["."]},
+                        "documentSymbolProvider": true,
+                    },
+                    "serverInfo": {"name": "jack-lsp"},
+                }))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let params: TextDocumentPositionParams = parse_params(params)?;
+                Ok(json!(self.definition(&params)))
+            }
+            "textDocument/hover" => {
+                let params: TextDocumentPositionParams = parse_params(params)?;
+                Ok(json!(self.hover(&params)))
+            }
+            "textDocument/completion" => {
+                let params: TextDocumentPositionParams = parse_params(params)?;
+                Ok(json!(self.completion(&params)))
+            }
+            "textDocument/documentSymbol" => {
+                let params: TextDocumentParams = parse_params(params)?;
+                let symbols = self
+                    .document(&params.text_document.uri)
+                    .map(analysis::document_symbols)
+                    .unwrap_or_default();
+                Ok(json!(symbols))
+            }
+            _ => Err((
+                error_code::METHOD_NOT_FOUND,
+                format!("unsupported method: {}", method),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let path = match method {
+            "textDocument/didOpen" => {
+                let params: DidOpenTextDocumentParams = match parse_params(params) {
+                    Ok(params) => params,
+                    Err(_) => return Ok(()),
+                };
+                let TextDocumentItem { uri, text } = params.text_document;
+                let path = document_path(&uri);
+                if let Some(dir) = path.parent() {
+                    self.load_dir(dir);
+                }
+                self.documents.insert(
+                    path.clone(),
+                    Document::new(uri, path.clone(), text, true, self.options),
+                );
+                path
+            }
+            "textDocument/didChange" => {
+                let params: DidChangeTextDocumentParams = match parse_params(params) {
+                    Ok(params) => params,
+                    Err(_) => return Ok(()),
+                };
+                let path = document_path(&params.text_document.uri);
+                let doc = match self.documents.get_mut(&path) {
+                    Some(doc) => doc,
+                    None => return Ok(()),
+                };
+                for change in params.content_changes {
+                    match change.range {
+                        Some(range) => doc.edit(range, &change.text),
+                        None => doc.set_text(change.text),
+                    }
+                }
+                path
+            }
+            "textDocument/didClose" => {
+                let params: TextDocumentParams = match parse_params(params) {
+                    Ok(params) => params,
+                    Err(_) => return Ok(()),
+                };
+                let uri = params.text_document.uri;
+                let path = document_path(&uri);
+                // fall back to the saved file, which is still a part of the program
+                match fs::read_to_string(&path) {
+                    Ok(text) => {
+                        self.documents.insert(
+                            path.clone(),
+                            Document::new(uri.clone(), path.clone(), text, false, self.options),
+                        );
+                    }
+                    Err(_) => {
+                        self.documents.remove(&path);
+                    }
+                }
+                self.publish(&PublishDiagnosticsParams {
+                    uri,
+                    diagnostics: vec![],
+                })?;
+                path
+            }
+            _ => return Ok(()),
+        };
+        self.publish_diagnostics(path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Reads the Jack files in the directory which are not opened yet.
+    fn load_dir(&mut self, dir: &Path) {
+        if dir.as_os_str().is_empty() || !self.loaded_dirs.insert(dir.to_owned()) {
+            return;
+        }
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("jack")
+                || self.documents.contains_key(&path)
+            {
+                continue;
+            }
+            if let Ok(text) = fs::read_to_string(&path) {
+                let uri = path_to_uri(&path);
+                self.documents.insert(
+                    path.clone(),
+                    Document::new(uri, path, text, false, self.options),
+                );
+            }
+        }
+    }
+
+    fn document(&self, uri: &str) -> Option<&Document> {
+        self.documents.get(&document_path(uri))
+    }
+
+    fn documents_in<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a Document> + 'a {
+        self.documents
+            .values()
+            .filter(move |doc| doc.path.parent().unwrap_or_else(|| Path::new("")) == dir)
+    }
+
+    /// Builds the symbol table of the classes in the directory.
+    ///
+    /// Returns the table with the errors of each file.
+    fn symbol_table(
+        &self,
+        dir: &Path,
+    ) -> (
+        GlobalSymbolTable,
+        HashMap<PathBuf, Vec<diagnostic::Diagnostic>>,
+    ) {
+        let mut table = GlobalSymbolTable::with_builtin();
+        let mut errors = HashMap::new();
+        for doc in self.documents_in(dir) {
+            if let Some(ast) = &doc.ast {
+                if let Err(e) = table.extend_with_class(&doc.path, &ast.data) {
+                    errors.insert(doc.path.clone(), vec![e.to_diagnostic()]);
+                }
+            }
+        }
+        (table, errors)
+    }
+
+    fn symbol_table_for(&self, doc: &Document) -> GlobalSymbolTable {
+        self.symbol_table(doc.path.parent().unwrap_or_else(|| Path::new("")))
+            .0
+    }
+
+    fn publish_diagnostics(&mut self, dir: &Path) -> io::Result<()> {
+        let (table, mut extend_errors) = self.symbol_table(dir);
+        let mut notifications = vec![];
+        for doc in self.documents_in(dir).filter(|doc| doc.open) {
+            let mut diagnostics = doc.parse_errors.clone();
+            diagnostics.extend(extend_errors.remove(&doc.path).unwrap_or_default());
+            if diagnostics.is_empty() {
+                if let Some(ast) = &doc.ast {
+                    diagnostics.extend(compile(ast, &table, &doc.lint_levels));
+                }
+            }
+            notifications.push(PublishDiagnosticsParams {
+                uri: doc.uri.clone(),
+                diagnostics: diagnostics
+                    .iter()
+                    .map(|diag| self.convert_diagnostic(doc, diag))
+                    .collect(),
+            });
+        }
+        for params in &notifications {
+            self.publish(params)?;
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, params: &PublishDiagnosticsParams) -> io::Result<()> {
+        rpc::write_message(
+            &mut self.writer,
+            &json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": params,
+            }),
+        )
+    }
+
+    fn convert_diagnostic(&self, doc: &Document, diag: &diagnostic::Diagnostic) -> Diagnostic {
+        let mut message = diag.message.clone();
+        for note in &diag.notes {
+            message.push_str("\nnote: ");
+            message.push_str(note);
+        }
+        let range = diag
+            .primary
+            .as_ref()
+            .map(|label| doc.range(label.loc))
+            .unwrap_or_default();
+        let related_information = diag
+            .primary
+            .iter()
+            .chain(&diag.secondary)
+            .filter_map(|label| {
+                let path = label.path.as_deref().unwrap_or(&doc.path);
+                let location = self.location(path, label.loc)?;
+                Some(DiagnosticRelatedInformation {
+                    location,
+                    message: label.message.clone(),
+                })
+            })
+            .collect();
+        Diagnostic {
+            range,
+            severity: match diag.severity {
+                diagnostic::Severity::Error => severity::ERROR,
+                diagnostic::Severity::Warning => severity::WARNING,
+            },
+            code: diag.code.clone(),
+            source: "jack",
+            message,
+            related_information,
+        }
+    }
+
+    fn location(&self, path: &Path, loc: jack::token::Location) -> Option<protocol::Location> {
+        let doc = self.documents.get(path)?;
+        Some(protocol::Location {
+            uri: doc.uri.clone(),
+            range: doc.range(loc),
+        })
+    }
+
+    fn occurrence(
+        &self,
+        params: &TextDocumentPositionParams,
+    ) -> Option<(&Document, analysis::Occurrence)> {
+        let doc = self.document(&params.text_document.uri)?;
+        let ast = doc.ast.as_ref()?;
+        let offset = doc.offset(params.position);
+        let occurrences = analysis::occurrences(&ast.data);
+        let occ = analysis::find(&occurrences, offset)?.clone();
+        Some((doc, occ))
+    }
+
+    fn definition(&self, params: &TextDocumentPositionParams) -> Option<protocol::Location> {
+        let (doc, occ) = self.occurrence(params)?;
+        let table = self.symbol_table_for(doc);
+        let (path, loc) = analysis::definition(&occ.target, &table, &doc.path)?;
+        self.location(&path, loc)
+    }
+
+    fn hover(&self, params: &TextDocumentPositionParams) -> Option<Hover> {
+        let (doc, occ) = self.occurrence(params)?;
+        let table = self.symbol_table_for(doc);
+        let value = analysis::hover(&occ.target, &table)?;
+        Some(Hover {
+            contents: MarkupContent {
+                kind: "markdown",
+                value,
+            },
+            range: doc.range(occ.loc),
+        })
+    }
+
+    fn completion(&self, params: &TextDocumentPositionParams) -> Vec<CompletionItem> {
+        let doc = match self.document(&params.text_document.uri) {
+            Some(doc) => doc,
+            None => return vec![],
+        };
+        let table = self.symbol_table_for(doc);
+        analysis::completions(doc, &table, doc.offset(params.position))
+    }
+}
+
+/// Runs the passes after the symbol table construction, and returns the lint warnings and the
+/// first error.
+fn compile(
+    ast: &WithLoc<Class>,
+    table: &GlobalSymbolTable,
+    lint_levels: &LintLevels,
+) -> Vec<diagnostic::Diagnostic> {
+    let typed = match ast.resolve(table) {
+        Ok(typed) => typed,
+        Err(e) => return vec![e.to_diagnostic()],
+    };
+    let mut cfg = match typed.to_control_flow_graph() {
+        Ok(cfg) => cfg,
+        Err(e) => return vec![e.to_diagnostic()],
+    };
+    let mut diagnostics = lint_levels.apply(&lint::check(&ast.data, &typed.data, &cfg.data));
+    diagnostics.extend(cfg.optimize().err().map(|e| e.to_diagnostic()));
+    diagnostics
+}
+
+fn parse_params<T>(params: Value) -> Result<T, RequestError>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_value(params).map_err(|e| (error_code::INVALID_PARAMS, e.to_string()))
+}
+
+/// Returns the path of the document, or the URI itself if it is not a file.
+fn document_path(uri: &str) -> PathBuf {
+    uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAIN: &str = "\
+class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new(1, 2);
+        do p.print();
+        do p.
+        return;
+    }
+}
+";
+
+    const POINT: &str = "\
+class Point {
+    field int x, y;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        return this;
+    }
+
+    method void print() {
+        do Output.printInt(x);
+        return;
+    }
+
+    function int origin() {
+        return 0;
+    }
+}
+";
+
+    struct Client {
+        dir: PathBuf,
+        input: Vec<u8>,
+        next_id: u64,
+    }
+
+    impl Client {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("jack-lsp-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("Point.jack"), POINT).unwrap();
+            Self {
+                dir,
+                input: vec![],
+                next_id: 0,
+            }
+        }
+
+        fn uri(&self, name: &str) -> String {
+            path_to_uri(&self.dir.join(name))
+        }
+
+        fn request(&mut self, method: &str, params: Value) -> u64 {
+            self.next_id += 1;
+            let message =
+                json!({"jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params});
+            rpc::write_message(&mut self.input, &message).unwrap();
+            self.next_id
+        }
+
+        fn notify(&mut self, method: &str, params: Value) {
+            let message = json!({"jsonrpc": "2.0", "method": method, "params": params});
+            rpc::write_message(&mut self.input, &message).unwrap();
+        }
+
+        fn position(&mut self, method: &str, name: &str, line: u32, character: u32) -> u64 {
+            let params = json!({
+                "textDocument": {"uri": self.uri(name)},
+                "position": {"line": line, "character": character},
+            });
+            self.request(method, params)
+        }
+
+        /// Runs the server, and returns the responses by ID and the notifications.
+        fn run(self) -> (bool, HashMap<u64, Value>, Vec<Value>) {
+            let mut output = vec![];
+            let clean = Server::new(&mut output).run(&self.input[..]).unwrap();
+            fs::remove_dir_all(&self.dir).unwrap();
+
+            let mut reader = &output[..];
+            let mut responses = HashMap::new();
+            let mut notifications = vec![];
+            while let Some(message) = rpc::read_message(&mut reader).unwrap() {
+                match message.get("id").and_then(Value::as_u64) {
+                    Some(id) => {
+                        responses.insert(id, message);
+                    }
+                    None => notifications.push(message),
+                }
+            }
+            (clean, responses, notifications)
+        }
+    }
+
+    #[test]
+    fn language_features() {
+        let mut client = Client::new("features");
+        let init = client.request("initialize", json!({"capabilities": {}}));
+        client.notify("initialized", json!({}));
+        let main_uri = client.uri("Main.jack");
+        client.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": main_uri, "languageId": "jack", "version": 1, "text": MAIN}}),
+        );
+        let def_class = client.position("textDocument/definition", "Main.jack", 3, 18);
+        let def_method = client.position("textDocument/definition", "Main.jack", 4, 14);
+        let def_var = client.position("textDocument/definition", "Main.jack", 4, 11);
+        let hover_var = client.position("textDocument/hover", "Main.jack", 3, 12);
+        let hover_sub = client.position("textDocument/hover", "Main.jack", 3, 22);
+        let completion = client.position("textDocument/completion", "Main.jack", 5, 13);
+        let class_completion = client.position("textDocument/completion", "Main.jack", 3, 22);
+        let symbols = client.request(
+            "textDocument/documentSymbol",
+            json!({"textDocument": {"uri": client.uri("Point.jack")}}),
+        );
+        let unknown = client.request("workspace/symbol", json!({"query": ""}));
+        let shutdown = client.request("shutdown", Value::Null);
+        client.notify("exit", Value::Null);
+        let point_uri = client.uri("Point.jack");
+
+        let (clean, responses, notifications) = client.run();
+        assert!(clean);
+        assert_eq!(
+            responses[&init]["result"]["capabilities"]["hoverProvider"],
+            true
+        );
+
+        assert_eq!(
+            responses[&def_class]["result"],
+            json!({"uri": point_uri, "range": {"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 11}}})
+        );
+        assert_eq!(
+            responses[&def_method]["result"]["range"]["start"],
+            json!({"line": 9, "character": 16})
+        );
+        assert_eq!(
+            responses[&def_var]["result"],
+            json!({"uri": main_uri, "range": {"start": {"line": 2, "character": 18}, "end": {"line": 2, "character": 19}}})
+        );
+
+        assert_eq!(
+            responses[&hover_var]["result"]["contents"]["value"],
+            "```jack\nvar Point p\n```"
+        );
+        assert_eq!(
+            responses[&hover_sub]["result"]["contents"]["value"],
+            "```jack\nconstructor Point Point.new(int ax, int ay)\n```"
+        );
+
+        let labels = |id: u64| {
+            responses[&id]["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["label"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(completion), ["print"]);
+        assert_eq!(labels(class_completion), ["new", "origin"]);
+
+        let symbols = &responses[&symbols]["result"][0];
+        assert_eq!(symbols["name"], "Point");
+        let children = symbols["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| {
+                (
+                    child["name"].as_str().unwrap(),
+                    child["kind"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            children,
+            [("x", 8), ("y", 8), ("new", 9), ("print", 6), ("origin", 12)]
+        );
+
+        assert_eq!(
+            responses[&unknown]["error"]["code"],
+            error_code::METHOD_NOT_FOUND
+        );
+        assert_eq!(responses[&shutdown]["result"], Value::Null);
+
+        // `do p.` is a syntax error
+        let diagnostics = &notifications[0]["params"];
+        assert_eq!(diagnostics["uri"], main_uri);
+        assert_eq!(diagnostics["diagnostics"][0]["range"]["start"]["line"], 6);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut client = Client::new("diagnostics");
+        client.request("initialize", json!({"capabilities": {}}));
+        let uri = client.uri("Main.jack");
+        client.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": uri, "text": "class Main {\n    function void main() {\n        do Point.foo();\n        return;\n    }\n}\n"}}),
+        );
+        client.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{
+                    "range": {"start": {"line": 2, "character": 17}, "end": {"line": 2, "character": 20}},
+                    "text": "origin",
+                }],
+            }),
+        );
+        let (clean, _, notifications) = client.run();
+        assert!(!clean);
+        assert_eq!(notifications.len(), 2);
+        let diagnostic = &notifications[0]["params"]["diagnostics"][0];
+        assert_eq!(
+            diagnostic["message"],
+            "class `Point` does not have a class method `foo`"
+        );
+        assert_eq!(
+            diagnostic["range"],
+            json!({"start": {"line": 2, "character": 17}, "end": {"line": 2, "character": 20}})
+        );
+        // the error is fixed, but the result of the call is discarded
+        let diagnostics = &notifications[1]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["severity"], severity::WARNING);
+        assert_eq!(diagnostics[0]["code"], "unused-result");
+    }
+
+    #[test]
+    fn extensions() {
+        const SOURCE: &str = "class Main {\n    function void main() {\n        var int i;\n        for (let i = 0; i < 3; let i = i + 1) {\n            if (i = 1) {\n                break;\n            }\n        }\n        return;\n    }\n}\n";
+
+        let diagnostics = |name: &str, params: Value| {
+            let mut client = Client::new(name);
+            client.request("initialize", params);
+            let uri = client.uri("Main.jack");
+            client.notify(
+                "textDocument/didOpen",
+                json!({"textDocument": {"uri": uri, "text": SOURCE}}),
+            );
+            let (_, _, notifications) = client.run();
+            notifications[0]["params"]["diagnostics"].clone()
+        };
+
+        let diags = diagnostics("extensions-off", json!({"capabilities": {}}));
+        assert!(diags[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("requires the language extensions"));
+
+        let diags = diagnostics(
+            "extensions-on",
+            json!({"capabilities": {}, "initializationOptions": {"extensions": true}}),
+        );
+        assert_eq!(diags, json!([]));
+    }
+}
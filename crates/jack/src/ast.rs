@@ -1,25 +1,37 @@
 use crate::token::{Ident, WithLoc};
 pub use parser::*;
 pub use resolver::*;
+use serde::{Serialize, Serializer};
 
 mod parser;
 mod resolver;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Class {
     pub name: WithLoc<Ident>,
+    /// Compile-time constants, only with the language extensions.
+    pub consts: Vec<WithLoc<ConstDec>>,
     pub vars: Vec<WithLoc<ClassVarDec>>,
     pub subs: Vec<WithLoc<Subroutine>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ClassVarDec {
     pub kind: WithLoc<ClassVarKind>,
     pub ty: WithLoc<Type>,
     pub names: Vec<WithLoc<Ident>>,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// `const type NAME = expr;`, only with the language extensions.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConstDec {
+    pub ty: WithLoc<Type>,
+    pub name: WithLoc<Ident>,
+    pub expr: WithLoc<Expression>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ClassVarKind {
     Static,
     Field,
@@ -60,7 +72,16 @@ impl Type {
     }
 }
 
-#[derive(Debug, Clone)]
+impl Serialize for Type {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum ReturnType {
     Void,
     Type(WithLoc<Type>),
@@ -75,7 +96,7 @@ impl ReturnType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Subroutine {
     pub kind: WithLoc<SubroutineKind>,
     pub return_type: WithLoc<ReturnType>,
@@ -84,7 +105,8 @@ pub struct Subroutine {
     pub body: WithLoc<SubroutineBody>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SubroutineKind {
     Constructor,
     Function,
@@ -101,88 +123,107 @@ impl SubroutineKind {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ParameterList(pub Vec<WithLoc<Parameter>>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Parameter {
     pub ty: WithLoc<Type>,
     pub name: WithLoc<Ident>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SubroutineBody {
     pub vars: Vec<WithLoc<LocalVarDec>>,
     pub stmts: WithLoc<StatementList>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LocalVarDec {
     pub ty: WithLoc<Type>,
     pub names: Vec<WithLoc<Ident>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct StatementList(pub Vec<WithLoc<Statement>>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Statement {
     Let(WithLoc<LetStatement>),
     If(WithLoc<IfStatement>),
     While(WithLoc<WhileStatement>),
     Do(WithLoc<DoStatement>),
     Return(WithLoc<ReturnStatement>),
+    /// `for (init; cond; step) { stmts }`, only with the language extensions.
+    For(Box<WithLoc<ForStatement>>),
+    /// `break;`, only with the language extensions.
+    Break,
+    /// `continue;`, only with the language extensions.
+    Continue,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LetStatement {
     pub target: WithLoc<Ident>,
     pub target_index: Option<WithLoc<Expression>>,
     pub expr: WithLoc<Expression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct IfStatement {
     pub cond: WithLoc<Expression>,
     pub then_stmts: WithLoc<StatementList>,
     pub else_stmts: Option<WithLoc<StatementList>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WhileStatement {
     pub cond: WithLoc<Expression>,
     pub stmts: WithLoc<StatementList>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct ForStatement {
+    pub init: Option<WithLoc<LetStatement>>,
+    /// The loop runs until `break` or `return` if the condition is omitted.
+    pub cond: Option<WithLoc<Expression>>,
+    pub step: Option<WithLoc<LetStatement>>,
+    pub stmts: WithLoc<StatementList>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct DoStatement {
     pub sub_call: WithLoc<SubroutineCall>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ReturnStatement {
     pub expr: Option<WithLoc<Expression>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Expression {
     pub term: WithLoc<Term>,
     pub binary_ops: Vec<(WithLoc<BinaryOp>, WithLoc<Term>)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Term {
     IntConstant(WithLoc<u16>),
     StringConstant(WithLoc<String>),
+    /// A character literal in the Hack character set, only with the language extensions.
+    CharConstant(WithLoc<char>),
     KeywordConstant(WithLoc<KeywordConstant>),
     Variable(WithLoc<Ident>),
     Index(WithLoc<Ident>, Box<WithLoc<Expression>>),
+    /// `Class.NAME` referring to a constant of another class, only with the language extensions.
+    ClassConstant(WithLoc<Ident>, WithLoc<Ident>),
     SubroutineCall(WithLoc<SubroutineCall>),
     Expression(Box<WithLoc<Expression>>),
     UnaryOp(WithLoc<UnaryOp>, Box<WithLoc<Term>>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum SubroutineCall {
     SubroutineCall(WithLoc<Ident>, WithLoc<ExpressionList>),
     PropertyCall(WithLoc<Ident>, WithLoc<Ident>, WithLoc<ExpressionList>),
@@ -197,10 +238,10 @@ impl SubroutineCall {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ExpressionList(pub Vec<WithLoc<Expression>>);
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -228,6 +269,30 @@ impl BinaryOp {
         }
     }
 
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::And => "&",
+            Self::Or => "|",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Eq => "=",
+        }
+    }
+
+    /// Returns the conventional precedence of the operator, higher binding tighter.
+    pub(crate) fn precedence(&self) -> u8 {
+        match self {
+            Self::Mul | Self::Div => 3,
+            Self::Add | Self::Sub => 2,
+            Self::Lt | Self::Gt | Self::Eq => 1,
+            Self::And | Self::Or => 0,
+        }
+    }
+
     pub(crate) fn get_ty(&self, lhs_ty: &Type, rhs_ty: &Type) -> (Type, Type) {
         match self {
             Self::Add | Self::Sub | Self::Mul | Self::Div => (Type::Int, Type::Int),
@@ -243,7 +308,7 @@ impl BinaryOp {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -271,7 +336,8 @@ impl UnaryOp {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum KeywordConstant {
     True,
     False,
@@ -1,10 +1,16 @@
 use self::private::FromTokensImpl;
+pub use self::recovery::*;
 use super::*;
-use crate::token::{Keyword, Location, Symbol, Token, WithLoc};
-use common::iter::Prependable;
-use std::error::Error as StdError;
+use crate::{
+    diagnostic::{Diagnostic, ToDiagnostic},
+    token::{Keyword, Location, ParseTokenError, Symbol, Token, WithLoc},
+};
+use common::iter::{IteratorExt, Prependable};
+use std::{error::Error as StdError, iter::Peekable};
 use thiserror::Error;
 
+mod recovery;
+
 #[derive(Debug, Error)]
 pub enum ParseError<E> {
     #[error(transparent)]
@@ -13,6 +19,10 @@ pub enum ParseError<E> {
     UnexpectedEof,
     #[error("expected {}, found `{}` at {}", _0, _1.data, _1.loc)]
     Expected(String, WithLoc<Token>),
+    #[error("`{}` requires the language extensions", _0.data)]
+    Extension(WithLoc<String>),
+    #[error("`{}` outside of a loop", _0.data)]
+    OutsideLoop(WithLoc<Keyword>),
     #[error("in parsing {} at {}", _0, _1.map(|loc| loc.to_string()).unwrap_or_else(|| "<EOF>".to_owned()))]
     Context(
         String,
@@ -21,6 +31,161 @@ pub enum ParseError<E> {
     ),
 }
 
+impl<E> ParseError<E>
+where
+    E: StdError + Send + Sync + 'static,
+{
+    /// Returns the location of the token that caused the error, if known.
+    pub fn loc(&self) -> Option<Location> {
+        match self {
+            Self::Tokenize(_) | Self::UnexpectedEof => None,
+            Self::Expected(_, found) => Some(found.loc),
+            Self::Extension(syntax) => Some(syntax.loc),
+            Self::OutsideLoop(keyword) => Some(keyword.loc),
+            Self::Context(_, loc, source) => {
+                source.downcast_ref::<Self>().and_then(|e| e.loc()).or(*loc)
+            }
+        }
+    }
+
+    /// Returns `true` if parsing cannot continue after the error.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::Tokenize(_) | Self::UnexpectedEof => true,
+            Self::Expected(..) | Self::Extension(_) | Self::OutsideLoop(_) => false,
+            Self::Context(_, _, source) => source
+                .downcast_ref::<Self>()
+                .map(|e| e.is_fatal())
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Language options affecting the parsing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Groups binary operators by their conventional precedence instead of evaluating them from
+    /// left to right. `*` and `/` bind tighter than `+` and `-`, then comparisons, then `&`
+    /// and `|`.
+    pub precedence: bool,
+    /// Accepts `for`, `break`, `continue`, `else if` and class constants. The lexer emits the
+    /// keywords of the extensions and the extended literals only when enabled separately by
+    /// [`Tokens::with_extensions`](crate::token::Tokens).
+    pub extensions: bool,
+}
+
+/// A stream of tokens that remembers where the last consumed token ends.
+pub struct TokenStream<I>
+where
+    I: Iterator,
+{
+    tokens: Prependable<I>,
+    last_end: Option<u32>,
+    prev_end: Option<u32>,
+    options: ParseOptions,
+}
+
+impl<I, E> TokenStream<I>
+where
+    I: Iterator<Item = Result<WithLoc<Token>, E>>,
+{
+    pub fn new(tokens: I) -> Self {
+        Self::with_options(tokens, ParseOptions::default())
+    }
+
+    pub fn with_options(tokens: I, options: ParseOptions) -> Self {
+        Self {
+            tokens: tokens.prependable(),
+            last_end: None,
+            prev_end: None,
+            options,
+        }
+    }
+
+    pub fn peek(&mut self) -> Option<&I::Item> {
+        self.tokens.peek()
+    }
+
+    pub fn prepend(&mut self, item: I::Item) {
+        if item.is_ok() {
+            self.last_end = self.prev_end;
+        }
+        self.tokens.prepend(item);
+    }
+
+    /// Returns the span from `start` to the end of the last consumed token.
+    pub(crate) fn span_from(&self, start: Location) -> Location {
+        Location {
+            end: self.last_end.unwrap_or(start.end).max(start.start),
+            ..start
+        }
+    }
+}
+
+impl<I, E> Iterator for TokenStream<I>
+where
+    I: Iterator<Item = Result<WithLoc<Token>, E>>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.tokens.next();
+        if let Some(Ok(token)) = &item {
+            self.prev_end = self.last_end;
+            self.last_end = Some(token.loc.end);
+        }
+        item
+    }
+}
+
+impl<E> ToDiagnostic for ParseError<E>
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::Tokenize(e) => {
+                let mut message = e.to_string();
+                let mut source: Option<&(dyn StdError + 'static)> = Some(e);
+                while let Some(e) = source {
+                    if let Some(e) = e.downcast_ref::<ParseTokenError>() {
+                        return e.to_diagnostic();
+                    }
+                    source = e.source();
+                    if let Some(e) = source {
+                        message = format!("{}: {}", message, e);
+                    }
+                }
+                Diagnostic::error(message)
+            }
+            Self::UnexpectedEof => Diagnostic::error("unexpected end of file"),
+            Self::Expected(expected, found) => {
+                Diagnostic::error(format!("expected {}, found `{}`", expected, found.data))
+                    .with_primary(found.loc, "unexpected token")
+            }
+            Self::Extension(syntax) => Diagnostic::error(self.to_string())
+                .with_primary(syntax.loc, "not part of standard Jack")
+                .with_note(
+                    "`for`, `break`, `continue`, `else if` and `const` are language extensions",
+                ),
+            Self::OutsideLoop(keyword) => Diagnostic::error(self.to_string()).with_primary(
+                keyword.loc,
+                format!("`{}` is only allowed in loops", keyword.data),
+            ),
+            Self::Context(context, loc, source) => {
+                let diag = match source.downcast_ref::<Self>() {
+                    Some(e) => e.to_diagnostic(),
+                    None => Diagnostic::error(source.to_string()),
+                };
+                match loc {
+                    Some(loc) => diag.with_secondary(*loc, format!("while parsing {}", context)),
+                    None => diag.with_note(format!("while parsing {}", context)),
+                }
+            }
+        }
+    }
+}
+
 fn expected<E>(expected: impl Into<String>, found: WithLoc<Token>) -> ParseError<E> {
     ParseError::Expected(expected.into(), found)
 }
@@ -44,9 +209,8 @@ trait TokensExt<E> {
     fn ident(&mut self) -> Result<WithLoc<Ident>, ParseError<E>>;
     fn try_int(&mut self) -> Result<Option<WithLoc<u16>>, ParseError<E>>;
     fn try_string(&mut self) -> Result<Option<WithLoc<String>>, ParseError<E>>;
-    fn repeat_opt<F, T>(&mut self, f: F) -> Result<Vec<WithLoc<T>>, ParseError<E>>
-    where
-        F: FnMut(&mut Self) -> Result<Option<WithLoc<T>>, ParseError<E>>;
+    fn try_char(&mut self) -> Result<Option<WithLoc<char>>, ParseError<E>>;
+    fn unexpected(&mut self, expected: &str) -> ParseError<E>;
     fn comma_separated<F, T>(&mut self, f: F) -> Result<Vec<WithLoc<T>>, ParseError<E>>
     where
         F: FnMut(&mut Self) -> Result<WithLoc<T>, ParseError<E>>;
@@ -55,7 +219,7 @@ trait TokensExt<E> {
         F: FnMut(&mut Self) -> Result<Option<WithLoc<T>>, ParseError<E>>;
 }
 
-impl<I, E> TokensExt<E> for Prependable<I>
+impl<I, E> TokensExt<E> for TokenStream<I>
 where
     I: Iterator<Item = Result<WithLoc<Token>, E>>,
     E: StdError + Send + Sync + 'static,
@@ -87,7 +251,14 @@ where
     where
         F: FnOnce(Token) -> Result<T, Token>,
     {
-        Ok(self.token()?.map(f).transpose())
+        // leave the unexpected token for error recovery
+        match self.token()?.map(f).transpose() {
+            Ok(value) => Ok(Ok(value)),
+            Err(token) => {
+                self.prepend(Ok(token.clone()));
+                Ok(Err(token))
+            }
+        }
     }
 
     fn try_keyword(&mut self, keyword: Keyword) -> Result<Option<WithLoc<Keyword>>, ParseError<E>> {
@@ -149,15 +320,21 @@ where
         })
     }
 
-    fn repeat_opt<F, T>(&mut self, mut f: F) -> Result<Vec<WithLoc<T>>, ParseError<E>>
-    where
-        F: FnMut(&mut Self) -> Result<Option<WithLoc<T>>, ParseError<E>>,
-    {
-        let mut values = vec![];
-        while let Some(val) = f(self)? {
-            values.push(val);
+    fn try_char(&mut self) -> Result<Option<WithLoc<char>>, ParseError<E>> {
+        self.try_token_with(|token| match token {
+            Token::Char(ch) => Ok(ch),
+            token => Err(token),
+        })
+    }
+
+    fn unexpected(&mut self, expected: &str) -> ParseError<E> {
+        match self.token() {
+            Ok(token) => {
+                self.prepend(Ok(token.clone()));
+                self::expected(expected, token)
+            }
+            Err(e) => e,
         }
-        Ok(values)
     }
 
     fn comma_separated<F, T>(&mut self, mut f: F) -> Result<Vec<WithLoc<T>>, ParseError<E>>
@@ -199,7 +376,7 @@ mod private {
 
         fn is_start_token(token: &Token) -> bool;
 
-        fn from_tokens_impl<I, E>(_tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+        fn from_tokens_impl<I, E>(_tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
         where
             Self: Sized,
             I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -212,7 +389,7 @@ mod private {
 
 pub trait FromTokens: FromTokensImpl {
     fn try_from_tokens<I, E>(
-        tokens: &mut Prependable<I>,
+        tokens: &mut TokenStream<I>,
     ) -> Result<Option<WithLoc<Self>>, ParseError<E>>
     where
         Self: Sized,
@@ -227,7 +404,7 @@ pub trait FromTokens: FromTokensImpl {
         }
     }
 
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -244,58 +421,58 @@ pub trait FromTokens: FromTokensImpl {
                 e
             }
         })?;
-        let loc = loc.unwrap();
+        let loc = tokens.span_from(loc.unwrap());
         Ok(WithLoc { loc, data })
     }
 }
 
-impl FromTokensImpl for Class {
+impl FromTokensImpl for ClassVarDec {
     fn context() -> Option<String> {
-        Some("class declaration".into())
+        Some("class variable declaration".into())
     }
 
     fn is_start_token(token: &Token) -> bool {
-        matches!(token, Token::Keyword(Keyword::Class))
+        ClassVarKind::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
         E: StdError + Send + Sync + 'static,
     {
-        tokens.keyword(Keyword::Class)?;
-        let name = tokens.ident()?;
-        tokens.symbol(Symbol::OpenBrace)?;
-        let vars = tokens.repeat_opt(ClassVarDec::try_from_tokens)?;
-        let subs = tokens.repeat_opt(Subroutine::try_from_tokens)?;
-        tokens.symbol(Symbol::CloseBrace)?;
-        Ok(Self { name, vars, subs })
+        let kind = ClassVarKind::from_tokens(tokens)?;
+        let ty = Type::from_tokens(tokens)?;
+        let names = tokens.comma_separated(|tokens| tokens.ident())?;
+        tokens.symbol(Symbol::Semicolon)?;
+        Ok(Self { kind, ty, names })
     }
 }
-impl FromTokens for Class {}
+impl FromTokens for ClassVarDec {}
 
-impl FromTokensImpl for ClassVarDec {
+impl FromTokensImpl for ConstDec {
     fn context() -> Option<String> {
-        Some("class variable declaration".into())
+        Some("constant declaration".into())
     }
 
     fn is_start_token(token: &Token) -> bool {
-        ClassVarKind::is_start_token(token)
+        matches!(token, Token::Keyword(Keyword::Const))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
         E: StdError + Send + Sync + 'static,
     {
-        let kind = ClassVarKind::from_tokens(tokens)?;
+        tokens.keyword(Keyword::Const)?;
         let ty = Type::from_tokens(tokens)?;
-        let names = tokens.comma_separated(|tokens| tokens.ident())?;
+        let name = tokens.ident()?;
+        tokens.symbol(Symbol::Equal)?;
+        let expr = Expression::from_tokens(tokens)?;
         tokens.symbol(Symbol::Semicolon)?;
-        Ok(Self { kind, ty, names })
+        Ok(Self { ty, name, expr })
     }
 }
-impl FromTokens for ClassVarDec {}
+impl FromTokens for ConstDec {}
 
 impl FromTokensImpl for ClassVarKind {
     fn is_start_token(token: &Token) -> bool {
@@ -303,7 +480,7 @@ impl FromTokensImpl for ClassVarKind {
     }
 }
 impl FromTokens for ClassVarKind {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -328,7 +505,7 @@ impl FromTokensImpl for Type {
     }
 }
 impl FromTokens for Type {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -351,7 +528,7 @@ impl FromTokensImpl for ReturnType {
         matches!(token, Token::Keyword(Keyword::Void)) || Type::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
         E: StdError + Send + Sync + 'static,
@@ -364,39 +541,6 @@ impl FromTokensImpl for ReturnType {
 }
 impl FromTokens for ReturnType {}
 
-impl FromTokensImpl for Subroutine {
-    fn context() -> Option<String> {
-        Some("subroutine declaration".into())
-    }
-
-    fn is_start_token(token: &Token) -> bool {
-        SubroutineKind::is_start_token(token)
-    }
-
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        let kind = SubroutineKind::from_tokens(tokens)?;
-        let return_type = ReturnType::from_tokens(tokens)?;
-        let name = tokens.ident()?;
-        tokens.symbol(Symbol::OpenParen)?;
-        let params = ParameterList::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseParen)?;
-        let body = SubroutineBody::from_tokens(tokens)?;
-        Ok(Self {
-            kind,
-            return_type,
-            name,
-            params,
-            body,
-        })
-    }
-}
-impl FromTokens for Subroutine {}
-
 impl FromTokensImpl for SubroutineKind {
     fn is_start_token(token: &Token) -> bool {
         matches!(
@@ -406,7 +550,7 @@ impl FromTokensImpl for SubroutineKind {
     }
 }
 impl FromTokens for SubroutineKind {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -428,7 +572,7 @@ impl FromTokensImpl for ParameterList {
         Parameter::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -446,7 +590,7 @@ impl FromTokensImpl for Parameter {
         Type::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -459,30 +603,6 @@ impl FromTokensImpl for Parameter {
 }
 impl FromTokens for Parameter {}
 
-impl FromTokensImpl for SubroutineBody {
-    fn context() -> Option<String> {
-        Some("subroutine body".into())
-    }
-
-    fn is_start_token(token: &Token) -> bool {
-        matches!(token, Token::Symbol(Symbol::OpenBrace))
-    }
-
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        tokens.symbol(Symbol::OpenBrace)?;
-        let vars = tokens.repeat_opt(LocalVarDec::try_from_tokens)?;
-        let stmts = StatementList::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseBrace)?;
-        Ok(Self { vars, stmts })
-    }
-}
-impl FromTokens for SubroutineBody {}
-
 impl FromTokensImpl for LocalVarDec {
     fn context() -> Option<String> {
         Some("variable declaration".into())
@@ -492,7 +612,7 @@ impl FromTokensImpl for LocalVarDec {
         matches!(token, Token::Keyword(Keyword::Var))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -507,57 +627,6 @@ impl FromTokensImpl for LocalVarDec {
 }
 impl FromTokens for LocalVarDec {}
 
-impl FromTokensImpl for StatementList {
-    fn is_start_token(token: &Token) -> bool {
-        Statement::is_start_token(token)
-    }
-
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        Ok(Self(tokens.repeat_opt(Statement::try_from_tokens)?))
-    }
-}
-impl FromTokens for StatementList {}
-
-impl FromTokensImpl for Statement {
-    fn is_start_token(token: &Token) -> bool {
-        LetStatement::is_start_token(token)
-            || IfStatement::is_start_token(token)
-            || WhileStatement::is_start_token(token)
-            || DoStatement::is_start_token(token)
-            || ReturnStatement::is_start_token(token)
-    }
-
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        if let Some(stmt) = LetStatement::try_from_tokens(tokens)? {
-            return Ok(Statement::Let(stmt));
-        }
-        if let Some(stmt) = IfStatement::try_from_tokens(tokens)? {
-            return Ok(Statement::If(stmt));
-        }
-        if let Some(stmt) = WhileStatement::try_from_tokens(tokens)? {
-            return Ok(Statement::While(stmt));
-        }
-        if let Some(stmt) = DoStatement::try_from_tokens(tokens)? {
-            return Ok(Statement::Do(stmt));
-        }
-        if let Some(stmt) = ReturnStatement::try_from_tokens(tokens)? {
-            return Ok(Statement::Return(stmt));
-        }
-        Err(expected("statement", tokens.token()?))
-    }
-}
-impl FromTokens for Statement {}
-
 impl FromTokensImpl for LetStatement {
     fn context() -> Option<String> {
         Some("let statement".into())
@@ -567,94 +636,62 @@ impl FromTokensImpl for LetStatement {
         matches!(token, Token::Keyword(Keyword::Let))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
         E: StdError + Send + Sync + 'static,
     {
-        tokens.keyword(Keyword::Let)?;
-        let target = tokens.ident()?;
-        let mut target_index = None;
-        if tokens.try_symbol(Symbol::OpenBracket)?.is_some() {
-            target_index = Some(Expression::from_tokens(tokens)?);
-            tokens.symbol(Symbol::CloseBracket)?;
-        }
-        tokens.symbol(Symbol::Equal)?;
-        let expr = Expression::from_tokens(tokens)?;
+        let stmt = let_clause(tokens)?;
         tokens.symbol(Symbol::Semicolon)?;
-        Ok(Self {
-            target,
-            target_index,
-            expr,
-        })
+        Ok(stmt)
     }
 }
 impl FromTokens for LetStatement {}
 
-impl FromTokensImpl for IfStatement {
-    fn context() -> Option<String> {
-        Some("if statement".into())
-    }
-
-    fn is_start_token(token: &Token) -> bool {
-        matches!(token, Token::Keyword(Keyword::If))
-    }
-
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        tokens.keyword(Keyword::If)?;
-        tokens.symbol(Symbol::OpenParen)?;
-        let cond = Expression::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseParen)?;
-        tokens.symbol(Symbol::OpenBrace)?;
-        let then_stmts = StatementList::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseBrace)?;
-        let mut else_stmts = None;
-        if tokens.try_keyword(Keyword::Else)?.is_some() {
-            tokens.symbol(Symbol::OpenBrace)?;
-            else_stmts = Some(StatementList::from_tokens(tokens)?);
-            tokens.symbol(Symbol::CloseBrace)?;
-        }
-        Ok(Self {
-            cond,
-            then_stmts,
-            else_stmts,
-        })
-    }
+/// Parses a let statement without the trailing `;`.
+fn let_clause<I, E>(tokens: &mut TokenStream<I>) -> Result<LetStatement, ParseError<E>>
+where
+    I: Iterator<Item = Result<WithLoc<Token>, E>>,
+    E: StdError + Send + Sync + 'static,
+{
+    tokens.keyword(Keyword::Let)?;
+    let target = tokens.ident()?;
+    let mut target_index = None;
+    if tokens.try_symbol(Symbol::OpenBracket)?.is_some() {
+        target_index = Some(Expression::from_tokens(tokens)?);
+        tokens.symbol(Symbol::CloseBracket)?;
+    }
+    tokens.symbol(Symbol::Equal)?;
+    let expr = Expression::from_tokens(tokens)?;
+    Ok(LetStatement {
+        target,
+        target_index,
+        expr,
+    })
 }
-impl FromTokens for IfStatement {}
-
-impl FromTokensImpl for WhileStatement {
-    fn context() -> Option<String> {
-        Some("while statement".into())
-    }
-
-    fn is_start_token(token: &Token) -> bool {
-        matches!(token, Token::Keyword(Keyword::While))
-    }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
-    where
-        Self: Sized,
-        I: Iterator<Item = Result<WithLoc<Token>, E>>,
-        E: StdError + Send + Sync + 'static,
-    {
-        tokens.keyword(Keyword::While)?;
-        tokens.symbol(Symbol::OpenParen)?;
-        let cond = Expression::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseParen)?;
-        tokens.symbol(Symbol::OpenBrace)?;
-        let stmts = StatementList::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseBrace)?;
-        Ok(Self { cond, stmts })
-    }
+/// Parses the optional initialization or step of a `for` statement, a let statement without the
+/// trailing `;`.
+fn for_clause<I, E>(
+    tokens: &mut TokenStream<I>,
+) -> Result<Option<WithLoc<LetStatement>>, ParseError<E>>
+where
+    I: Iterator<Item = Result<WithLoc<Token>, E>>,
+    E: StdError + Send + Sync + 'static,
+{
+    let loc = match tokens.peek() {
+        Some(Ok(token)) if LetStatement::is_start_token(&token.data) => token.loc,
+        _ => return Ok(None),
+    };
+    let data = let_clause(tokens).map_err(|e| {
+        ParseError::Context(LetStatement::context().unwrap(), Some(loc), Box::new(e))
+    })?;
+    Ok(Some(WithLoc {
+        loc: tokens.span_from(loc),
+        data,
+    }))
 }
-impl FromTokens for WhileStatement {}
 
 impl FromTokensImpl for DoStatement {
     fn context() -> Option<String> {
@@ -665,7 +702,7 @@ impl FromTokensImpl for DoStatement {
         matches!(token, Token::Keyword(Keyword::Do))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -689,7 +726,7 @@ impl FromTokensImpl for ReturnStatement {
         matches!(token, Token::Keyword(Keyword::Return))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -709,7 +746,7 @@ impl FromTokensImpl for Expression {
         Term::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -721,24 +758,79 @@ impl FromTokensImpl for Expression {
             let operand = Term::from_tokens(tokens)?;
             binary_ops.push((op, operand));
         }
+        if tokens.options.precedence {
+            let mut binary_ops = binary_ops.into_iter().peekable();
+            return Ok(group_by_precedence(term, &mut binary_ops, 0).data);
+        }
         Ok(Self { term, binary_ops })
     }
 }
 impl FromTokens for Expression {}
 
+/// Parses the operators of a flat expression by precedence climbing, keeping operators of the
+/// same precedence flat and wrapping the tighter ones into parenthesized terms.
+fn group_by_precedence(
+    term: WithLoc<Term>,
+    binary_ops: &mut Peekable<impl Iterator<Item = (WithLoc<BinaryOp>, WithLoc<Term>)>>,
+    min_precedence: u8,
+) -> WithLoc<Expression> {
+    let mut expr = WithLoc {
+        loc: term.loc,
+        data: Expression {
+            term,
+            binary_ops: vec![],
+        },
+    };
+    while let Some((op, _)) = binary_ops.peek() {
+        let precedence = op.data.precedence();
+        if precedence < min_precedence {
+            break;
+        }
+        let (op, operand) = binary_ops.next().unwrap();
+        let operand = group_by_precedence(operand, binary_ops, precedence + 1);
+        let operand = into_term(operand);
+        if let Some((last_op, _)) = expr.data.binary_ops.last() {
+            if last_op.data.precedence() != precedence {
+                let lhs = into_term(expr);
+                expr = WithLoc {
+                    loc: lhs.loc,
+                    data: Expression {
+                        term: lhs,
+                        binary_ops: vec![],
+                    },
+                };
+            }
+        }
+        expr.loc = expr.loc.to(operand.loc);
+        expr.data.binary_ops.push((op, operand));
+    }
+    expr
+}
+
+fn into_term(expr: WithLoc<Expression>) -> WithLoc<Term> {
+    if expr.data.binary_ops.is_empty() {
+        return expr.data.term;
+    }
+    WithLoc {
+        loc: expr.loc,
+        data: Term::Expression(Box::new(expr)),
+    }
+}
+
 impl FromTokensImpl for Term {
     fn is_start_token(token: &Token) -> bool {
         matches!(
             token,
             Token::Int(_)
                 | Token::String(_)
+                | Token::Char(_)
                 | Token::Keyword(_)
                 | Token::Ident(_)
                 | Token::Symbol(Symbol::OpenParen)
         ) || UnaryOp::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -750,6 +842,9 @@ impl FromTokensImpl for Term {
         if let Some(s) = tokens.try_string()? {
             return Ok(Self::StringConstant(s));
         }
+        if let Some(ch) = tokens.try_char()? {
+            return Ok(Self::CharConstant(ch));
+        }
         if let Some(k) = KeywordConstant::try_from_tokens(tokens)? {
             return Ok(Self::KeywordConstant(k));
         }
@@ -759,6 +854,23 @@ impl FromTokensImpl for Term {
                 tokens.symbol(Symbol::CloseBracket)?;
                 return Ok(Self::Index(var, Box::new(index)));
             }
+            if tokens.options.extensions && tokens.try_symbol(Symbol::Dot)?.is_some() {
+                let name = tokens.ident()?;
+                let is_call = matches!(
+                    tokens.peek(),
+                    Some(Ok(WithLoc {
+                        data: Token::Symbol(Symbol::OpenParen),
+                        ..
+                    }))
+                );
+                if !is_call {
+                    return Ok(Self::ClassConstant(var, name));
+                }
+                let loc = var.loc;
+                let args = SubroutineCall::args_from_tokens(tokens)?;
+                let data = SubroutineCall::PropertyCall(var, name, args);
+                return Ok(Self::SubroutineCall(WithLoc { loc, data }));
+            }
             let is_subroutime_call = matches!(
                 tokens.peek(),
                 Some(Ok(WithLoc {
@@ -782,7 +894,7 @@ impl FromTokensImpl for Term {
             let term = Term::from_tokens(tokens)?;
             return Ok(Self::UnaryOp(op, Box::new(term)));
         }
-        Err(expected("term", tokens.token()?))
+        Err(tokens.unexpected("term"))
     }
 }
 impl FromTokens for Term {}
@@ -792,7 +904,7 @@ impl FromTokensImpl for SubroutineCall {
         matches!(token, Token::Ident(_))
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -807,7 +919,7 @@ impl FromTokens for SubroutineCall {}
 impl SubroutineCall {
     fn from_tokens_with_ident<I, E>(
         ident: WithLoc<Ident>,
-        tokens: &mut Prependable<I>,
+        tokens: &mut TokenStream<I>,
     ) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
@@ -818,15 +930,26 @@ impl SubroutineCall {
         if tokens.try_symbol(Symbol::Dot)?.is_some() {
             prop = Some(tokens.ident()?);
         }
-        tokens.symbol(Symbol::OpenParen)?;
-        let args = ExpressionList::from_tokens(tokens)?;
-        tokens.symbol(Symbol::CloseParen)?;
+        let args = Self::args_from_tokens(tokens)?;
         if let Some(prop) = prop {
             Ok(Self::PropertyCall(ident, prop, args))
         } else {
             Ok(Self::SubroutineCall(ident, args))
         }
     }
+
+    fn args_from_tokens<I, E>(
+        tokens: &mut TokenStream<I>,
+    ) -> Result<WithLoc<ExpressionList>, ParseError<E>>
+    where
+        I: Iterator<Item = Result<WithLoc<Token>, E>>,
+        E: StdError + Send + Sync + 'static,
+    {
+        tokens.symbol(Symbol::OpenParen)?;
+        let args = ExpressionList::from_tokens(tokens)?;
+        tokens.symbol(Symbol::CloseParen)?;
+        Ok(args)
+    }
 }
 
 impl FromTokensImpl for ExpressionList {
@@ -834,7 +957,7 @@ impl FromTokensImpl for ExpressionList {
         Expression::is_start_token(token)
     }
 
-    fn from_tokens_impl<I, E>(tokens: &mut Prependable<I>) -> Result<Self, ParseError<E>>
+    fn from_tokens_impl<I, E>(tokens: &mut TokenStream<I>) -> Result<Self, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -866,7 +989,7 @@ impl FromTokensImpl for BinaryOp {
     }
 }
 impl FromTokens for BinaryOp {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -895,7 +1018,7 @@ impl FromTokensImpl for UnaryOp {
     }
 }
 impl FromTokens for UnaryOp {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
@@ -920,7 +1043,7 @@ impl FromTokensImpl for KeywordConstant {
     }
 }
 impl FromTokens for KeywordConstant {
-    fn from_tokens<I, E>(tokens: &mut Prependable<I>) -> Result<WithLoc<Self>, ParseError<E>>
+    fn from_tokens<I, E>(tokens: &mut TokenStream<I>) -> Result<WithLoc<Self>, ParseError<E>>
     where
         Self: Sized,
         I: Iterator<Item = Result<WithLoc<Token>, E>>,
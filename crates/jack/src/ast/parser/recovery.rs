@@ -0,0 +1,775 @@
+use super::{for_clause, FromTokens, ParseError, ParseOptions, TokenStream, TokensExt};
+use crate::{
+    ast::*,
+    token::{Keyword, Location, Symbol, Token, WithLoc},
+};
+use std::{error::Error as StdError, fmt};
+
+/// Result of parsing a class with error recovery.
+#[derive(Debug)]
+pub struct ParseResult<T, E> {
+    /// The parsed AST, without the declarations and statements containing syntax errors.
+    /// This is `None` if the class header cannot be parsed.
+    pub ast: Option<WithLoc<T>>,
+    pub errors: Vec<ParseError<E>>,
+}
+
+impl<T, E> ParseResult<T, E> {
+    pub fn into_result(self) -> Result<WithLoc<T>, ParseErrors<E>> {
+        match self.ast {
+            Some(ast) if self.errors.is_empty() => Ok(ast),
+            _ => Err(ParseErrors(self.errors)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseErrors<E>(pub Vec<ParseError<E>>);
+
+impl<E> fmt::Display for ParseErrors<E>
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", e)?;
+            let mut source = e.source();
+            while let Some(e) = source {
+                write!(f, ": {}", e)?;
+                source = e.source();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<E> StdError for ParseErrors<E> where E: StdError + Send + Sync + 'static {}
+
+impl Class {
+    /// Parses a class, skipping erroneous declarations and statements to report all syntax errors.
+    ///
+    /// After an error, the parser skips tokens until `;`, `}`, a statement keyword or a
+    /// declaration of a class variable or a subroutine.
+    pub fn from_tokens<I, E>(tokens: I) -> ParseResult<Self, E>
+    where
+        I: IntoIterator<Item = Result<WithLoc<Token>, E>>,
+        E: StdError + Send + Sync + 'static,
+    {
+        Self::from_tokens_with(tokens, ParseOptions::default())
+    }
+
+    /// Parses a class like [`Class::from_tokens`], with the given language options.
+    pub fn from_tokens_with<I, E>(tokens: I, options: ParseOptions) -> ParseResult<Self, E>
+    where
+        I: IntoIterator<Item = Result<WithLoc<Token>, E>>,
+        E: StdError + Send + Sync + 'static,
+    {
+        let mut parser = Parser {
+            tokens: &mut TokenStream::with_options(tokens.into_iter(), options),
+            errors: vec![],
+            aborted: false,
+            loop_depth: 0,
+        };
+        let ast = parser.class();
+        ParseResult {
+            ast,
+            errors: parser.errors,
+        }
+    }
+}
+
+struct Parser<'a, I, E>
+where
+    I: Iterator,
+{
+    tokens: &'a mut TokenStream<I>,
+    errors: Vec<ParseError<E>>,
+    aborted: bool,
+    /// The number of loops enclosing the statements being parsed.
+    loop_depth: usize,
+}
+
+fn is_class_member_start(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Static
+                | Keyword::Field
+                | Keyword::Const
+                | Keyword::Constructor
+                | Keyword::Function
+                | Keyword::Method
+        )
+    )
+}
+
+fn is_statement_start(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Let
+                | Keyword::If
+                | Keyword::While
+                | Keyword::Do
+                | Keyword::Return
+                | Keyword::For
+                | Keyword::Break
+                | Keyword::Continue
+        )
+    )
+}
+
+/// Returns `true` for the identifiers that start extended statements in standard Jack.
+fn is_extension_start(token: &Token) -> bool {
+    matches!(token, Token::Ident(ident) if ["for", "break", "continue"].contains(&ident.as_str()))
+}
+
+fn is_symbol(token: &Token, symbol: Symbol) -> bool {
+    matches!(token, Token::Symbol(sym) if *sym == symbol)
+}
+
+impl<'a, I, E> Parser<'a, I, E>
+where
+    I: Iterator<Item = Result<WithLoc<Token>, E>>,
+    E: StdError + Send + Sync + 'static,
+{
+    fn report(&mut self, e: ParseError<E>) {
+        self.aborted |= e.is_fatal();
+        self.errors.push(e);
+    }
+
+    fn report_with_context(&mut self, context: &str, loc: Option<Location>, e: ParseError<E>) {
+        self.report(ParseError::Context(context.into(), loc, Box::new(e)));
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        match self.tokens.peek() {
+            Some(Ok(token)) => Some(&token.data),
+            _ => None,
+        }
+    }
+
+    fn peek_loc(&mut self) -> Option<Location> {
+        match self.tokens.peek() {
+            Some(Ok(token)) => Some(token.loc),
+            _ => None,
+        }
+    }
+
+    fn parse<T>(&mut self) -> Option<WithLoc<T>>
+    where
+        T: FromTokens,
+    {
+        T::from_tokens(self.tokens).map_err(|e| self.report(e)).ok()
+    }
+
+    fn expect_symbol(&mut self, symbol: Symbol) -> Option<WithLoc<Symbol>> {
+        self.tokens.symbol(symbol).map_err(|e| self.report(e)).ok()
+    }
+
+    /// Skips tokens until `stop` matches a token outside of braces.
+    ///
+    /// Skipping also stops before `}` closing the current block and before a class member
+    /// declaration, which cannot appear inside of subroutines.
+    fn skip_until(&mut self, stop: impl Fn(&Token) -> bool) {
+        let mut depth = 0;
+        loop {
+            let token = match self.tokens.peek() {
+                Some(Ok(token)) => &token.data,
+                Some(Err(_)) => {
+                    let e = self.tokens.token().unwrap_err();
+                    self.report(e);
+                    return;
+                }
+                None => return,
+            };
+            if (depth == 0 && stop(token)) || is_class_member_start(token) {
+                return;
+            }
+            if is_symbol(token, Symbol::OpenBrace) {
+                depth += 1;
+            } else if is_symbol(token, Symbol::CloseBrace) {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+            }
+            self.tokens.next();
+        }
+    }
+
+    /// Skips the rest of an erroneous declaration or statement terminated by `;`.
+    fn skip_statement(&mut self, stop: impl Fn(&Token) -> bool) {
+        self.skip_until(|token| is_symbol(token, Symbol::Semicolon) || stop(token));
+        if self.peek().map(|token| is_symbol(token, Symbol::Semicolon)) == Some(true) {
+            self.tokens.next();
+        }
+    }
+
+    fn class(&mut self) -> Option<WithLoc<Class>> {
+        let loc = self.peek_loc();
+        let header = (|| {
+            self.tokens.keyword(Keyword::Class)?;
+            let name = self.tokens.ident()?;
+            self.tokens.symbol(Symbol::OpenBrace)?;
+            Ok(name)
+        })();
+        let name = match header {
+            Ok(name) => name,
+            Err(e) => {
+                self.report_with_context("class declaration", loc, e);
+                return None;
+            }
+        };
+
+        let mut consts = vec![];
+        let mut vars = vec![];
+        let mut subs = vec![];
+        while !self.aborted {
+            match self.peek() {
+                Some(Token::Keyword(Keyword::Const)) => match self.parse::<ConstDec>() {
+                    Some(constant) => consts.push(constant),
+                    None => self.skip_statement(|_| false),
+                },
+                Some(Token::Ident(ident)) if ident.as_str() == "const" => {
+                    let token = self.tokens.next().unwrap().ok().unwrap();
+                    self.report(ParseError::Extension(token.map(|data| data.to_string())));
+                    self.skip_statement(|_| false);
+                }
+                Some(Token::Keyword(Keyword::Static | Keyword::Field)) => {
+                    match self.parse::<ClassVarDec>() {
+                        Some(var) => vars.push(var),
+                        None => self.skip_statement(|_| false),
+                    }
+                }
+                Some(Token::Keyword(
+                    Keyword::Constructor | Keyword::Function | Keyword::Method,
+                )) => {
+                    subs.extend(self.subroutine());
+                }
+                Some(Token::Symbol(Symbol::CloseBrace)) => {
+                    self.tokens.next();
+                    break;
+                }
+                _ => {
+                    let e = self.tokens.unexpected("class member declaration or `}`");
+                    self.report(e);
+                    self.skip_until(|_| false);
+                }
+            }
+        }
+
+        Some(WithLoc {
+            data: Class {
+                name,
+                consts,
+                vars,
+                subs,
+            },
+            loc: self.tokens.span_from(loc.unwrap()),
+        })
+    }
+
+    fn subroutine(&mut self) -> Option<WithLoc<Subroutine>> {
+        let loc = self.peek_loc();
+        let header = (|| {
+            let kind = SubroutineKind::from_tokens(self.tokens)?;
+            let return_type = ReturnType::from_tokens(self.tokens)?;
+            let name = self.tokens.ident()?;
+            self.tokens.symbol(Symbol::OpenParen)?;
+            let params = ParameterList::from_tokens(self.tokens)?;
+            self.tokens.symbol(Symbol::CloseParen)?;
+            Ok((kind, return_type, name, params))
+        })();
+        let (kind, return_type, name, params) = match header {
+            Ok(header) => header,
+            Err(e) => {
+                self.report_with_context("subroutine declaration", loc, e);
+                self.skip_until(|_| false);
+                return None;
+            }
+        };
+        let body = self.subroutine_body()?;
+        Some(WithLoc {
+            data: Subroutine {
+                kind,
+                return_type,
+                name,
+                params,
+                body,
+            },
+            loc: self.tokens.span_from(loc.unwrap()),
+        })
+    }
+
+    fn subroutine_body(&mut self) -> Option<WithLoc<SubroutineBody>> {
+        let open = match self.expect_symbol(Symbol::OpenBrace) {
+            Some(open) => open,
+            None => {
+                self.skip_until(|_| false);
+                return None;
+            }
+        };
+        let mut vars = vec![];
+        while !self.aborted && self.peek() == Some(&Token::Keyword(Keyword::Var)) {
+            match self.parse::<LocalVarDec>() {
+                Some(var) => vars.push(var),
+                None => self.skip_statement(|token| {
+                    token == &Token::Keyword(Keyword::Var) || is_statement_start(token)
+                }),
+            }
+        }
+        let stmts = self.statements(open.loc);
+        self.expect_symbol(Symbol::CloseBrace);
+        Some(WithLoc {
+            data: SubroutineBody { vars, stmts },
+            loc: self.tokens.span_from(open.loc),
+        })
+    }
+
+    /// Parses statements until `}`, a class member declaration or EOF.
+    fn statements(&mut self, default_loc: Location) -> WithLoc<StatementList> {
+        let loc = self.peek_loc().unwrap_or(default_loc);
+        let mut stmts = vec![];
+        while !self.aborted {
+            let loc = match self.tokens.peek() {
+                Some(Ok(token)) => token.loc,
+                _ => break,
+            };
+            let stmt = match self.peek() {
+                Some(Token::Keyword(Keyword::Let)) => self.parse().map(Statement::Let),
+                Some(Token::Keyword(Keyword::If)) => self.if_statement().map(Statement::If),
+                Some(Token::Keyword(Keyword::While)) => {
+                    self.while_statement().map(Statement::While)
+                }
+                Some(Token::Keyword(Keyword::Do)) => self.parse().map(Statement::Do),
+                Some(Token::Keyword(Keyword::Return)) => self.parse().map(Statement::Return),
+                Some(Token::Keyword(Keyword::For)) => self
+                    .for_statement()
+                    .map(|stmt| Statement::For(Box::new(stmt))),
+                Some(Token::Keyword(Keyword::Break | Keyword::Continue)) => self.jump_statement(),
+                Some(token) if is_extension_start(token) => {
+                    self.extension_statement();
+                    None
+                }
+                Some(token) if is_symbol(token, Symbol::CloseBrace) => break,
+                Some(token) if is_class_member_start(token) => break,
+                _ => {
+                    let e = self.tokens.unexpected("statement");
+                    self.report(e);
+                    None
+                }
+            };
+            match stmt {
+                Some(data) => stmts.push(WithLoc {
+                    data,
+                    loc: self.tokens.span_from(loc),
+                }),
+                None => self.skip_statement(is_statement_start),
+            }
+        }
+        WithLoc {
+            data: StatementList(stmts),
+            loc: self.tokens.span_from(loc),
+        }
+    }
+
+    /// Parses `{ statements }`.
+    fn block(&mut self) -> Option<WithLoc<StatementList>> {
+        let open = self.expect_symbol(Symbol::OpenBrace)?;
+        let stmts = self.statements(open.loc);
+        self.expect_symbol(Symbol::CloseBrace);
+        Some(stmts)
+    }
+
+    /// Parses `keyword ( expression )` at the head of `if` or `while` statements.
+    ///
+    /// On error, the following block is skipped.
+    fn condition(&mut self, keyword: Keyword, context: &str) -> Option<WithLoc<Expression>> {
+        let loc = self.peek_loc();
+        let cond = (|| {
+            self.tokens.keyword(keyword)?;
+            self.tokens.symbol(Symbol::OpenParen)?;
+            let cond = Expression::from_tokens(self.tokens)?;
+            self.tokens.symbol(Symbol::CloseParen)?;
+            Ok(cond)
+        })();
+        match cond {
+            Ok(cond) => Some(cond),
+            Err(e) => {
+                self.report_with_context(context, loc, e);
+                self.skip_header();
+                None
+            }
+        }
+    }
+
+    /// Skips the rest of an erroneous statement header and the following block.
+    fn skip_header(&mut self) {
+        self.skip_until(|token| {
+            is_symbol(token, Symbol::OpenBrace)
+                || is_symbol(token, Symbol::Semicolon)
+                || is_statement_start(token)
+        });
+        if self.peek().map(|token| is_symbol(token, Symbol::OpenBrace)) == Some(true) {
+            self.block();
+        }
+    }
+
+    /// Parses the block of a loop, in which `break` and `continue` are allowed.
+    fn loop_block(&mut self) -> Option<WithLoc<StatementList>> {
+        self.loop_depth += 1;
+        let stmts = self.block();
+        self.loop_depth -= 1;
+        stmts
+    }
+
+    fn if_statement(&mut self) -> Option<WithLoc<IfStatement>> {
+        let loc = self.peek_loc();
+        let cond = self.condition(Keyword::If, "if statement")?;
+        let then_stmts = self.block()?;
+        let mut else_stmts = None;
+        if self.peek() == Some(&Token::Keyword(Keyword::Else)) {
+            let else_loc = self.peek_loc().unwrap();
+            self.tokens.next();
+            if self.peek() == Some(&Token::Keyword(Keyword::If)) {
+                // `else if` is parsed as an `else` block containing only the `if` statement
+                if !self.tokens.options.extensions {
+                    let loc = Location {
+                        end: self.peek_loc().unwrap().end,
+                        ..else_loc
+                    };
+                    self.report(ParseError::Extension(WithLoc {
+                        data: "else if".into(),
+                        loc,
+                    }));
+                }
+                let stmt = self.if_statement()?;
+                else_stmts = Some(WithLoc {
+                    loc: stmt.loc,
+                    data: StatementList(vec![WithLoc {
+                        loc: stmt.loc,
+                        data: Statement::If(stmt),
+                    }]),
+                });
+            } else {
+                else_stmts = Some(self.block()?);
+            }
+        }
+        Some(WithLoc {
+            data: IfStatement {
+                cond,
+                then_stmts,
+                else_stmts,
+            },
+            loc: self.tokens.span_from(loc.unwrap()),
+        })
+    }
+
+    fn while_statement(&mut self) -> Option<WithLoc<WhileStatement>> {
+        let loc = self.peek_loc();
+        let cond = self.condition(Keyword::While, "while statement")?;
+        let stmts = self.loop_block()?;
+        Some(WithLoc {
+            data: WhileStatement { cond, stmts },
+            loc: self.tokens.span_from(loc.unwrap()),
+        })
+    }
+
+    fn for_statement(&mut self) -> Option<WithLoc<ForStatement>> {
+        let loc = self.peek_loc();
+        let header = (|| {
+            self.tokens.keyword(Keyword::For)?;
+            self.tokens.symbol(Symbol::OpenParen)?;
+            let init = for_clause(self.tokens)?;
+            self.tokens.symbol(Symbol::Semicolon)?;
+            let cond = Expression::try_from_tokens(self.tokens)?;
+            self.tokens.symbol(Symbol::Semicolon)?;
+            let step = for_clause(self.tokens)?;
+            self.tokens.symbol(Symbol::CloseParen)?;
+            Ok((init, cond, step))
+        })();
+        let (init, cond, step) = match header {
+            Ok(header) => header,
+            Err(e) => {
+                self.report_with_context("for statement", loc, e);
+                self.skip_header();
+                return None;
+            }
+        };
+        let stmts = self.loop_block()?;
+        Some(WithLoc {
+            data: ForStatement {
+                init,
+                cond,
+                step,
+                stmts,
+            },
+            loc: self.tokens.span_from(loc.unwrap()),
+        })
+    }
+
+    /// Parses `break;` or `continue;`.
+    fn jump_statement(&mut self) -> Option<Statement> {
+        let token = self.tokens.next()?.ok()?;
+        let (keyword, stmt) = match token.data {
+            Token::Keyword(Keyword::Break) => (Keyword::Break, Statement::Break),
+            _ => (Keyword::Continue, Statement::Continue),
+        };
+        if self.loop_depth == 0 {
+            self.report(ParseError::OutsideLoop(WithLoc {
+                data: keyword,
+                loc: token.loc,
+            }));
+            return None;
+        }
+        self.expect_symbol(Symbol::Semicolon)?;
+        Some(stmt)
+    }
+
+    /// Reports an extended statement in standard Jack and skips it.
+    fn extension_statement(&mut self) {
+        let token = self.tokens.next().unwrap().ok().unwrap();
+        let is_for = matches!(&token.data, Token::Ident(ident) if ident.as_str() == "for");
+        self.report(ParseError::Extension(token.map(|data| data.to_string())));
+        if is_for {
+            // the header contains `;` and statement keywords, so skip it up to the block
+            self.skip_until(|token| is_symbol(token, Symbol::OpenBrace));
+            if self.peek().map(|token| is_symbol(token, Symbol::OpenBrace)) == Some(true) {
+                self.loop_block();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::token::Tokens;
+
+    fn parse(source: &str) -> ParseResult<Class, crate::token::ParseTokenError> {
+        Class::from_tokens(Tokens::from_reader(source.as_bytes()))
+    }
+
+    fn error_lines<E>(result: &ParseResult<Class, E>) -> Vec<u32>
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        result
+            .errors
+            .iter()
+            .map(|e| e.loc().unwrap().line_num)
+            .collect()
+    }
+
+    #[test]
+    fn no_errors() {
+        let result = parse(
+            "class Main {
+                field int x;
+                function void main() {
+                    var int i;
+                    if (i) { let i = 1; } else { do Main.f(); }
+                    while (i < 10) { let i = i + 1; }
+                    return;
+                }
+            }",
+        );
+        assert!(result.errors.is_empty());
+        let class = result.into_result().unwrap().data;
+        assert_eq!(class.vars.len(), 1);
+        assert_eq!(class.subs[0].data.body.data.stmts.data.0.len(), 3);
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let result = parse(
+            "class Main {
+                field int x y;
+                static int z;
+                function void main() {
+                    var int i;
+                    let i = ;
+                    let i = 1;
+                    if (i { let i = 2; }
+                    while (i) { let = 3; do Main.f(); }
+                    return;
+                }
+                method void f( {
+                    return;
+                }
+                method int g() {
+                    return 1 +;
+                }
+            }",
+        );
+        assert_eq!(error_lines(&result), [2, 6, 8, 9, 12, 16]);
+        assert!(result.errors.iter().all(|e| !e.is_fatal()));
+
+        let class = result.ast.unwrap().data;
+        assert_eq!(class.vars.len(), 1);
+        let subs = class
+            .subs
+            .iter()
+            .map(|sub| {
+                (
+                    sub.data.name.data.as_str(),
+                    sub.data.body.data.stmts.data.0.len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(subs, [("main", 3), ("g", 0)]);
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let result = parse("class Main { function void main() { let x = 1;");
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].is_fatal());
+        assert_eq!(result.ast.unwrap().data.subs.len(), 1);
+    }
+
+    #[test]
+    fn precedence() {
+        fn render(expr: &Expression) -> String {
+            let term = |term: &Term| match term {
+                Term::IntConstant(n) => n.data.to_string(),
+                Term::Expression(expr) => format!("({})", render(&expr.data)),
+                term => panic!("unexpected term: {:?}", term),
+            };
+            let mut s = term(&expr.term.data);
+            for (op, operand) in &expr.binary_ops {
+                s += &format!(" {} {}", op.data.symbol(), term(&operand.data));
+            }
+            s
+        }
+
+        let source =
+            "class Main { function int f() { return 1 + 2 * 3 * 4 - 5 < 6 & 7 / (8 - 9); } }";
+        let options = ParseOptions {
+            precedence: true,
+            ..ParseOptions::default()
+        };
+        let class = Class::from_tokens_with(Tokens::from_reader(source.as_bytes()), options)
+            .into_result()
+            .unwrap();
+        let stmts = &class.data.subs[0].data.body.data.stmts.data.0;
+        let expr = match &stmts[0].data {
+            Statement::Return(stmt) => &stmt.data.expr.as_ref().unwrap().data,
+            stmt => panic!("unexpected statement: {:?}", stmt),
+        };
+        assert_eq!(render(expr), "((1 + (2 * 3 * 4) - 5) < 6) & (7 / (8 - 9))");
+    }
+
+    #[test]
+    fn extensions() {
+        let source = "class Main {
+            function void main() {
+                var int i;
+                for (let i = 0; i < 10; let i = i + 1) {
+                    if (i = 1) { continue; } else if (i = 2) { break; } else { let i = 3; }
+                }
+                for (;;) { break; }
+                return;
+            }
+        }";
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let tokens = Tokens::from_reader(source.as_bytes()).with_extensions(true);
+        let class = Class::from_tokens_with(tokens, options)
+            .into_result()
+            .unwrap();
+        let stmts = &class.data.subs[0].data.body.data.stmts.data.0;
+        let stmt = match &stmts[0].data {
+            Statement::For(stmt) => &stmt.data,
+            stmt => panic!("unexpected statement: {:?}", stmt),
+        };
+        assert!(stmt.init.is_some() && stmt.cond.is_some() && stmt.step.is_some());
+        let else_stmts = match &stmt.stmts.data.0[0].data {
+            Statement::If(stmt) => &stmt.data.else_stmts.as_ref().unwrap().data.0,
+            stmt => panic!("unexpected statement: {:?}", stmt),
+        };
+        assert!(matches!(
+            &else_stmts[..],
+            [WithLoc {
+                data: Statement::If(_),
+                ..
+            }]
+        ));
+        assert!(matches!(&stmts[1].data, Statement::For(stmt) if stmt.data.cond.is_none()));
+
+        // standard Jack rejects the extensions, and keeps their keywords as identifiers
+        let result = parse(source);
+        assert_eq!(error_lines(&result), [4, 5, 5, 5, 7, 7]);
+        let result = parse(
+            "class Main {
+                function void main() {
+                    var int for, continue;
+                    let for = continue;
+                    while (true) { break; }
+                    return;
+                }
+            }",
+        );
+        assert_eq!(error_lines(&result), [5]);
+
+        let result = Class::from_tokens_with(
+            Tokens::from_reader("class Main { function void f() { break; } }".as_bytes())
+                .with_extensions(true),
+            options,
+        );
+        assert!(matches!(&result.errors[..], [ParseError::OutsideLoop(_)]));
+    }
+
+    #[test]
+    fn constants() {
+        let source = "class Main {
+            const int N = 2 * Other.M;
+            function int main() {
+                return N + Other.M + Other.f();
+            }
+        }";
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let tokens = Tokens::from_reader(source.as_bytes()).with_extensions(true);
+        let class = Class::from_tokens_with(tokens, options)
+            .into_result()
+            .unwrap();
+        assert_eq!(class.data.consts[0].data.name.data.as_str(), "N");
+        let stmts = &class.data.subs[0].data.body.data.stmts.data.0;
+        let expr = match &stmts[0].data {
+            Statement::Return(stmt) => &stmt.data.expr.as_ref().unwrap().data,
+            stmt => panic!("unexpected statement: {:?}", stmt),
+        };
+        assert!(matches!(
+            &expr.binary_ops[..],
+            [
+                (
+                    _,
+                    WithLoc {
+                        data: Term::ClassConstant(..),
+                        ..
+                    }
+                ),
+                (
+                    _,
+                    WithLoc {
+                        data: Term::SubroutineCall(..),
+                        ..
+                    }
+                ),
+            ]
+        ));
+
+        // standard Jack has neither constant declarations nor `Class.NAME` terms
+        let result = parse(source);
+        assert_eq!(error_lines(&result), [2, 4]);
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use crate::{
+    diagnostic::{Diagnostic, ToDiagnostic},
     symbol_table::{
         extend::SymbolTableExtendError, ClassMethodKind, ExternalClassSymbolTable,
         GlobalSymbolTable, InternalClassSymbolTable, SubroutineSymbol, SubroutineSymbolTable,
@@ -8,9 +9,12 @@ use crate::{
     token::Location,
     typed_ast::*,
 };
+use constant::{value_term, ConstEvaluator};
 use either::Either;
 use thiserror::Error;
 
+mod constant;
+
 #[derive(Debug, Error)]
 pub enum ResolveError {
     #[error(transparent)]
@@ -53,6 +57,155 @@ pub enum ResolveError {
     IndexOfNonArray(WithLoc<Ident>),
     #[error("cannot cast `{}` to `{}` at {}", _1.as_str(), _2.as_str(), _0)]
     ImplicitCast(Location, Type, Type),
+    #[error("symbol `{}` is not a class found at {}", _0.data.as_str(), _0.loc)]
+    NotClass(WithLoc<Ident>),
+    #[error("class `{}` does not have a constant `{}` used at {}", _0.as_str(), _1.data.as_str(), _1.loc)]
+    ConstantNotFound(Ident, WithLoc<Ident>),
+    #[error("invalid constant type `{}` found at {}", _0.data.as_str(), _0.loc)]
+    InvalidConstantType(WithLoc<Type>),
+    #[error("expression is not a compile-time constant at {}", _0)]
+    NotConstant(Location),
+    #[error("constant `{}` depends on itself at {}", _0.data.as_str(), _0.loc)]
+    CyclicConstant(WithLoc<Ident>),
+    #[error("constant division cannot be evaluated at {}", _0)]
+    ConstantNotEvaluated(Location),
+}
+
+impl ToDiagnostic for ResolveError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let ident_error = |message: String, ident: &WithLoc<Ident>, label: &str| {
+            Diagnostic::error(message).with_primary(ident.loc, label)
+        };
+        match self {
+            Self::SymbolTable(e) => e.to_diagnostic(),
+            Self::InvalidType(ty) => {
+                Diagnostic::error(format!("invalid type `{}`", ty.data.as_str()))
+                    .with_primary(ty.loc, "not a type")
+            }
+            Self::ReturnVoidValue(loc) => Diagnostic::error("void subroutine returns a value")
+                .with_primary(*loc, "unexpected return value"),
+            Self::NoReturnValue(loc) => Diagnostic::error("non-void subroutine returns no value")
+                .with_primary(*loc, "missing return value"),
+            Self::UndefinedSymbol(ident) => ident_error(
+                format!("undefined symbol `{}`", ident.data.as_str()),
+                ident,
+                "not found in this scope",
+            ),
+            Self::NotVariable(ident) => ident_error(
+                format!("symbol `{}` is not a variable", ident.data.as_str()),
+                ident,
+                "not a variable",
+            ),
+            Self::NotSubroutine(ident) => ident_error(
+                format!("symbol `{}` is not a subroutine", ident.data.as_str()),
+                ident,
+                "not a subroutine",
+            ),
+            Self::NotReceiver(ident) => ident_error(
+                format!("symbol `{}` is not a receiver", ident.data.as_str()),
+                ident,
+                "not a variable or a class",
+            ),
+            Self::ArgumentCountMismatch {
+                subroutine,
+                expected,
+                actual,
+            } => ident_error(
+                format!(
+                    "subroutine `{}` takes {} arguments but {} were supplied",
+                    subroutine.data.as_str(),
+                    expected,
+                    actual
+                ),
+                subroutine,
+                &format!("expected {} arguments", expected),
+            ),
+            Self::VoidSubroutineCall(ident) => ident_error(
+                format!(
+                    "void subroutine `{}` called in expression",
+                    ident.data.as_str()
+                ),
+                ident,
+                "returns no value",
+            ),
+            Self::MethodNotFound(class, ident) => ident_error(
+                format!(
+                    "class `{}` does not have a method `{}`",
+                    class.as_str(),
+                    ident.data.as_str()
+                ),
+                ident,
+                "method not found",
+            ),
+            Self::MethodCallOnPrimitive(ident, ty) => ident_error(
+                format!(
+                    "method `{}` called on primitive type `{}`",
+                    ident.data.as_str(),
+                    ty.as_str()
+                ),
+                ident,
+                "primitive types have no methods",
+            ),
+            Self::ClassMethodNotFound(class, ident) => ident_error(
+                format!(
+                    "class `{}` does not have a class method `{}`",
+                    class.as_str(),
+                    ident.data.as_str()
+                ),
+                ident,
+                "class method not found",
+            ),
+            Self::IndexOfNonArray(ident) => ident_error(
+                format!(
+                    "non-array variable `{}` cannot be indexed",
+                    ident.data.as_str()
+                ),
+                ident,
+                "not an array",
+            ),
+            Self::ImplicitCast(loc, from, to) => Diagnostic::error(format!(
+                "cannot cast `{}` to `{}`",
+                from.as_str(),
+                to.as_str()
+            ))
+            .with_primary(
+                *loc,
+                format!("expected `{}`, found `{}`", to.as_str(), from.as_str()),
+            ),
+            Self::NotClass(ident) => ident_error(
+                format!("symbol `{}` is not a class", ident.data.as_str()),
+                ident,
+                "not a class",
+            ),
+            Self::ConstantNotFound(class, ident) => ident_error(
+                format!(
+                    "class `{}` does not have a constant `{}`",
+                    class.as_str(),
+                    ident.data.as_str()
+                ),
+                ident,
+                "constant not found",
+            ),
+            Self::InvalidConstantType(ty) => {
+                Diagnostic::error(format!("invalid constant type `{}`", ty.data.as_str()))
+                    .with_primary(ty.loc, "expected `int`, `char` or `boolean`")
+            }
+            Self::NotConstant(loc) => {
+                Diagnostic::error("expression is not a compile-time constant")
+                    .with_primary(*loc, "not a constant")
+                    .with_note("constants may only use literals, operators and other constants")
+            }
+            Self::CyclicConstant(ident) => ident_error(
+                format!("constant `{}` depends on itself", ident.data.as_str()),
+                ident,
+                "cyclic reference",
+            ),
+            Self::ConstantNotEvaluated(loc) => {
+                Diagnostic::error("constant division cannot be evaluated")
+                    .with_primary(*loc, "division by zero or by `-32768`")
+            }
+        }
+    }
 }
 
 impl WithLoc<Class> {
@@ -68,8 +221,18 @@ impl WithLoc<Class> {
 
 impl Class {
     fn resolve(&self, sym_tab: &GlobalSymbolTable) -> Result<TypedClass, ResolveError> {
-        let Self { name, vars, subs } = self;
+        let Self {
+            name,
+            consts,
+            vars,
+            subs,
+        } = self;
         let sym_tab = InternalClassSymbolTable::from_class(self, sym_tab)?;
+        // constants are substituted where they are used, but all of them are checked
+        for constant in consts {
+            ConstEvaluator::new(sym_tab.global())
+                .internal_constant(&sym_tab, &constant.data.name)?;
+        }
         let mut static_vars = vec![];
         let mut fields = vec![];
         for var in vars {
@@ -293,6 +456,9 @@ impl ResolveLocal for Statement {
             Statement::While(stmt) => TypedStatement::While(stmt.resolve_local(sym_tab)?),
             Statement::Do(stmt) => TypedStatement::Do(stmt.resolve_local(sym_tab)?),
             Statement::Return(stmt) => TypedStatement::Return(stmt.resolve_local(sym_tab)?),
+            Statement::For(stmt) => TypedStatement::For(Box::new(stmt.resolve_local(sym_tab)?)),
+            Statement::Break => TypedStatement::Break,
+            Statement::Continue => TypedStatement::Continue,
         };
         Ok(stmt)
     }
@@ -389,12 +555,41 @@ impl ResolveLocal for WhileStatement {
     }
 }
 
+impl ResolveLocal for ForStatement {
+    type Output = TypedForStatement;
+
+    fn resolve_local(&self, sym_tab: &SubroutineSymbolTable) -> Result<Self::Output, ResolveError> {
+        let init = self
+            .init
+            .as_ref()
+            .map(|stmt| stmt.resolve_local(sym_tab))
+            .transpose()?;
+        let cond = self
+            .cond
+            .as_ref()
+            .map(|cond| cond.resolve_local(sym_tab)?.implicit_cast(&Type::Boolean))
+            .transpose()?;
+        let step = self
+            .step
+            .as_ref()
+            .map(|stmt| stmt.resolve_local(sym_tab))
+            .transpose()?;
+        let stmts = self.stmts.data.resolve_local(sym_tab)?;
+        Ok(Self::Output {
+            init,
+            cond,
+            step,
+            stmts,
+        })
+    }
+}
+
 impl ResolveLocal for DoStatement {
     type Output = TypedDoStatement;
 
     fn resolve_local(&self, sym_tab: &SubroutineSymbolTable) -> Result<Self::Output, ResolveError> {
         let WithLoc {
-            data: (_, sub_call),
+            data: (return_type, sub_call),
             loc,
         } = self.sub_call.resolve_local(sym_tab)?;
         Ok(Self::Output {
@@ -402,6 +597,7 @@ impl ResolveLocal for DoStatement {
                 data: sub_call,
                 loc,
             },
+            return_type,
         })
     }
 }
@@ -481,6 +677,7 @@ impl ResolveLocal for Term {
         let (ty, term) = match &self {
             Term::IntConstant(n) => (Type::Int, TypedTerm::Int(*n)),
             Term::StringConstant(s) => (Type::string(), TypedTerm::String(s.clone())),
+            Term::CharConstant(ch) => (Type::Char, TypedTerm::Int(ch.map(|ch| ch as u16))),
             Term::KeywordConstant(kw) => match kw.data {
                 KeywordConstant::True => (Type::Boolean, TypedTerm::Bool(kw.map(|_| true))),
                 KeywordConstant::False => (Type::Boolean, TypedTerm::Bool(kw.map(|_| false))),
@@ -491,8 +688,24 @@ impl ResolveLocal for Term {
                 ),
             },
             Term::Variable(name) => {
-                let (ty, var) = resolve_var_direct_access(name, sym_tab)?;
-                (ty, TypedTerm::Var(var))
+                if let Some(Symbol::Constant(_)) = sym_tab.get(&name.data) {
+                    let class = sym_tab.class();
+                    let (ty, n) =
+                        ConstEvaluator::new(class.global()).internal_constant(class, name)?;
+                    (ty.clone(), value_term(&ty, name.loc, n))
+                } else {
+                    let (ty, var) = resolve_var_direct_access(name, sym_tab)?;
+                    (ty, TypedTerm::Var(var))
+                }
+            }
+            Term::ClassConstant(class, name) => {
+                let table = sym_tab
+                    .get_sym(class)?
+                    .to_class()
+                    .ok_or_else(|| ResolveError::NotClass(class.clone()))?;
+                let (ty, n) =
+                    ConstEvaluator::new(sym_tab.class().global()).class_constant(table, name)?;
+                (ty.clone(), value_term(&ty, class.loc.to(name.loc), n))
             }
             Term::Index(name, index) => {
                 let (ty, var, index) = resolve_var_index_access(name, index, sym_tab)?;
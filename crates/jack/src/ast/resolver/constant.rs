@@ -0,0 +1,322 @@
+use super::*;
+use crate::{
+    control_flow_graph::fold::{constant_term, evaluate_binary, evaluate_unary},
+    symbol_table::Constant,
+};
+
+/// The class whose constants are visible without the class name.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    Internal(&'a InternalClassSymbolTable<'a>),
+    External(&'a ExternalClassSymbolTable),
+}
+
+impl<'a> Scope<'a> {
+    fn class_name(self) -> &'a Ident {
+        match self {
+            Self::Internal(table) => &table.class_name().data,
+            Self::External(table) => &table.class_name().data,
+        }
+    }
+
+    fn constant(self, name: &Ident) -> Option<&'a Constant> {
+        match self {
+            Self::Internal(table) => table.constant(name),
+            Self::External(table) => table.constant(name),
+        }
+    }
+}
+
+/// Evaluates constants with the 16-bit arithmetic of the VM. Booleans are `0` and `-1`.
+pub(super) struct ConstEvaluator<'a> {
+    global: &'a GlobalSymbolTable,
+    /// The class and name of the constants being evaluated, to detect cycles.
+    visiting: Vec<(&'a Ident, &'a Ident)>,
+}
+
+impl<'a> ConstEvaluator<'a> {
+    pub(super) fn new(global: &'a GlobalSymbolTable) -> Self {
+        Self {
+            global,
+            visiting: vec![],
+        }
+    }
+
+    /// Evaluates the constant `name` declared in the class being resolved.
+    pub(super) fn internal_constant(
+        &mut self,
+        class: &'a InternalClassSymbolTable<'a>,
+        name: &WithLoc<Ident>,
+    ) -> Result<(Type, i16), ResolveError> {
+        let constant = class
+            .constant(&name.data)
+            .ok_or_else(|| ResolveError::UndefinedSymbol(name.clone()))?;
+        self.constant(Scope::Internal(class), name, constant)
+    }
+
+    /// Evaluates the constant `Class.NAME`.
+    pub(super) fn class_constant(
+        &mut self,
+        class: &'a ExternalClassSymbolTable,
+        name: &WithLoc<Ident>,
+    ) -> Result<(Type, i16), ResolveError> {
+        let constant = class.constant(&name.data).ok_or_else(|| {
+            ResolveError::ConstantNotFound(class.class_name().data.clone(), name.clone())
+        })?;
+        self.constant(Scope::External(class), name, constant)
+    }
+
+    fn constant(
+        &mut self,
+        scope: Scope<'a>,
+        name: &WithLoc<Ident>,
+        constant: &'a Constant,
+    ) -> Result<(Type, i16), ResolveError> {
+        let key = (scope.class_name(), &constant.name.data);
+        if self.visiting.contains(&key) {
+            return Err(ResolveError::CyclicConstant(name.clone()));
+        }
+        self.visiting.push(key);
+        let value = self.declared_value(scope, constant);
+        self.visiting.pop();
+        value
+    }
+
+    fn declared_value(
+        &mut self,
+        scope: Scope<'a>,
+        constant: &'a Constant,
+    ) -> Result<(Type, i16), ResolveError> {
+        let ty = &constant.ty;
+        if !matches!(ty.data, Type::Int | Type::Char | Type::Boolean) {
+            return Err(ResolveError::InvalidConstantType(ty.clone()));
+        }
+        let (expr_ty, n) = self.expression(scope, &constant.expr)?;
+        cast(constant.expr.loc, &expr_ty, &ty.data)?;
+        Ok((ty.data.clone(), n))
+    }
+
+    fn expression(
+        &mut self,
+        scope: Scope<'a>,
+        expr: &'a WithLoc<Expression>,
+    ) -> Result<(Type, i16), ResolveError> {
+        let Expression { term, binary_ops } = &expr.data;
+        let (mut ty, mut n) = self.term(scope, term)?;
+        for (op, rhs) in binary_ops {
+            let (rhs_ty, r) = self.term(scope, rhs)?;
+            let (operand_ty, result_ty) = op.data.get_ty(&ty, &rhs_ty);
+            cast(term.loc, &ty, &operand_ty)?;
+            cast(rhs.loc, &rhs_ty, &operand_ty)?;
+            n = evaluate_binary(op.data, n, r).ok_or(ResolveError::ConstantNotEvaluated(op.loc))?;
+            ty = result_ty;
+        }
+        Ok((ty, n))
+    }
+
+    fn term(
+        &mut self,
+        scope: Scope<'a>,
+        term: &'a WithLoc<Term>,
+    ) -> Result<(Type, i16), ResolveError> {
+        match &term.data {
+            Term::IntConstant(n) => Ok((Type::Int, n.data as i16)),
+            Term::CharConstant(ch) => Ok((Type::Char, ch.data as i16)),
+            Term::KeywordConstant(kw) => match kw.data {
+                KeywordConstant::True => Ok((Type::Boolean, -1)),
+                KeywordConstant::False => Ok((Type::Boolean, 0)),
+                KeywordConstant::Null | KeywordConstant::This => {
+                    Err(ResolveError::NotConstant(term.loc))
+                }
+            },
+            Term::Variable(name) => {
+                let constant = scope
+                    .constant(&name.data)
+                    .ok_or(ResolveError::NotConstant(term.loc))?;
+                self.constant(scope, name, constant)
+            }
+            Term::ClassConstant(class, name) => {
+                let table = self
+                    .global
+                    .get(&class.data)
+                    .ok_or_else(|| ResolveError::UndefinedSymbol(class.clone()))?
+                    .to_class()
+                    .ok_or_else(|| ResolveError::NotClass(class.clone()))?;
+                self.class_constant(table, name)
+            }
+            Term::Expression(expr) => self.expression(scope, expr),
+            Term::UnaryOp(op, operand) => {
+                let (ty, n) = self.term(scope, operand)?;
+                let (operand_ty, result_ty) = op.data.get_ty(&ty);
+                cast(operand.loc, &ty, &operand_ty)?;
+                Ok((result_ty, evaluate_unary(op.data, n)))
+            }
+            Term::StringConstant(_) | Term::Index(..) | Term::SubroutineCall(_) => {
+                Err(ResolveError::NotConstant(term.loc))
+            }
+        }
+    }
+}
+
+impl GlobalSymbolTable {
+    /// Evaluates a constant of the class as other classes see it.
+    pub(crate) fn constant_value(
+        &self,
+        class: &ExternalClassSymbolTable,
+        constant: &Constant,
+    ) -> Result<(Type, i16), ResolveError> {
+        ConstEvaluator::new(self).class_constant(class, &constant.name)
+    }
+}
+
+/// Checks the implicit casts allowed between the types of constants.
+fn cast(loc: Location, from: &Type, to: &Type) -> Result<(), ResolveError> {
+    match (from, to) {
+        (Type::Int, Type::Char) | (Type::Char, Type::Int) => Ok(()),
+        (a, b) if a == b => Ok(()),
+        _ => Err(ResolveError::ImplicitCast(loc, from.clone(), to.clone())),
+    }
+}
+
+/// Builds the term substituted for a constant used at `loc`.
+pub(super) fn value_term(ty: &Type, loc: Location, n: i16) -> TypedTerm {
+    constant_term(ty, loc, n).unwrap_or_else(|| {
+        // `-32768` is `~32767`, as `32768` is not an integer constant
+        let operand = TypedExpression {
+            ty: Type::Int,
+            term: Box::new(TypedTerm::Int(WithLoc { data: 32767, loc })),
+        };
+        TypedTerm::UnaryOp(
+            WithLoc {
+                data: UnaryOp::Not,
+                loc,
+            },
+            WithLoc { data: operand, loc },
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::ParseOptions, token::Tokens};
+
+    fn resolve(sources: &[(&str, &str)]) -> Result<(), ResolveError> {
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let classes = sources
+            .iter()
+            .map(|(name, source)| {
+                let tokens = Tokens::from_reader(source.as_bytes()).with_extensions(true);
+                let ast = Class::from_tokens_with(tokens, options)
+                    .into_result()
+                    .unwrap();
+                (format!("{}.jack", name), ast)
+            })
+            .collect::<Vec<_>>();
+        let mut table = GlobalSymbolTable::with_builtin();
+        for (path, ast) in &classes {
+            table.extend_with_class(path, &ast.data)?;
+        }
+        for (_, ast) in &classes {
+            ast.resolve(&table)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn class_interface() {
+        let interface = |other: &str| {
+            let options = ParseOptions {
+                extensions: true,
+                ..ParseOptions::default()
+            };
+            let mut table = GlobalSymbolTable::with_builtin();
+            for (name, source) in [
+                (
+                    "Main",
+                    "class Main { const int N = Other.M * 2; function void f(int x) {} }",
+                ),
+                ("Other", other),
+            ] {
+                let tokens = Tokens::from_reader(source.as_bytes()).with_extensions(true);
+                let ast = Class::from_tokens_with(tokens, options)
+                    .into_result()
+                    .unwrap();
+                table
+                    .extend_with_class(format!("{}.jack", name), &ast.data)
+                    .unwrap();
+            }
+            table.class_interface("Main").unwrap()
+        };
+        assert_eq!(
+            interface("class Other { const int M = 3; }"),
+            "function void Main.f(int x)\nconst Main.N = int 6"
+        );
+        assert_eq!(
+            interface("class Other { const int M = 3; function void g() {} }"),
+            interface("class Other { const int M = 3; }")
+        );
+        assert_ne!(
+            interface("class Other { const int M = 4; }"),
+            interface("class Other { const int M = 3; }")
+        );
+    }
+
+    #[test]
+    fn constant_errors() {
+        let other = ("Other", "class Other { const int M = Main.N + 1; }");
+        let main = ("Main", "class Main { const int N = Other.M; }");
+        assert!(matches!(
+            resolve(&[main, other]),
+            Err(ResolveError::CyclicConstant(name)) if name.data.as_str() == "N"
+        ));
+
+        let main = ("Main", "class Main { const int N = 1; static int N; }");
+        assert!(matches!(
+            resolve(&[main]),
+            Err(ResolveError::SymbolTable(
+                SymbolTableExtendError::DuplicateClassSymbol(..)
+            ))
+        ));
+
+        let main = ("Main", "class Main { static int x; const int N = x + 1; }");
+        assert!(matches!(
+            resolve(&[main]),
+            Err(ResolveError::NotConstant(_))
+        ));
+
+        let main = ("Main", "class Main { const boolean B = 1; }");
+        assert!(matches!(
+            resolve(&[main]),
+            Err(ResolveError::ImplicitCast(..))
+        ));
+
+        let main = ("Main", "class Main { const int N = 1 / 0; }");
+        assert!(matches!(
+            resolve(&[main]),
+            Err(ResolveError::ConstantNotEvaluated(_))
+        ));
+
+        let main = (
+            "Main",
+            "class Main { const int N = 1; function void f() { let N = 2; return; } }",
+        );
+        assert!(matches!(
+            resolve(&[main]),
+            Err(ResolveError::NotVariable(_))
+        ));
+
+        let other = ("Other", "class Other { const int M = 1; }");
+        let main = (
+            "Main",
+            "class Main { function int f() { return Other.K; } }",
+        );
+        assert!(matches!(
+            resolve(&[main, other]),
+            Err(ResolveError::ConstantNotFound(..))
+        ));
+    }
+}
@@ -3,13 +3,16 @@ use crate::{
     token::{Ident, WithLoc},
     typed_ast::{TypedDoStatement, TypedExpression, TypedLetStatement, Variable},
 };
+use serde::{Serialize, Serializer};
 use std::{collections::HashMap, fmt};
 
 mod codegen;
+mod dot;
+pub(crate) mod fold;
 mod optimizer;
 mod update;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CfgClass {
     pub name: WithLoc<Ident>,
     pub static_vars: Vec<WithLoc<Variable>>,
@@ -17,7 +20,7 @@ pub struct CfgClass {
     pub subs: Vec<WithLoc<CfgSubroutine>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CfgSubroutine {
     pub name: WithLoc<Ident>,
     pub kind: WithLoc<SubroutineKind>,
@@ -25,6 +28,7 @@ pub struct CfgSubroutine {
     pub params: Vec<WithLoc<Variable>>,
     pub vars: Vec<WithLoc<Variable>>,
     pub entry_id: BbId,
+    #[serde(skip)]
     pub block_index_map: HashMap<BbId, usize>,
     pub blocks: Vec<WithLoc<BasicBlock>>,
 }
@@ -41,7 +45,16 @@ impl fmt::Display for BbId {
     }
 }
 
-#[derive(Debug, Clone)]
+impl Serialize for BbId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct BasicBlock {
     pub id: BbId,
     pub src_ids: Vec<BbId>,
@@ -49,13 +62,13 @@ pub struct BasicBlock {
     pub exit: Exit,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum CfgStatement {
     Let(WithLoc<TypedLetStatement>),
     Do(WithLoc<TypedDoStatement>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Exit {
     Return(Option<WithLoc<TypedExpression>>),
     Goto(BbId),
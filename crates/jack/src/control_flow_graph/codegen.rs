@@ -172,7 +172,7 @@ impl EmitVmBb for TypedLetStatement {
 
 impl EmitVmBb for TypedDoStatement {
     fn emit_vm(&self, p: &EmitVmBbParam, e: &mut Emitter) {
-        let Self { sub_call } = self;
+        let Self { sub_call, .. } = self;
         sub_call.emit_vm(p, e);
         e.emit_pop(Segment::Temp, 0);
     }
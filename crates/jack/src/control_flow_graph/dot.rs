@@ -0,0 +1,209 @@
+use super::{BasicBlock, BbId, CfgClass, CfgStatement, CfgSubroutine, Exit};
+use crate::{
+    ast::UnaryOp,
+    typed_ast::{TypedExpression, TypedLetStatement, TypedSubroutineCall, TypedTerm},
+};
+use std::io::{self, Write};
+
+impl CfgClass {
+    /// Writes the control-flow graphs of the subroutines in Graphviz DOT format, one cluster per
+    /// subroutine.
+    pub fn write_dot(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "digraph {} {{", quote(self.name.data.as_str()))?;
+        writeln!(writer, "  node [shape=box, fontname=monospace];")?;
+        for (sub_index, sub) in self.subs.iter().enumerate() {
+            sub.data
+                .write_dot(self.name.data.as_str(), sub_index, &mut writer)?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+impl CfgSubroutine {
+    fn write_dot(
+        &self,
+        class_name: &str,
+        sub_index: usize,
+        writer: &mut impl Write,
+    ) -> io::Result<()> {
+        let node_id = |id: BbId| format!("s{}_{}", sub_index, id);
+        writeln!(writer, "  subgraph cluster_{} {{", sub_index)?;
+        writeln!(
+            writer,
+            "    label={};",
+            quote(&format!("{}.{}", class_name, self.name.data.as_str()))
+        )?;
+        for block in &self.blocks {
+            let block = &block.data;
+            write!(
+                writer,
+                "    {} [label=\"{}\"",
+                node_id(block.id),
+                block_label(block)
+            )?;
+            if block.id == self.entry_id {
+                write!(writer, ", peripheries=2")?;
+            }
+            match block.exit {
+                Exit::Return(_) => write!(writer, ", style=filled, fillcolor=lightgray")?,
+                Exit::Unreachable => write!(writer, ", style=dashed")?,
+                Exit::Goto(_) | Exit::If(..) => {}
+            }
+            writeln!(writer, "];")?;
+        }
+        for block in &self.blocks {
+            let block = &block.data;
+            match &block.exit {
+                Exit::Goto(id) => {
+                    writeln!(writer, "    {} -> {};", node_id(block.id), node_id(*id))?
+                }
+                Exit::If(_, then_id, else_id) => {
+                    for (id, label) in [(then_id, "true"), (else_id, "false")] {
+                        writeln!(
+                            writer,
+                            "    {} -> {} [label={}];",
+                            node_id(block.id),
+                            node_id(*id),
+                            quote(label)
+                        )?;
+                    }
+                }
+                Exit::Return(_) | Exit::Unreachable => {}
+            }
+        }
+        writeln!(writer, "  }}")?;
+        Ok(())
+    }
+}
+
+/// Returns the label of the block: its id, statements and exit, each on a left-aligned line.
+fn block_label(block: &BasicBlock) -> String {
+    let stmts = block.stmts.iter().map(|stmt| match stmt {
+        CfgStatement::Let(stmt) => let_statement(&stmt.data),
+        CfgStatement::Do(stmt) => format!("do {}", sub_call(&stmt.data.sub_call.data)),
+    });
+    let exit = match &block.exit {
+        Exit::Return(Some(expr)) => Some(format!("return {}", expression(&expr.data))),
+        Exit::Return(None) => Some("return".to_owned()),
+        Exit::If(cond, _, _) => Some(format!("if {}", expression(&cond.data))),
+        Exit::Unreachable => Some("unreachable".to_owned()),
+        Exit::Goto(_) => None,
+    };
+    std::iter::once(format!("{}:", block.id))
+        .chain(stmts)
+        .chain(exit)
+        .map(|line| format!("{}\\l", escape(&line)))
+        .collect()
+}
+
+fn let_statement(stmt: &TypedLetStatement) -> String {
+    let index = stmt
+        .target_index
+        .as_ref()
+        .map(|index| format!("[{}]", expression(&index.data)))
+        .unwrap_or_default();
+    format!(
+        "let {}{} = {}",
+        stmt.target.name().data.as_str(),
+        index,
+        expression(&stmt.expr.data)
+    )
+}
+
+fn expression(expr: &TypedExpression) -> String {
+    match &*expr.term {
+        TypedTerm::Int(n) => n.data.to_string(),
+        TypedTerm::String(s) => format!("{:?}", s.data),
+        TypedTerm::Bool(b) => b.data.to_string(),
+        TypedTerm::Null => "null".to_owned(),
+        TypedTerm::This => "this".to_owned(),
+        TypedTerm::Var(var) => var.name().data.as_str().to_owned(),
+        TypedTerm::Index(var, index) => {
+            format!("{}[{}]", var.name().data.as_str(), expression(&index.data))
+        }
+        TypedTerm::SubroutineCall(call) => sub_call(&call.data),
+        TypedTerm::UnaryOp(op, operand) => {
+            let op = match op.data {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "~",
+            };
+            format!("{}{}", op, operand_expression(&operand.data))
+        }
+        TypedTerm::BinaryOp(op, lhs, rhs) => format!(
+            "{} {} {}",
+            operand_expression(&lhs.data),
+            op.data.symbol(),
+            operand_expression(&rhs.data)
+        ),
+    }
+}
+
+/// Renders an operand, in parentheses if it is itself an operation.
+fn operand_expression(expr: &TypedExpression) -> String {
+    match &*expr.term {
+        TypedTerm::BinaryOp(..) => format!("({})", expression(expr)),
+        _ => expression(expr),
+    }
+}
+
+fn sub_call(call: &TypedSubroutineCall) -> String {
+    let (receiver, name, args) = match call {
+        TypedSubroutineCall::Method(var, name, args) => {
+            (var.as_ref().map(|var| var.name().data.as_str()), name, args)
+        }
+        TypedSubroutineCall::Function(class, name, args)
+        | TypedSubroutineCall::Constructor(class, name, args) => {
+            (class.as_ref().map(|class| class.data.as_str()), name, args)
+        }
+    };
+    let args = args
+        .iter()
+        .map(|arg| expression(&arg.data))
+        .collect::<Vec<_>>()
+        .join(", ");
+    match receiver {
+        Some(receiver) => format!("{}.{}({})", receiver, name.data.as_str(), args),
+        None => format!("{}({})", name.data.as_str(), args),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::compile;
+
+    #[test]
+    fn write_dot() {
+        let source = "\
+class Main {
+    function int f(int x) {
+        if (x < 0) {
+            let x = -x;
+        }
+        return x * (x + 1);
+    }
+}
+";
+        let cfg = compile(source).cfg;
+        let mut dot = vec![];
+        cfg.data.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph \"Main\" {\n"));
+        assert!(dot.contains("label=\"Main.f\";"));
+        assert!(dot.contains("if x < 0\\l\", peripheries=2];"));
+        assert!(dot.contains("let x = -x\\l"));
+        assert!(dot.contains("return x * (x + 1)\\l\", style=filled, fillcolor=lightgray];"));
+        assert!(dot.contains(" [label=\"true\"];"));
+        assert!(dot.contains(" [label=\"false\"];"));
+    }
+}
@@ -0,0 +1,412 @@
+use super::*;
+use crate::{
+    ast::{BinaryOp, Type, UnaryOp},
+    token::Location,
+    typed_ast::{TypedSubroutineCall, TypedTerm},
+};
+use std::mem;
+
+/// Multiplications of a variable by a power of two up to `2^MAX_DOUBLINGS` are rewritten into
+/// additions instead of calling `Math.multiply`.
+const MAX_DOUBLINGS: u32 = 3;
+
+/// Simplifies the expressions of the blocks, and replaces conditional exits whose condition is
+/// constant with an unconditional jump.
+pub(super) fn fold_constants(blocks: &mut [WithLoc<BasicBlock>]) {
+    for block in blocks {
+        for stmt in &mut block.data.stmts {
+            match stmt {
+                CfgStatement::Let(stmt) => {
+                    if let Some(index) = &mut stmt.data.target_index {
+                        fold_expr(index);
+                    }
+                    fold_expr(&mut stmt.data.expr);
+                }
+                CfgStatement::Do(stmt) => fold_call(&mut stmt.data.sub_call.data),
+            }
+        }
+
+        let dest = match &mut block.data.exit {
+            Exit::Return(Some(expr)) => {
+                fold_expr(expr);
+                None
+            }
+            Exit::If(cond, then_id, else_id) => {
+                fold_expr(cond);
+                constant(&cond.data).map(|n| if n != 0 { *then_id } else { *else_id })
+            }
+            Exit::Return(None) | Exit::Goto(_) | Exit::Unreachable => None,
+        };
+        if let Some(dest) = dest {
+            block.data.exit = Exit::Goto(dest);
+        }
+    }
+}
+
+/// Removes the negation of comparisons branching to the next block by swapping the branch
+/// targets, so that `if (~(a < b))` falls through instead of jumping over the `then` block.
+pub(super) fn invert_branches(blocks: &mut [WithLoc<BasicBlock>]) {
+    for i in 0..blocks.len() {
+        let next_id = blocks.get(i + 1).map(|b| b.data.id);
+        if let Exit::If(cond, then_id, else_id) = &mut blocks[i].data.exit {
+            if Some(*then_id) != next_id {
+                continue;
+            }
+            let negated = match &*cond.data.term {
+                TypedTerm::UnaryOp(op, operand) => {
+                    matches!(op.data, UnaryOp::Not) && is_comparison(&operand.data)
+                }
+                _ => false,
+            };
+            if negated {
+                if let TypedTerm::UnaryOp(_, operand) =
+                    mem::replace(&mut *cond.data.term, TypedTerm::Null)
+                {
+                    cond.data = operand.data;
+                }
+                mem::swap(then_id, else_id);
+            }
+        }
+    }
+}
+
+fn fold_call(call: &mut TypedSubroutineCall) {
+    let args = match call {
+        TypedSubroutineCall::Method(_, _, args)
+        | TypedSubroutineCall::Function(_, _, args)
+        | TypedSubroutineCall::Constructor(_, _, args) => args,
+    };
+    for arg in args {
+        fold_expr(arg);
+    }
+}
+
+fn fold_expr(expr: &mut WithLoc<TypedExpression>) {
+    match &mut *expr.data.term {
+        TypedTerm::Index(_, index) => fold_expr(index),
+        TypedTerm::SubroutineCall(call) => fold_call(&mut call.data),
+        TypedTerm::UnaryOp(_, operand) => fold_expr(operand),
+        TypedTerm::BinaryOp(_, lhs, rhs) => {
+            fold_expr(lhs);
+            fold_expr(rhs);
+        }
+        TypedTerm::Int(_)
+        | TypedTerm::String(_)
+        | TypedTerm::Bool(_)
+        | TypedTerm::Null
+        | TypedTerm::This
+        | TypedTerm::Var(_) => {}
+    }
+
+    let term = mem::replace(&mut *expr.data.term, TypedTerm::Null);
+    *expr.data.term = match term {
+        TypedTerm::UnaryOp(op, operand) => simplify_unary(&expr.data.ty, expr.loc, op, operand),
+        TypedTerm::BinaryOp(op, lhs, rhs) => simplify_binary(&expr.data.ty, expr.loc, op, lhs, rhs),
+        term => term,
+    };
+}
+
+fn simplify_unary(
+    ty: &Type,
+    loc: Location,
+    op: WithLoc<UnaryOp>,
+    operand: WithLoc<TypedExpression>,
+) -> TypedTerm {
+    if let Some(term) = constant(&operand.data)
+        .map(|n| evaluate_unary(op.data, n))
+        .and_then(|n| constant_term(ty, loc, n))
+    {
+        return term;
+    }
+
+    // `--x` and `~~x`
+    let is_double = match &*operand.data.term {
+        TypedTerm::UnaryOp(inner, _) => matches!(
+            (op.data, inner.data),
+            (UnaryOp::Neg, UnaryOp::Neg) | (UnaryOp::Not, UnaryOp::Not)
+        ),
+        _ => false,
+    };
+    match *operand.data.term {
+        TypedTerm::UnaryOp(_, inner) if is_double => *inner.data.term,
+        term => TypedTerm::UnaryOp(
+            op,
+            WithLoc {
+                data: TypedExpression {
+                    ty: operand.data.ty,
+                    term: Box::new(term),
+                },
+                loc: operand.loc,
+            },
+        ),
+    }
+}
+
+fn simplify_binary(
+    ty: &Type,
+    loc: Location,
+    op: WithLoc<BinaryOp>,
+    lhs: WithLoc<TypedExpression>,
+    rhs: WithLoc<TypedExpression>,
+) -> TypedTerm {
+    let (l, r) = (constant(&lhs.data), constant(&rhs.data));
+    if let Some(term) = l
+        .zip(r)
+        .and_then(|(l, r)| evaluate_binary(op.data, l, r))
+        .and_then(|n| constant_term(ty, loc, n))
+    {
+        return term;
+    }
+
+    let neg = |operand: WithLoc<TypedExpression>| {
+        let op = WithLoc {
+            data: UnaryOp::Neg,
+            loc: op.loc,
+        };
+        simplify_unary(ty, loc, op, operand)
+    };
+    match (op.data, l, r) {
+        // identities
+        (BinaryOp::Add | BinaryOp::Or, Some(0), _)
+        | (BinaryOp::Mul, Some(1), _)
+        | (BinaryOp::And, Some(-1), _) => *rhs.data.term,
+        (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or, _, Some(0))
+        | (BinaryOp::Mul | BinaryOp::Div, _, Some(1))
+        | (BinaryOp::And, _, Some(-1)) => *lhs.data.term,
+        (BinaryOp::Sub, Some(0), _) | (BinaryOp::Mul, Some(-1), _) => neg(rhs),
+        (BinaryOp::Mul, _, Some(-1)) => neg(lhs),
+
+        // absorbing elements, as long as the discarded operand has no side effect
+        (BinaryOp::Mul | BinaryOp::And, Some(0), _) if is_pure(&rhs.data) => {
+            constant_term(ty, loc, 0).unwrap()
+        }
+        (BinaryOp::Mul | BinaryOp::And, _, Some(0)) if is_pure(&lhs.data) => {
+            constant_term(ty, loc, 0).unwrap()
+        }
+        (BinaryOp::Or, Some(-1), _) if is_pure(&rhs.data) => constant_term(ty, loc, -1).unwrap(),
+        (BinaryOp::Or, _, Some(-1)) if is_pure(&lhs.data) => constant_term(ty, loc, -1).unwrap(),
+
+        // `x * 2^n` as `n` doublings of `x`
+        (BinaryOp::Mul, Some(n), _) if doublings(n).is_some() && is_var(&rhs.data) => {
+            double(rhs, op.loc, doublings(n).unwrap())
+        }
+        (BinaryOp::Mul, _, Some(n)) if doublings(n).is_some() && is_var(&lhs.data) => {
+            double(lhs, op.loc, doublings(n).unwrap())
+        }
+
+        _ => TypedTerm::BinaryOp(op, lhs, rhs),
+    }
+}
+
+/// Returns the value of a constant expression, with booleans as `0` and `-1`.
+fn constant(expr: &TypedExpression) -> Option<i16> {
+    match &*expr.term {
+        TypedTerm::Int(n) => Some(n.data as i16),
+        TypedTerm::Bool(b) => Some(if b.data { -1 } else { 0 }),
+        TypedTerm::Null => Some(0),
+        TypedTerm::UnaryOp(op, operand) => {
+            constant(&operand.data).map(|n| evaluate_unary(op.data, n))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn evaluate_unary(op: UnaryOp, n: i16) -> i16 {
+    match op {
+        UnaryOp::Neg => n.wrapping_neg(),
+        UnaryOp::Not => !n,
+    }
+}
+
+/// Evaluates the operator the way the VM and `Math` do at runtime, with 16-bit wrapping
+/// arithmetic. Divisions that are errors or depend on `Math.divide` edge cases are not evaluated.
+pub(crate) fn evaluate_binary(op: BinaryOp, l: i16, r: i16) -> Option<i16> {
+    let flag = |b: bool| if b { -1 } else { 0 };
+    let n = match op {
+        BinaryOp::Add => l.wrapping_add(r),
+        BinaryOp::Sub => l.wrapping_sub(r),
+        BinaryOp::Mul => l.wrapping_mul(r),
+        BinaryOp::Div => {
+            if r == 0 || l == i16::MIN || r == i16::MIN {
+                return None;
+            }
+            l / r
+        }
+        BinaryOp::And => l & r,
+        BinaryOp::Or => l | r,
+        // `lt` and `gt` test the sign of the wrapped difference
+        BinaryOp::Lt => flag(l.wrapping_sub(r) < 0),
+        BinaryOp::Gt => flag(l.wrapping_sub(r) > 0),
+        BinaryOp::Eq => flag(l == r),
+    };
+    Some(n)
+}
+
+/// Builds the term pushing `n`. `-32768` has no such term, as `32768` is not an integer constant.
+pub(crate) fn constant_term(ty: &Type, loc: Location, n: i16) -> Option<TypedTerm> {
+    if *ty == Type::Boolean && (n == 0 || n == -1) {
+        return Some(TypedTerm::Bool(WithLoc { data: n != 0, loc }));
+    }
+    if n >= 0 {
+        return Some(TypedTerm::Int(WithLoc {
+            data: n as u16,
+            loc,
+        }));
+    }
+    if n == i16::MIN {
+        return None;
+    }
+    let operand = TypedExpression {
+        ty: Type::Int,
+        term: Box::new(TypedTerm::Int(WithLoc {
+            data: -n as u16,
+            loc,
+        })),
+    };
+    Some(TypedTerm::UnaryOp(
+        WithLoc {
+            data: UnaryOp::Neg,
+            loc,
+        },
+        WithLoc { data: operand, loc },
+    ))
+}
+
+/// Returns `k` if `n` is `2^k`, with `1 <= k <= MAX_DOUBLINGS`.
+fn doublings(n: i16) -> Option<u32> {
+    let k = n.trailing_zeros();
+    (n > 1 && n.count_ones() == 1 && k <= MAX_DOUBLINGS).then_some(k)
+}
+
+fn double(expr: WithLoc<TypedExpression>, op_loc: Location, count: u32) -> TypedTerm {
+    let mut sum = expr;
+    for _ in 0..count {
+        let op = WithLoc {
+            data: BinaryOp::Add,
+            loc: op_loc,
+        };
+        let loc = sum.loc;
+        sum = WithLoc {
+            data: TypedExpression {
+                ty: Type::Int,
+                term: Box::new(TypedTerm::BinaryOp(op, sum.clone(), sum)),
+            },
+            loc,
+        };
+    }
+    *sum.data.term
+}
+
+fn is_var(expr: &TypedExpression) -> bool {
+    matches!(&*expr.term, TypedTerm::Var(_))
+}
+
+fn is_comparison(expr: &TypedExpression) -> bool {
+    matches!(
+        &*expr.term,
+        TypedTerm::BinaryOp(op, _, _)
+            if matches!(op.data, BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq)
+    )
+}
+
+/// Returns `true` if evaluating the expression has no side effect. Divisions may call
+/// `Sys.error`, so they are not pure.
+fn is_pure(expr: &TypedExpression) -> bool {
+    match &*expr.term {
+        TypedTerm::Int(_)
+        | TypedTerm::Bool(_)
+        | TypedTerm::Null
+        | TypedTerm::This
+        | TypedTerm::Var(_) => true,
+        TypedTerm::String(_) | TypedTerm::SubroutineCall(_) => false,
+        TypedTerm::Index(_, index) => is_pure(&index.data),
+        TypedTerm::UnaryOp(_, operand) => is_pure(&operand.data),
+        TypedTerm::BinaryOp(op, lhs, rhs) => {
+            !matches!(op.data, BinaryOp::Div) && is_pure(&lhs.data) && is_pure(&rhs.data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util;
+
+    fn compile(source: &str) -> String {
+        let mut cfg = test_util::compile(source).cfg;
+        cfg.optimize().unwrap();
+        cfg.to_vm()
+            .iter()
+            .map(|command| format!("{}\n", command))
+            .collect()
+    }
+
+    #[test]
+    fn fold_expressions() {
+        let source = "\
+class Main {
+    function int main(int x) {
+        var int y;
+        let y = (2 + 3) * 4 - 22;
+        let y = (x + 0) * 4;
+        let y = x * (1 - 1);
+        return --x;
+    }
+}
+";
+        let expected = "\
+function Main.main 1
+push constant 2
+neg
+pop local 0
+push argument 0
+push argument 0
+add
+push argument 0
+push argument 0
+add
+add
+pop local 0
+push constant 0
+pop local 0
+push argument 0
+return
+";
+        assert_eq!(compile(source), expected);
+    }
+
+    #[test]
+    fn fold_conditions() {
+        let source = "\
+class Main {
+    function void main(int x) {
+        if (false) {
+            do Main.main(0);
+        }
+        if (~(x < 1)) {
+            let x = 1;
+        }
+        while (true) {
+            let x = x + 1;
+        }
+        return;
+    }
+}
+";
+        let expected = "\
+function Main.main 0
+push argument 0
+push constant 1
+lt
+if-goto WHILE_BODY0
+push constant 1
+pop argument 0
+label WHILE_BODY0
+push argument 0
+push constant 1
+add
+pop argument 0
+goto WHILE_BODY0
+";
+        assert_eq!(compile(source), expected);
+    }
+}
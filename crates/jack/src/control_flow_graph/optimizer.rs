@@ -1,6 +1,7 @@
-use super::*;
+use super::{fold, *};
 use crate::{
     control_flow_graph::{BasicBlock, BbId, CfgClass, Exit},
+    diagnostic::{Diagnostic, ToDiagnostic},
     token::Location,
 };
 use std::{
@@ -15,12 +16,24 @@ pub enum OptimizeError {
     NoReturnStatement(Location),
 }
 
+impl ToDiagnostic for OptimizeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::NoReturnStatement(loc) => {
+                Diagnostic::error("no return statement at end of subroutine")
+                    .with_primary(*loc, "control reaches the end without returning")
+            }
+        }
+    }
+}
+
 impl WithLoc<CfgClass> {
     pub fn optimize(&mut self) -> Result<(), OptimizeError> {
         for sub in &mut self.data.subs {
             let mut blocks = mem::take(&mut sub.data.blocks);
             let mut block_count = usize::MAX;
             let mut updated = false;
+            fold::fold_constants(&mut blocks);
             while sub.data.blocks.len() < block_count || updated {
                 updated = false;
                 block_count = sub.data.blocks.len();
@@ -28,6 +41,7 @@ impl WithLoc<CfgClass> {
                 updated |= concat_unique(&mut blocks);
                 blocks = remove_unreachable(sub.data.entry_id, blocks)?;
             }
+            fold::invert_branches(&mut blocks);
             sub.data.blocks = blocks;
             sub.data.update_bb_links();
         }
@@ -52,6 +66,21 @@ fn replace_empty(blocks: &mut [WithLoc<BasicBlock>]) -> bool {
         }
     }
 
+    // follow chains of empty blocks to their end, leaving out empty infinite loops
+    let replace_block = replace_block
+        .keys()
+        .filter_map(|id| {
+            let mut dest = replace_block[id];
+            for _ in 0..replace_block.len() {
+                match replace_block.get(&dest) {
+                    Some(next) => dest = *next,
+                    None => return Some((*id, dest)),
+                }
+            }
+            None
+        })
+        .collect::<HashMap<_, _>>();
+
     for block in &mut *blocks {
         match &mut block.data.exit {
             Exit::Return(_) => {}
@@ -103,7 +132,7 @@ fn concat_unique(blocks: &mut [WithLoc<BasicBlock>]) -> bool {
     for i in 0..blocks.len() {
         let block = &mut blocks[i];
         if let Exit::Goto(dest) = &block.data.exit {
-            if entry_count[dest] == 1 {
+            if entry_count[dest] == 1 && *dest != block.data.id {
                 let next_idx = block_map[dest];
                 let append_stmts = mem::take(&mut blocks[next_idx].data.stmts);
                 let new_exit = mem::replace(&mut blocks[next_idx].data.exit, Exit::Unreachable);
@@ -0,0 +1,485 @@
+//! A lossless concrete syntax tree.
+//!
+//! The tree keeps every token with its source text and the whitespace and comments before it, so
+//! that printing the tree gives back the source exactly. Its nodes follow the [`ast`](crate::ast)
+//! types, and a node has the same location as the AST node it was built from, so that tools can
+//! find the tokens of an AST node to rewrite them.
+
+use crate::{
+    ast::*,
+    token::{
+        Comment, Location, LosslessTokens, ParseTokenError, Symbol, Token, Trivia, TriviaToken,
+        WithLoc,
+    },
+};
+use std::{fmt, iter::Peekable};
+
+/// The AST type a node is built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole source, including tokens outside of the class.
+    Source,
+    Class,
+    ConstDec,
+    ClassVarDec,
+    Subroutine,
+    ParameterList,
+    Parameter,
+    SubroutineBody,
+    LocalVarDec,
+    StatementList,
+    LetStatement,
+    IfStatement,
+    WhileStatement,
+    ForStatement,
+    DoStatement,
+    ReturnStatement,
+    BreakStatement,
+    ContinueStatement,
+    Expression,
+    Term,
+    SubroutineCall,
+    ExpressionList,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: NodeKind,
+    /// The location of the AST node, without the leading trivia.
+    pub loc: Location,
+    pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(TriviaToken),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    pub root: SyntaxNode,
+    /// The trivia after the last token.
+    pub trailing: Vec<WithLoc<Trivia>>,
+}
+
+impl SyntaxTree {
+    /// Parses a class into both its syntax tree and its AST.
+    ///
+    /// Syntax errors are reported in the AST result. The tokens of the declarations and statements
+    /// skipped by the error recovery belong to the enclosing node.
+    pub fn parse(
+        source: &str,
+        options: ParseOptions,
+    ) -> Result<(Self, ParseResult<Class, ParseTokenError>), ParseTokenError> {
+        let tokens = LosslessTokens::from_source(source, options.extensions)?;
+        let result = Class::from_tokens_with(
+            tokens.tokens.iter().map(|token| Ok(token.token.clone())),
+            options,
+        );
+        let tree = Self::from_ast(tokens, result.ast.as_ref());
+        Ok((tree, result))
+    }
+
+    /// Builds the tree of the tokens of a source and the AST parsed from them.
+    pub fn from_ast(tokens: LosslessTokens, ast: Option<&WithLoc<Class>>) -> Self {
+        let LosslessTokens { tokens, trailing } = tokens;
+        let end = tokens.last().map_or(0, |token| token.token.loc.end);
+        let loc = Location {
+            line_num: 1,
+            column: 0,
+            start: 0,
+            end,
+        };
+        let root = Span::at(NodeKind::Source, loc)
+            .with(ast.map(|class| Span::class(class, &tokens)))
+            .build(&mut tokens.into_iter().peekable());
+        Self { root, trailing }
+    }
+
+    /// Calls `f` with each token in source order, to rewrite their text.
+    pub fn for_each_token_mut(&mut self, f: &mut impl FnMut(&mut TriviaToken)) {
+        self.root.for_each_token_mut(f);
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root)?;
+        for trivia in &self.trailing {
+            f.write_str(trivia.data.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl SyntaxNode {
+    /// Returns the tokens of the node in source order.
+    pub fn tokens(&self) -> Vec<&TriviaToken> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a TriviaToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    pub fn for_each_token_mut(&mut self, f: &mut impl FnMut(&mut TriviaToken)) {
+        for child in &mut self.children {
+            match child {
+                SyntaxElement::Node(node) => node.for_each_token_mut(f),
+                SyntaxElement::Token(token) => f(token),
+            }
+        }
+    }
+
+    /// Returns the child nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> + '_ {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Returns the outermost node of `kind` located at `loc`, which is the node of the AST node
+    /// with this location.
+    pub fn find(&self, kind: NodeKind, loc: Location) -> Option<&SyntaxNode> {
+        if self.kind == kind && self.loc.start == loc.start && self.loc.end == loc.end {
+            return Some(self);
+        }
+        self.nodes()
+            .filter(|node| node.loc.start <= loc.start && loc.end <= node.loc.end)
+            .find_map(|node| node.find(kind, loc))
+    }
+
+    /// Returns the comments on the lines before the node, such as the documentation of a
+    /// declaration. A comment on the line of the previous token belongs to that token.
+    pub fn leading_comments(&self) -> Vec<WithLoc<&Comment>> {
+        let tokens = self.tokens();
+        let Some(token) = tokens.first() else {
+            return vec![];
+        };
+        let leading = &token.leading;
+        let at_source_start = leading.first().is_none_or(|trivia| trivia.loc.start == 0);
+        let skip = if at_source_start {
+            0
+        } else {
+            leading
+                .iter()
+                .position(
+                    |trivia| matches!(&trivia.data, Trivia::Whitespace(ws) if ws.contains('\n')),
+                )
+                .map_or(leading.len(), |i| i + 1)
+        };
+        leading[skip..]
+            .iter()
+            .filter_map(|trivia| match &trivia.data {
+                Trivia::Comment(comment) => Some(WithLoc {
+                    data: comment,
+                    loc: trivia.loc,
+                }),
+                Trivia::Whitespace(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{}", node)?,
+                SyntaxElement::Token(token) => write!(f, "{}", token)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The location of a node to build, with the locations of its child nodes.
+struct Span {
+    kind: NodeKind,
+    loc: Location,
+    children: Vec<Span>,
+}
+
+impl Span {
+    fn at(kind: NodeKind, loc: Location) -> Self {
+        Self {
+            kind,
+            loc,
+            children: vec![],
+        }
+    }
+
+    fn with(mut self, children: impl IntoIterator<Item = Span>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    /// Distributes the tokens to the node and its children. A token belongs to the innermost
+    /// node whose span contains its start.
+    fn build(mut self, tokens: &mut Peekable<impl Iterator<Item = TriviaToken>>) -> SyntaxNode {
+        self.children.sort_by_key(|child| child.loc.start);
+        let mut spans = self.children.into_iter().peekable();
+        let mut children = vec![];
+        loop {
+            let token_start = tokens
+                .peek()
+                .map(|token| token.token.loc.start)
+                .filter(|start| *start < self.loc.end);
+            let child_first = match (spans.peek(), token_start) {
+                (Some(span), Some(start)) => span.loc.start <= start,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let child = if child_first {
+                SyntaxElement::Node(spans.next().unwrap().build(tokens))
+            } else {
+                SyntaxElement::Token(tokens.next().unwrap())
+            };
+            children.push(child);
+        }
+        SyntaxNode {
+            kind: self.kind,
+            loc: self.loc,
+            children,
+        }
+    }
+
+    fn class(class: &WithLoc<Class>, tokens: &[TriviaToken]) -> Self {
+        let Class {
+            name: _,
+            consts,
+            vars,
+            subs,
+        } = &class.data;
+        Self::at(NodeKind::Class, class.loc)
+            .with(consts.iter().map(|constant| {
+                Self::at(NodeKind::ConstDec, constant.loc)
+                    .with([Self::expression(&constant.data.expr, tokens)])
+            }))
+            .with(
+                vars.iter()
+                    .map(|var| Self::at(NodeKind::ClassVarDec, var.loc)),
+            )
+            .with(subs.iter().map(|sub| Self::subroutine(sub, tokens)))
+    }
+
+    fn subroutine(sub: &WithLoc<Subroutine>, tokens: &[TriviaToken]) -> Self {
+        let Subroutine { params, body, .. } = &sub.data;
+        let params = Self::at(NodeKind::ParameterList, params.loc).with(
+            params
+                .data
+                .0
+                .iter()
+                .map(|param| Self::at(NodeKind::Parameter, param.loc)),
+        );
+        let body = Self::at(NodeKind::SubroutineBody, body.loc)
+            .with(
+                body.data
+                    .vars
+                    .iter()
+                    .map(|var| Self::at(NodeKind::LocalVarDec, var.loc)),
+            )
+            .with([Self::statements(&body.data.stmts, tokens)]);
+        Self::at(NodeKind::Subroutine, sub.loc).with([params, body])
+    }
+
+    fn statements(stmts: &WithLoc<StatementList>, tokens: &[TriviaToken]) -> Self {
+        Self::at(NodeKind::StatementList, stmts.loc).with(
+            stmts
+                .data
+                .0
+                .iter()
+                .map(|stmt| Self::statement(stmt, tokens)),
+        )
+    }
+
+    fn statement(stmt: &WithLoc<Statement>, tokens: &[TriviaToken]) -> Self {
+        match &stmt.data {
+            Statement::Let(stmt) => Self::let_statement(NodeKind::LetStatement, stmt, tokens),
+            Statement::If(stmt) => {
+                let IfStatement {
+                    cond,
+                    then_stmts,
+                    else_stmts,
+                } = &stmt.data;
+                Self::at(NodeKind::IfStatement, stmt.loc)
+                    .with([
+                        Self::expression(cond, tokens),
+                        Self::statements(then_stmts, tokens),
+                    ])
+                    .with(
+                        else_stmts
+                            .iter()
+                            .map(|stmts| Self::statements(stmts, tokens)),
+                    )
+            }
+            Statement::While(stmt) => Self::at(NodeKind::WhileStatement, stmt.loc).with([
+                Self::expression(&stmt.data.cond, tokens),
+                Self::statements(&stmt.data.stmts, tokens),
+            ]),
+            Statement::For(stmt) => {
+                let ForStatement {
+                    init,
+                    cond,
+                    step,
+                    stmts,
+                } = &stmt.data;
+                Self::at(NodeKind::ForStatement, stmt.loc)
+                    .with(
+                        init.iter().chain(step).map(|clause| {
+                            Self::let_statement(NodeKind::LetStatement, clause, tokens)
+                        }),
+                    )
+                    .with(cond.iter().map(|cond| Self::expression(cond, tokens)))
+                    .with([Self::statements(stmts, tokens)])
+            }
+            Statement::Do(stmt) => Self::at(NodeKind::DoStatement, stmt.loc)
+                .with([Self::subroutine_call(&stmt.data.sub_call, tokens)]),
+            Statement::Return(stmt) => Self::at(NodeKind::ReturnStatement, stmt.loc).with(
+                stmt.data
+                    .expr
+                    .iter()
+                    .map(|expr| Self::expression(expr, tokens)),
+            ),
+            Statement::Break => Self::at(NodeKind::BreakStatement, stmt.loc),
+            Statement::Continue => Self::at(NodeKind::ContinueStatement, stmt.loc),
+        }
+    }
+
+    fn let_statement(kind: NodeKind, stmt: &WithLoc<LetStatement>, tokens: &[TriviaToken]) -> Self {
+        let LetStatement {
+            target: _,
+            target_index,
+            expr,
+        } = &stmt.data;
+        Self::at(kind, stmt.loc)
+            .with(
+                target_index
+                    .iter()
+                    .map(|index| Self::expression(index, tokens)),
+            )
+            .with([Self::expression(expr, tokens)])
+    }
+
+    fn expression(expr: &WithLoc<Expression>, tokens: &[TriviaToken]) -> Self {
+        let Expression { term, binary_ops } = &expr.data;
+        Self::at(NodeKind::Expression, expr.loc)
+            .with([Self::term(term, tokens)])
+            .with(binary_ops.iter().map(|(_, term)| Self::term(term, tokens)))
+    }
+
+    fn term(term: &WithLoc<Term>, tokens: &[TriviaToken]) -> Self {
+        let span = Self::at(NodeKind::Term, term.loc);
+        match &term.data {
+            Term::IntConstant(_)
+            | Term::StringConstant(_)
+            | Term::CharConstant(_)
+            | Term::KeywordConstant(_)
+            | Term::Variable(_)
+            | Term::ClassConstant(..) => span,
+            Term::Index(_, index) => span.with([Self::expression(index, tokens)]),
+            Term::SubroutineCall(call) => span.with([Self::subroutine_call(call, tokens)]),
+            Term::Expression(expr) => span.with([Self::expression(expr, tokens)]),
+            Term::UnaryOp(_, operand) => span.with([Self::term(operand, tokens)]),
+        }
+    }
+
+    fn subroutine_call(call: &WithLoc<SubroutineCall>, tokens: &[TriviaToken]) -> Self {
+        let args = match &call.data {
+            SubroutineCall::SubroutineCall(_, args) | SubroutineCall::PropertyCall(_, _, args) => {
+                args
+            }
+        };
+        // the location of a call may only cover its name, so it is extended up to the `)`
+        // following the arguments
+        let end = tokens
+            .iter()
+            .find(|token| {
+                token.token.loc.start >= args.loc.end
+                    && token.token.data == Token::Symbol(Symbol::CloseParen)
+            })
+            .map_or(args.loc.end, |token| token.token.loc.end);
+        let args = Self::at(NodeKind::ExpressionList, args.loc)
+            .with(args.data.0.iter().map(|arg| Self::expression(arg, tokens)));
+        let loc = Location { end, ..call.loc };
+        Self::at(NodeKind::SubroutineCall, loc).with([args])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "\
+// The entry point.
+class Main {
+    static int count; // calls so far
+
+    /** Returns `x` plus one. */
+    function int inc(int x) {
+        let count = count + 1;
+        return Main.add(x,   1);
+    }
+
+    function int add(int x, int y) { return x + y; }
+}
+/* end */
+";
+
+    fn parse(source: &str) -> (SyntaxTree, WithLoc<Class>) {
+        let (tree, result) = SyntaxTree::parse(source, ParseOptions::default()).unwrap();
+        (tree, result.into_result().unwrap())
+    }
+
+    #[test]
+    fn lossless() {
+        let (tree, _) = parse(SOURCE);
+        assert_eq!(tree.to_string(), SOURCE);
+
+        // skipped tokens are kept, too
+        let source = "class Main { function void f() { let = 1; return; } } extra";
+        let (tree, result) = SyntaxTree::parse(source, ParseOptions::default()).unwrap();
+        assert!(!result.errors.is_empty());
+        assert_eq!(tree.to_string(), source);
+    }
+
+    #[test]
+    fn ast_nodes() {
+        let (tree, class) = parse(SOURCE);
+        let sub = &class.data.subs[0];
+        let node = tree.root.find(NodeKind::Subroutine, sub.loc).unwrap();
+        let comments = node.leading_comments();
+        assert_eq!(comments[0].data.text, "/** Returns `x` plus one. */");
+        assert_eq!(node.tokens()[0].text, "function");
+
+        let stmt = &sub.data.body.data.stmts.data.0[1];
+        let node = tree.root.find(NodeKind::ReturnStatement, stmt.loc).unwrap();
+        let call = node
+            .nodes()
+            .next()
+            .and_then(|expr| expr.nodes().next())
+            .and_then(|term| term.nodes().next())
+            .unwrap();
+        assert_eq!(call.kind, NodeKind::SubroutineCall);
+        assert_eq!(call.to_string(), " Main.add(x,   1)");
+
+        // rename `add` without touching the layout
+        let mut tree = tree;
+        tree.for_each_token_mut(&mut |token| {
+            if token.text == "add" {
+                token.text = "plus".into();
+            }
+        });
+        assert_eq!(tree.to_string(), SOURCE.replace("add", "plus"));
+    }
+}
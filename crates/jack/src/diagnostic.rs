@@ -0,0 +1,444 @@
+use crate::token::Location;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+/// A message attached to a span of the source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    /// The file containing the span, or `None` if it is the file the diagnostic is reported for.
+    pub path: Option<PathBuf>,
+    pub loc: Location,
+    pub message: String,
+}
+
+/// An error or a warning with the spans of the source that caused it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The identifier of the lint reporting the diagnostic.
+    pub code: Option<String>,
+    pub message: String,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+/// Conversion of compiler errors into diagnostics.
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            primary: None,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_primary(mut self, loc: Location, message: impl Into<String>) -> Self {
+        self.primary = Some(Label {
+            path: None,
+            loc,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, loc: Location, message: impl Into<String>) -> Self {
+        if loc.is_builtin() {
+            self.notes.push(format!("{} (built-in)", message.into()));
+        } else {
+            self.secondary.push(Label {
+                path: None,
+                loc,
+                message: message.into(),
+            });
+        }
+        self
+    }
+
+    pub fn with_secondary_in(
+        mut self,
+        path: impl Into<PathBuf>,
+        loc: Location,
+        message: impl Into<String>,
+    ) -> Self {
+        self.secondary.push(Label {
+            path: Some(path.into()),
+            loc,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn labels(&self) -> impl Iterator<Item = (bool, &Label)> {
+        self.primary
+            .iter()
+            .map(|label| (true, label))
+            .chain(self.secondary.iter().map(|label| (false, label)))
+    }
+
+    /// Renders the diagnostic with the lines of `source` underlined by the labels.
+    ///
+    /// `path` and `source` are the file the diagnostic is reported for. Labels in other files are
+    /// shown without the source lines.
+    pub fn render(&self, path: &Path, source: &str) -> String {
+        let mut lines = self
+            .labels()
+            .map(|(primary, label)| {
+                let pos = label
+                    .path
+                    .is_none()
+                    .then(|| Span::new(source, label.loc))
+                    .flatten();
+                (primary, label, pos)
+            })
+            .collect::<Vec<_>>();
+        let width = lines
+            .iter()
+            .filter_map(|(_, _, pos)| pos.as_ref())
+            .map(|pos| pos.start.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(width);
+
+        let mut out = String::new();
+        match &self.code {
+            Some(code) => {
+                let _ = writeln!(
+                    out,
+                    "{}[{}]: {}",
+                    self.severity.as_str(),
+                    code,
+                    self.message
+                );
+            }
+            None => {
+                let _ = writeln!(out, "{}: {}", self.severity.as_str(), self.message);
+            }
+        }
+        match lines.first() {
+            Some((_, label, Some(pos))) if label.path.is_none() => {
+                let _ = writeln!(
+                    out,
+                    "{}--> {}:{}:{}",
+                    gutter,
+                    path.display(),
+                    pos.start.line,
+                    pos.start.column
+                );
+            }
+            _ => {
+                let _ = writeln!(out, "{}--> {}", gutter, path.display());
+            }
+        }
+        // snippets are shown in source order, followed by the labels in other files
+        lines.sort_by_key(|(_, _, pos)| pos.as_ref().map_or(u32::MAX, |pos| pos.start.offset));
+        for (primary, label, pos) in &lines {
+            let pos = match pos {
+                Some(pos) => pos,
+                None => {
+                    let path = label.path.as_deref().unwrap_or(path);
+                    let _ = writeln!(
+                        out,
+                        "{} = note: {} at {}:{}",
+                        gutter,
+                        label.message,
+                        path.display(),
+                        label.loc
+                    );
+                    continue;
+                }
+            };
+            let marker = if *primary { "^" } else { "-" };
+            let _ = writeln!(out, "{} |", gutter);
+            let _ = writeln!(
+                out,
+                "{:>width$} | {}",
+                pos.start.line,
+                pos.text,
+                width = width
+            );
+            let _ = writeln!(
+                out,
+                "{} | {}{} {}",
+                gutter,
+                pos.indent,
+                marker.repeat(pos.len),
+                label.message
+            );
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{} = note: {}", gutter, note);
+        }
+        out
+    }
+
+    /// Writes the diagnostic as a single line of JSON.
+    pub fn write_json(&self, path: &Path, source: &str, mut writer: impl Write) -> io::Result<()> {
+        let labels = self
+            .labels()
+            .map(|(primary, label)| {
+                let span = label
+                    .path
+                    .is_none()
+                    .then(|| Span::new(source, label.loc))
+                    .flatten();
+                JsonLabel {
+                    file: label.path.as_deref().unwrap_or(path),
+                    primary,
+                    message: &label.message,
+                    start: span
+                        .as_ref()
+                        .map(|s| s.start)
+                        .unwrap_or_else(|| JsonPosition::from_loc(label.loc.start, &label.loc)),
+                    end: span
+                        .as_ref()
+                        .map(|s| s.end)
+                        .unwrap_or_else(|| JsonPosition::from_loc(label.loc.end, &label.loc)),
+                }
+            })
+            .collect();
+        let json = JsonDiagnostic {
+            severity: self.severity,
+            code: self.code.as_deref(),
+            message: &self.message,
+            file: path,
+            labels,
+            notes: &self.notes,
+        };
+        serde_json::to_writer(&mut writer, &json)?;
+        writeln!(writer)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonDiagnostic<'a> {
+    severity: Severity,
+    code: Option<&'a str>,
+    message: &'a str,
+    file: &'a Path,
+    labels: Vec<JsonLabel<'a>>,
+    notes: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLabel<'a> {
+    file: &'a Path,
+    primary: bool,
+    message: &'a str,
+    start: JsonPosition,
+    end: JsonPosition,
+}
+
+/// A position in the source. `line` and `column` are 1-based, and `column` counts characters.
+#[derive(Debug, Clone, Copy, Serialize)]
+struct JsonPosition {
+    offset: u32,
+    line: u32,
+    column: u32,
+}
+
+impl JsonPosition {
+    fn from_loc(offset: u32, loc: &Location) -> Self {
+        Self {
+            offset,
+            line: loc.line_num,
+            column: loc.column + 1,
+        }
+    }
+}
+
+/// A span resolved against the source text.
+#[derive(Debug)]
+struct Span<'a> {
+    start: JsonPosition,
+    end: JsonPosition,
+    /// The line containing the start of the span.
+    text: &'a str,
+    /// Whitespace preceding the start of the span in `text`.
+    indent: String,
+    /// Number of characters of the span in `text`, at least 1.
+    len: usize,
+}
+
+impl<'a> Span<'a> {
+    fn new(source: &'a str, loc: Location) -> Option<Self> {
+        if loc.is_builtin() {
+            return None;
+        }
+        let start = loc.start as usize;
+        let end = usize::max(loc.end as usize, start);
+        if end > source.len() || !source.is_char_boundary(start) || !source.is_char_boundary(end) {
+            return None;
+        }
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(source.len());
+        let text = source[line_start..line_end].trim_end_matches('\r');
+        let prefix = &source[line_start..start];
+        let indent = prefix
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let len = source[start..usize::min(end, line_start + text.len())]
+            .chars()
+            .count()
+            .max(1);
+        Some(Self {
+            start: position(source, start),
+            end: position(source, end),
+            text,
+            indent,
+            len,
+        })
+    }
+}
+
+fn position(source: &str, offset: usize) -> JsonPosition {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    JsonPosition {
+        offset: offset as u32,
+        line: before.matches('\n').count() as u32 + 1,
+        column: before[line_start..].chars().count() as u32 + 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ast::Class,
+        symbol_table::GlobalSymbolTable,
+        token::{ParseTokenError, Tokens},
+    };
+
+    fn diagnose(source: &str) -> Diagnostic {
+        let ast = Class::from_tokens(Tokens::from_reader(source.as_bytes()));
+        let ast = match ast.into_result() {
+            Ok(ast) => ast,
+            Err(e) => return e.0[0].to_diagnostic(),
+        };
+        let mut sym_tab = GlobalSymbolTable::with_builtin();
+        if let Err(e) = sym_tab.extend_with_class("Main.jack", &ast.data) {
+            return e.to_diagnostic();
+        }
+        ast.resolve(&sym_tab).unwrap_err().to_diagnostic()
+    }
+
+    #[test]
+    fn render_secondary_label() {
+        let source = "class Main {\n    method void foo() { return; }\n    method void foo() { return; }\n}\n";
+        let diag = diagnose(source);
+        assert_eq!(
+            diag.render(Path::new("Main.jack"), source),
+            "\
+error: method `foo` is already defined
+ --> Main.jack:3:17
+  |
+2 |     method void foo() { return; }
+  |                 --- first defined here
+  |
+3 |     method void foo() { return; }
+  |                 ^^^ redefined here
+"
+        );
+    }
+
+    #[test]
+    fn render_expression_span() {
+        let source = "class Main {\n  function void f() {\n    var int x;\n    let x = true | false;\n    return;\n  }\n}\n";
+        let diag = diagnose(source);
+        assert_eq!(
+            diag.render(Path::new("Main.jack"), source),
+            "\
+error: cannot cast `boolean` to `int`
+ --> Main.jack:4:13
+  |
+4 |     let x = true | false;
+  |             ^^^^^^^^^^^^ expected `int`, found `boolean`
+"
+        );
+    }
+
+    #[test]
+    fn render_parse_error() {
+        let source = "class Main {\n  function void f() {\n    let x = ;\n  }\n}\n";
+        let diag = diagnose(source);
+        let rendered = diag.render(Path::new("Main.jack"), source);
+        assert!(rendered.starts_with("error: expected term, found `;`\n --> Main.jack:3:13\n"));
+        assert!(rendered.contains("  |             ^ unexpected token\n"));
+        assert!(rendered.contains("  |     --- while parsing let statement\n"));
+    }
+
+    #[test]
+    fn json() {
+        let source = "class Main {\n  function void f() {\n    do g(1, 2);\n    return;\n  }\n  function void g(int a) { return; }\n}\n";
+        let diag = diagnose(source);
+        let mut json = vec![];
+        diag.write_json(Path::new("Main.jack"), source, &mut json)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["file"], "Main.jack");
+        let label = &json["labels"][0];
+        assert_eq!(label["primary"], true);
+        assert_eq!(label["start"]["line"], 3);
+        assert_eq!(label["start"]["column"], 8);
+        assert_eq!(label["end"]["column"], 9);
+    }
+
+    #[test]
+    fn token_error() {
+        let source = "class Main {\n  field int x;\n  ?\n}\n";
+        let mut tokens = Tokens::from_reader(source.as_bytes());
+        let e: ParseTokenError = tokens.find_map(Result::err).unwrap();
+        let rendered = e.to_diagnostic().render(Path::new("Main.jack"), source);
+        assert!(rendered.starts_with("error: unexpected char: ?\n --> Main.jack:3:3\n"));
+    }
+}
@@ -0,0 +1,403 @@
+//! API documentation generated from the `/** ... */` comments of classes and subroutines.
+
+use crate::{
+    ast::*,
+    cst::{NodeKind, SyntaxNode, SyntaxTree},
+    diagnostic::{Diagnostic, ToDiagnostic},
+    token::{Ident, ParseTokenError, WithLoc},
+};
+use std::{collections::HashSet, fmt::Write};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DocError {
+    #[error(transparent)]
+    Tokenize(#[from] ParseTokenError),
+    #[error(transparent)]
+    Parse(#[from] ParseErrors<ParseTokenError>),
+}
+
+impl DocError {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Tokenize(e) => vec![e.to_diagnostic()],
+            Self::Parse(e) => e.0.iter().map(|e| e.to_diagnostic()).collect(),
+        }
+    }
+}
+
+/// The documentation of a class.
+#[derive(Debug, Clone)]
+pub struct ClassDoc {
+    pub name: Ident,
+    pub doc: Option<String>,
+    pub consts: Vec<ConstDoc>,
+    pub subs: Vec<SubroutineDoc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConstDoc {
+    pub ty: Type,
+    pub name: Ident,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubroutineDoc {
+    pub kind: SubroutineKind,
+    pub return_type: ReturnType,
+    pub name: Ident,
+    pub params: Vec<(Type, Ident)>,
+    pub doc: Option<String>,
+}
+
+/// The output format of [`render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl ClassDoc {
+    /// Extracts the documentation of the class defined in `source`.
+    pub fn from_source(source: &str, options: ParseOptions) -> Result<Self, DocError> {
+        let (tree, result) = SyntaxTree::parse(source, options)?;
+        let class = result.into_result()?;
+        Ok(Self::from_class(&class, &tree))
+    }
+
+    /// Extracts the documentation of `class` from the comments in its syntax tree.
+    pub fn from_class(class: &WithLoc<Class>, tree: &SyntaxTree) -> Self {
+        let doc = |kind, loc| tree.root.find(kind, loc).and_then(doc_comment);
+        let Class {
+            name, consts, subs, ..
+        } = &class.data;
+        Self {
+            name: name.data.clone(),
+            doc: doc(NodeKind::Class, class.loc),
+            consts: consts
+                .iter()
+                .map(|constant| ConstDoc {
+                    ty: constant.data.ty.data.clone(),
+                    name: constant.data.name.data.clone(),
+                    doc: doc(NodeKind::ConstDec, constant.loc),
+                })
+                .collect(),
+            subs: subs
+                .iter()
+                .map(|sub| SubroutineDoc {
+                    kind: sub.data.kind.data,
+                    return_type: sub.data.return_type.data.clone(),
+                    name: sub.data.name.data.clone(),
+                    params: sub
+                        .data
+                        .params
+                        .data
+                        .0
+                        .iter()
+                        .map(|param| (param.data.ty.data.clone(), param.data.name.data.clone()))
+                        .collect(),
+                    doc: doc(NodeKind::Subroutine, sub.loc),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns the text of the last `/** ... */` comment before the node, without the delimiters, the
+/// leading `*` of each line and the indentation common to the lines.
+fn doc_comment(node: &SyntaxNode) -> Option<String> {
+    let comments = node.leading_comments();
+    let text = comments
+        .iter()
+        .rev()
+        .map(|comment| comment.data.text.as_str())
+        .find(|text| text.starts_with("/**") && text != &"/**/")?;
+    let text = text.trim_start_matches("/**").trim_end_matches("*/");
+    let mut lines = text.lines().map(|line| {
+        let line = line.trim();
+        line.strip_prefix('*').unwrap_or(line).trim_end()
+    });
+    let first = lines.next().unwrap_or_default().trim_start();
+    let rest = lines.collect::<Vec<_>>();
+    let indent = rest
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let text = std::iter::once(first)
+        .chain(
+            rest.iter()
+                .map(|line| line.get(indent..).unwrap_or_default()),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Renders the API reference of the classes, with the types linking to their classes.
+pub fn render(classes: &[ClassDoc], format: DocFormat) -> String {
+    let mut classes = classes.iter().collect::<Vec<_>>();
+    classes.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+    let names = classes
+        .iter()
+        .map(|class| class.name.as_str())
+        .collect::<HashSet<_>>();
+    let mut renderer = Renderer {
+        format,
+        names: &names,
+        out: String::new(),
+    };
+    renderer.document(&classes);
+    renderer.out
+}
+
+struct Renderer<'a> {
+    format: DocFormat,
+    /// The documented classes, which types link to.
+    names: &'a HashSet<&'a str>,
+    out: String,
+}
+
+impl Renderer<'_> {
+    fn document(&mut self, classes: &[&ClassDoc]) {
+        match self.format {
+            DocFormat::Markdown => {
+                self.out.push_str("# API Reference\n\n");
+                for class in classes {
+                    let name = class.name.as_str();
+                    writeln!(self.out, "- [{}](#{})", name, name).unwrap();
+                }
+            }
+            DocFormat::Html => {
+                self.out.push_str(concat!(
+                    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+                    "<title>API Reference</title>\n</head>\n<body>\n<h1>API Reference</h1>\n<ul>\n",
+                ));
+                for class in classes {
+                    let name = class.name.as_str();
+                    writeln!(self.out, "<li><a href=\"#{}\">{}</a></li>", name, name).unwrap();
+                }
+                self.out.push_str("</ul>\n");
+            }
+        }
+        for class in classes {
+            self.class(class);
+        }
+        if self.format == DocFormat::Html {
+            self.out.push_str("</body>\n</html>\n");
+        }
+    }
+
+    fn class(&mut self, class: &ClassDoc) {
+        let name = class.name.as_str();
+        self.heading(2, name, name);
+        self.doc(&class.doc);
+        if !class.consts.is_empty() {
+            self.heading(3, &format!("{}.constants", name), "Constants");
+            for constant in &class.consts {
+                let signature = format!(
+                    "const {} {}.{}",
+                    self.ty(&constant.ty),
+                    name,
+                    escape(constant.name.as_str(), self.format)
+                );
+                self.paragraph(&signature);
+                self.doc(&constant.doc);
+            }
+        }
+        for sub in &class.subs {
+            let id = format!("{}.{}", name, sub.name.as_str());
+            self.heading(3, &id, sub.name.as_str());
+            let return_type = match &sub.return_type {
+                ReturnType::Void => "void".to_owned(),
+                ReturnType::Type(ty) => self.ty(&ty.data),
+            };
+            let params = sub
+                .params
+                .iter()
+                .map(|(ty, name)| format!("{} {}", self.ty(ty), escape(name.as_str(), self.format)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let signature = format!(
+                "{} {} {}({})",
+                sub.kind.as_str(),
+                return_type,
+                escape(sub.name.as_str(), self.format),
+                params
+            );
+            self.paragraph(&signature);
+            self.doc(&sub.doc);
+        }
+    }
+
+    /// Renders a type, linking to its class if it is documented.
+    fn ty(&self, ty: &Type) -> String {
+        let name = escape(ty.as_str(), self.format);
+        if !matches!(ty, Type::Class(class) if self.names.contains(class.as_str())) {
+            return name;
+        }
+        match self.format {
+            DocFormat::Markdown => format!("[{}](#{})", name, ty.as_str()),
+            DocFormat::Html => format!("<a href=\"#{}\">{}</a>", ty.as_str(), name),
+        }
+    }
+
+    fn heading(&mut self, level: usize, id: &str, text: &str) {
+        let text = escape(text, self.format);
+        match self.format {
+            DocFormat::Markdown => {
+                writeln!(
+                    self.out,
+                    "\n<a id=\"{}\"></a>\n\n{} {}\n",
+                    id,
+                    "#".repeat(level),
+                    text
+                )
+                .unwrap();
+            }
+            DocFormat::Html => {
+                writeln!(self.out, "<h{0} id=\"{1}\">{2}</h{0}>", level, id, text).unwrap();
+            }
+        }
+    }
+
+    /// Renders a line of already escaped text.
+    fn paragraph(&mut self, text: &str) {
+        match self.format {
+            DocFormat::Markdown => writeln!(self.out, "{}\n", text).unwrap(),
+            DocFormat::Html => writeln!(self.out, "<p><code>{}</code></p>", text).unwrap(),
+        }
+    }
+
+    /// Renders a doc comment. Markdown output keeps the text as is, so that comments may use
+    /// Markdown; HTML output escapes it and splits it into paragraphs at blank lines.
+    fn doc(&mut self, doc: &Option<String>) {
+        let Some(doc) = doc else {
+            return;
+        };
+        match self.format {
+            DocFormat::Markdown => writeln!(self.out, "{}\n", doc).unwrap(),
+            DocFormat::Html => {
+                for paragraph in doc.split("\n\n") {
+                    writeln!(self.out, "<p>{}</p>", escape(paragraph, self.format)).unwrap();
+                }
+            }
+        }
+    }
+}
+
+fn escape(text: &str, format: DocFormat) -> String {
+    match format {
+        DocFormat::Markdown => text.replace('_', "\\_"),
+        DocFormat::Html => text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "\
+/**
+ * A list of integers.
+ */
+class List {
+    field int data;
+
+    /** Creates a list with `car` followed by `cdr`. */
+    constructor List new(int car, List cdr) {
+        return this;
+    }
+
+    // not documentation
+    method int _size() {
+        return 0;
+    }
+}
+";
+
+    fn classes() -> Vec<ClassDoc> {
+        let list = ClassDoc::from_source(SOURCE, ParseOptions::default()).unwrap();
+        let main = ClassDoc::from_source(
+            "class Main { function void main() { return; } }",
+            ParseOptions::default(),
+        )
+        .unwrap();
+        vec![main, list]
+    }
+
+    #[test]
+    fn extract() {
+        let list = &classes()[1];
+        assert_eq!(list.doc.as_deref(), Some("A list of integers."));
+        assert_eq!(
+            list.subs[0].doc.as_deref(),
+            Some("Creates a list with `car` followed by `cdr`.")
+        );
+        assert_eq!(list.subs[0].params.len(), 2);
+        assert_eq!(list.subs[1].doc, None);
+    }
+
+    #[test]
+    fn render_markdown() {
+        let expected = "\
+# API Reference
+
+- [List](#List)
+- [Main](#Main)
+
+<a id=\"List\"></a>
+
+## List
+
+A list of integers.
+
+
+<a id=\"List.new\"></a>
+
+### new
+
+constructor [List](#List) new(int car, [List](#List) cdr)
+
+Creates a list with `car` followed by `cdr`.
+
+
+<a id=\"List._size\"></a>
+
+### \\_size
+
+method int \\_size()
+
+
+<a id=\"Main\"></a>
+
+## Main
+
+
+<a id=\"Main.main\"></a>
+
+### main
+
+function void main()
+
+";
+        assert_eq!(render(&classes(), DocFormat::Markdown), expected);
+    }
+
+    #[test]
+    fn render_html() {
+        let html = render(&classes(), DocFormat::Html);
+        assert!(html.contains("<h3 id=\"List.new\">new</h3>"));
+        assert!(html.contains(
+            "<p><code>constructor <a href=\"#List\">List</a> new(int car, <a href=\"#List\">List</a> cdr)</code></p>"
+        ));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+}
@@ -0,0 +1,729 @@
+use crate::{
+    ast::*,
+    diagnostic::{Diagnostic, ToDiagnostic},
+    token::{Comment, ParseTokenError, Token, Tokens, WithLoc},
+};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error(transparent)]
+    Tokenize(#[from] ParseTokenError),
+    #[error(transparent)]
+    Parse(#[from] ParseErrors<ParseTokenError>),
+    #[error("unexpected token `{}` after the class at {}", _0.data, _0.loc)]
+    TrailingToken(WithLoc<Token>),
+}
+
+impl FormatError {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Tokenize(e) => vec![e.to_diagnostic()],
+            Self::Parse(e) => e.0.iter().map(|e| e.to_diagnostic()).collect(),
+            Self::TrailingToken(token) => vec![Diagnostic::error(format!(
+                "unexpected token `{}` after the class",
+                token.data
+            ))
+            .with_primary(token.loc, "expected end of file")],
+        }
+    }
+}
+
+/// Formats a Jack source file in the canonical style.
+///
+/// Comments are kept at the same positions relative to the declarations and statements. Block
+/// comments on a single line inside an expression stay before the following operand or operator,
+/// and the other comments inside a declaration or a statement are moved before it. At most one
+/// blank line is kept between declarations and statements, and subroutines are always separated
+/// by a blank line.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    format_source_with(source, ParseOptions::default())
+}
+
+/// Formats a Jack source file like [`format_source`], with the given language options.
+///
+/// The literal extensions of the lexer are enabled together with [`ParseOptions::extensions`].
+pub fn format_source_with(source: &str, options: ParseOptions) -> Result<String, FormatError> {
+    let mut tokens = Tokens::from_reader(source.as_bytes()).with_extensions(options.extensions);
+    let class = Class::from_tokens_with(&mut tokens, options).into_result()?;
+    if let Some(token) = tokens.next().transpose()? {
+        return Err(FormatError::TrailingToken(token));
+    }
+    let mut formatter = Formatter {
+        source,
+        comments: tokens.comments().iter().cloned().collect(),
+        all_comments: tokens.comments(),
+        out: String::new(),
+        indent: 0,
+        last_end: 0,
+        at_block_start: true,
+        extensions: options.extensions,
+    };
+    formatter.class(&class.data, class.loc.end as usize);
+    formatter.comments_before(source.len());
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    /// Comments not printed yet.
+    comments: VecDeque<WithLoc<Comment>>,
+    all_comments: &'a [WithLoc<Comment>],
+    out: String,
+    indent: usize,
+    /// Offset in the source of the end of the last printed item.
+    last_end: usize,
+    at_block_start: bool,
+    /// Escapes string literals as the lexer with the literal extensions reads them.
+    extensions: bool,
+}
+
+impl Formatter<'_> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.at_block_start = false;
+    }
+
+    /// Returns the offset of the first `ch` from `start` outside the comments.
+    fn find(&self, start: usize, ch: char) -> usize {
+        let mut offset = start;
+        loop {
+            if let Some(comment) = self
+                .all_comments
+                .iter()
+                .find(|c| c.loc.start as usize == offset)
+            {
+                offset = comment.loc.end as usize;
+                continue;
+            }
+            match self.source[offset..].chars().next() {
+                Some(c) if c == ch => return offset,
+                Some(c) => offset += c.len_utf8(),
+                None => return offset,
+            }
+        }
+    }
+
+    /// Emits a blank line if there is a blank line before `start` in the source.
+    fn blank_line_before(&mut self, start: usize) {
+        let gap = &self.source[usize::min(self.last_end, start)..start];
+        if !self.at_block_start && gap.matches('\n').count() >= 2 {
+            self.out.push('\n');
+        }
+    }
+
+    /// Prints the comments before `end` on their own lines.
+    fn comments_before(&mut self, end: usize) {
+        while self
+            .comments
+            .front()
+            .map(|c| (c.loc.start as usize) < end)
+            .unwrap_or(false)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            let start = comment.loc.start as usize;
+            self.blank_line_before(start);
+            let line_start = self.source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let column = self.source[line_start..start].chars().count();
+            let mut lines = comment.data.text.lines();
+            let first = lines.next().unwrap_or("").trim_end().to_owned();
+            self.line(&first);
+            for line in lines {
+                // keep the alignment of the continuation lines of block comments
+                let trimmed = line.trim_start();
+                let leading = line.len() - trimmed.len();
+                let line = &line[usize::min(leading, column)..];
+                self.line(line.trim_end());
+            }
+            self.last_end = comment.loc.end as usize;
+        }
+    }
+
+    /// Appends a comment following `self.last_end` on the same line to the last printed line.
+    fn trailing_comment(&mut self) {
+        let comment = match self.comments.front() {
+            Some(comment) => comment,
+            None => return,
+        };
+        let start = comment.loc.start as usize;
+        if start < self.last_end
+            || !self.source[self.last_end..start].trim().is_empty()
+            || self.source[self.last_end..start].contains('\n')
+            || comment.data.text.contains('\n')
+        {
+            return;
+        }
+        let comment = self.comments.pop_front().unwrap();
+        self.out.pop();
+        self.out.push(' ');
+        self.out.push_str(comment.data.text.trim_end());
+        self.out.push('\n');
+        self.last_end = comment.loc.end as usize;
+    }
+
+    /// Takes the single-line block comments right before `start` out of the comments to print on
+    /// their own lines, and returns them to be printed inline before the token at `start`.
+    fn inline_comments(&mut self, start: u32) -> String {
+        let mut end = start as usize;
+        let mut texts = vec![];
+        while let Some(index) = self.comments.iter().position(|c| {
+            c.data.text.starts_with("/*")
+                && !c.data.text.contains('\n')
+                && c.loc.end as usize <= end
+                && self.source[c.loc.end as usize..end].trim().is_empty()
+        }) {
+            let comment = self.comments.remove(index).unwrap();
+            end = comment.loc.start as usize;
+            texts.push(comment.data.text);
+        }
+        texts
+            .iter()
+            .rev()
+            .map(|text| format!("{} ", text.trim_end()))
+            .collect()
+    }
+
+    /// Prints the leading comments and the blank line before an item.
+    fn item(&mut self, start: usize, end: usize) {
+        self.comments_before(end);
+        self.blank_line_before(start);
+    }
+
+    fn leaf(&mut self, start: usize, end: usize, text: &str) {
+        self.item(start, end);
+        self.line(text);
+        self.last_end = end;
+        self.trailing_comment();
+    }
+
+    /// Prints `header {` and starts a block.
+    fn open_block(&mut self, header: &str, open: usize) {
+        self.line(&format!("{} {{", header));
+        self.last_end = open + 1;
+        self.trailing_comment();
+        self.indent += 1;
+        self.at_block_start = true;
+    }
+
+    /// Ends the block closed with `}` at `close`.
+    fn close_block(&mut self, close: usize, footer: &str) {
+        self.comments_before(close);
+        self.indent -= 1;
+        self.line(&format!("}}{}", footer));
+        self.last_end = close + 1;
+    }
+
+    fn class(&mut self, class: &Class, end: usize) {
+        let start = class.name.loc.start as usize;
+        self.comments_before(start);
+        let open = self.find(class.name.loc.end as usize, '{');
+        self.open_block(&format!("class {}", class.name.data.as_str()), open);
+        for constant in &class.consts {
+            let ConstDec { ty, name, expr } = &constant.data;
+            let text = format!(
+                "const {} {} = {};",
+                ty.data.as_str(),
+                name.data.as_str(),
+                self.expression(&expr.data)
+            );
+            self.leaf(
+                constant.loc.start as usize,
+                constant.loc.end as usize,
+                &text,
+            );
+        }
+        for var in &class.vars {
+            let ClassVarDec { kind, ty, names } = &var.data;
+            let kind = match kind.data {
+                ClassVarKind::Static => "static",
+                ClassVarKind::Field => "field",
+            };
+            let text = format!("{} {} {};", kind, ty.data.as_str(), names_list(names));
+            self.leaf(var.loc.start as usize, var.loc.end as usize, &text);
+        }
+        for sub in &class.subs {
+            if !self.at_block_start {
+                // always separate subroutines
+                let start = sub.loc.start as usize;
+                let first_comment = self.comments.front().map(|c| c.loc.start as usize);
+                let blank = self.source[self.last_end..first_comment.unwrap_or(start).min(start)]
+                    .matches('\n')
+                    .count()
+                    >= 2;
+                if !blank {
+                    self.out.push('\n');
+                    self.at_block_start = true;
+                }
+            }
+            self.subroutine(sub);
+        }
+        self.close_block(end - 1, "");
+        self.trailing_comment();
+    }
+
+    fn subroutine(&mut self, sub: &WithLoc<Subroutine>) {
+        let Subroutine {
+            kind,
+            return_type,
+            name,
+            params,
+            body,
+        } = &sub.data;
+        let open = self.find(name.loc.end as usize, '{');
+        self.item(sub.loc.start as usize, open);
+        let params = params
+            .data
+            .0
+            .iter()
+            .map(|p| format!("{} {}", p.data.ty.data.as_str(), p.data.name.data.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let header = format!(
+            "{} {} {}({})",
+            kind.data.as_str(),
+            return_type.data.as_str(),
+            name.data.as_str(),
+            params
+        );
+        self.open_block(&header, open);
+        for var in &body.data.vars {
+            let LocalVarDec { ty, names } = &var.data;
+            let text = format!("var {} {};", ty.data.as_str(), names_list(names));
+            self.leaf(var.loc.start as usize, var.loc.end as usize, &text);
+        }
+        self.statements(&body.data.stmts.data);
+        let close = self.find(body.data.stmts.loc.end as usize, '}');
+        self.close_block(close, "");
+        self.trailing_comment();
+    }
+
+    fn statements(&mut self, stmts: &StatementList) {
+        for stmt in &stmts.0 {
+            self.statement(stmt);
+        }
+    }
+
+    fn block(&mut self, header: &str, header_end: usize, stmts: &WithLoc<StatementList>) -> usize {
+        let open = self.find(header_end, '{');
+        self.open_block(header, open);
+        self.statements(&stmts.data);
+        self.find(stmts.loc.end as usize, '}')
+    }
+
+    /// Prints `header {}` if the block has neither statements nor comments.
+    fn empty_block(&mut self, header: &str, stmts: &WithLoc<StatementList>) -> bool {
+        let close = self.find(stmts.loc.end as usize, '}');
+        let has_comments = self
+            .comments
+            .front()
+            .map(|c| (c.loc.start as usize) < close)
+            .unwrap_or(false);
+        if !stmts.data.0.is_empty() || has_comments {
+            return false;
+        }
+        self.line(&format!("{} {{}}", header));
+        self.last_end = close + 1;
+        true
+    }
+
+    fn statement(&mut self, stmt: &WithLoc<Statement>) {
+        let start = stmt.loc.start as usize;
+        let end = stmt.loc.end as usize;
+        match &stmt.data {
+            Statement::Let(WithLoc { data: s, .. }) => {
+                let text = format!("{};", self.let_clause(s));
+                self.leaf(start, end, &text);
+            }
+            Statement::Do(WithLoc { data: s, .. }) => {
+                let text = format!("do {};", self.subroutine_call(&s.sub_call.data));
+                self.leaf(start, end, &text);
+            }
+            Statement::Return(WithLoc { data: s, .. }) => {
+                let text = match &s.expr {
+                    Some(expr) => format!("return {};", self.expression(&expr.data)),
+                    None => "return;".to_owned(),
+                };
+                self.leaf(start, end, &text);
+            }
+            Statement::If(WithLoc { data: s, .. }) => {
+                // the condition is rendered first to keep its inline comments
+                let header = format!("if ({})", self.expression(&s.cond.data));
+                self.item(start, s.cond.loc.end as usize);
+                self.if_statement(&header, s);
+                self.trailing_comment();
+            }
+            Statement::While(WithLoc { data: s, .. }) => {
+                let cond_end = s.cond.loc.end as usize;
+                let header = format!("while ({})", self.expression(&s.cond.data));
+                self.item(start, cond_end);
+                if !self.empty_block(&header, &s.stmts) {
+                    let close = self.block(&header, cond_end, &s.stmts);
+                    self.close_block(close, "");
+                }
+                self.trailing_comment();
+            }
+            Statement::For(stmt) => {
+                let s = &stmt.data;
+                let header_end = [&s.step, &s.init]
+                    .iter()
+                    .filter_map(|clause| clause.as_ref().map(|clause| clause.loc.end))
+                    .chain(s.cond.as_ref().map(|cond| cond.loc.end))
+                    .max()
+                    .map(|end| end as usize)
+                    .unwrap_or(start);
+                let init = s
+                    .init
+                    .as_ref()
+                    .map(|init| self.let_clause(&init.data))
+                    .unwrap_or_default();
+                let cond = s
+                    .cond
+                    .as_ref()
+                    .map(|cond| format!(" {}", self.expression(&cond.data)))
+                    .unwrap_or_default();
+                let step = s
+                    .step
+                    .as_ref()
+                    .map(|step| format!(" {}", self.let_clause(&step.data)))
+                    .unwrap_or_default();
+                let header = format!("for ({};{};{})", init, cond, step);
+                self.item(start, header_end);
+                if !self.empty_block(&header, &s.stmts) {
+                    let close = self.block(&header, header_end, &s.stmts);
+                    self.close_block(close, "");
+                }
+                self.trailing_comment();
+            }
+            Statement::Break => self.leaf(start, end, "break;"),
+            Statement::Continue => self.leaf(start, end, "continue;"),
+        }
+    }
+
+    /// Prints an `if` statement headed by `header`, keeping `else if` chains flat.
+    fn if_statement(&mut self, header: &str, s: &IfStatement) {
+        let cond_end = s.cond.loc.end as usize;
+        match &s.else_stmts {
+            None if self.empty_block(header, &s.then_stmts) => {}
+            None => {
+                let close = self.block(header, cond_end, &s.then_stmts);
+                self.close_block(close, "");
+            }
+            Some(else_stmts) => {
+                let close = self.block(header, cond_end, &s.then_stmts);
+                self.comments_before(close);
+                self.indent -= 1;
+                let open = self.find(close + 1, '{');
+                match else_stmts.data.0.as_slice() {
+                    // `else if` has no braces before the nested `if`
+                    [WithLoc {
+                        data: Statement::If(nested),
+                        loc,
+                    }] if open > loc.start as usize => {
+                        self.last_end = close + 1;
+                        let nested = &nested.data;
+                        let header = format!("}} else if ({})", self.expression(&nested.cond.data));
+                        self.if_statement(&header, nested);
+                    }
+                    _ => {
+                        self.open_block("} else", open);
+                        self.statements(&else_stmts.data);
+                        let close = self.find(else_stmts.loc.end as usize, '}');
+                        self.close_block(close, "");
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn names_list(names: &[WithLoc<crate::token::Ident>]) -> String {
+    names
+        .iter()
+        .map(|name| name.data.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Formatter<'_> {
+    fn let_clause(&mut self, s: &LetStatement) -> String {
+        let index = s
+            .target_index
+            .as_ref()
+            .map(|index| format!("[{}]", self.expression(&index.data)))
+            .unwrap_or_default();
+        format!(
+            "let {}{} = {}",
+            s.target.data.as_str(),
+            index,
+            self.expression(&s.expr.data)
+        )
+    }
+
+    fn expression(&mut self, expr: &Expression) -> String {
+        let mut s = self.term(&expr.term);
+        for (op, operand) in &expr.binary_ops {
+            let comments = self.inline_comments(op.loc.start);
+            let operand = self.term(operand);
+            s.push_str(&format!(" {}{} {}", comments, op.data.symbol(), operand));
+        }
+        s
+    }
+
+    fn term(&mut self, t: &WithLoc<Term>) -> String {
+        let comments = self.inline_comments(t.loc.start);
+        let text = match &t.data {
+            Term::IntConstant(n) => n.data.to_string(),
+            Term::StringConstant(s) if self.extensions => {
+                let mut out = String::from('"');
+                for ch in s.data.chars() {
+                    match ch {
+                        '\u{80}' => out.push_str(r"\n"),
+                        '"' | '\\' => {
+                            out.push('\\');
+                            out.push(ch);
+                        }
+                        ch => out.push(ch),
+                    }
+                }
+                out.push('"');
+                out
+            }
+            // standard Jack has no escapes
+            Term::StringConstant(s) => format!("\"{}\"", s.data),
+            Term::CharConstant(ch) => match ch.data {
+                '\u{80}' => r"'\n'".to_owned(),
+                '\'' | '\\' => format!(r"'\{}'", ch.data),
+                ch => format!("'{}'", ch),
+            },
+            Term::KeywordConstant(k) => match k.data {
+                KeywordConstant::True => "true",
+                KeywordConstant::False => "false",
+                KeywordConstant::Null => "null",
+                KeywordConstant::This => "this",
+            }
+            .to_owned(),
+            Term::Variable(name) => name.data.as_str().to_owned(),
+            Term::Index(name, index) => {
+                format!("{}[{}]", name.data.as_str(), self.expression(&index.data))
+            }
+            Term::ClassConstant(class, name) => {
+                format!("{}.{}", class.data.as_str(), name.data.as_str())
+            }
+            Term::SubroutineCall(call) => self.subroutine_call(&call.data),
+            Term::Expression(expr) => format!("({})", self.expression(&expr.data)),
+            Term::UnaryOp(op, operand) => {
+                let op = match op.data {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "~",
+                };
+                format!("{}{}", op, self.term(operand))
+            }
+        };
+        comments + &text
+    }
+
+    fn subroutine_call(&mut self, call: &SubroutineCall) -> String {
+        let (name, args) = match call {
+            SubroutineCall::SubroutineCall(name, args) => (name.data.as_str().to_owned(), args),
+            SubroutineCall::PropertyCall(receiver, name, args) => (
+                format!("{}.{}", receiver.data.as_str(), name.data.as_str()),
+                args,
+            ),
+        };
+        let args = args
+            .data
+            .0
+            .iter()
+            .map(|arg| self.expression(&arg.data))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}({})", name, args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let source = "\
+// Main class
+class Main{
+
+  field int x,y; // coordinates
+  static   boolean flag;
+  /** Entry point.
+    * Does nothing useful.
+    */
+  function void main( ) {var int i;
+    let i=-1+(2*3);   let x[i]=Foo.bar(i,~flag,\"a b\");
+    if(i<0){do Output.printInt(i);}else{ // negative
+    }
+
+
+    while (~(i=0)) { let i = i - 1; /* decrement */ }
+    return ;
+  }
+  method int get(){
+    // no-op
+
+    while(x){ }
+    return x;
+  } // end of get
+}
+";
+        let expected = "\
+// Main class
+class Main {
+    field int x, y; // coordinates
+    static boolean flag;
+
+    /** Entry point.
+      * Does nothing useful.
+      */
+    function void main() {
+        var int i;
+        let i = -1 + (2 * 3);
+        let x[i] = Foo.bar(i, ~flag, \"a b\");
+        if (i < 0) {
+            do Output.printInt(i);
+        } else { // negative
+        }
+
+        while (~(i = 0)) {
+            let i = i - 1; /* decrement */
+        }
+        return;
+    }
+
+    method int get() {
+        // no-op
+
+        while (x) {}
+        return x;
+    } // end of get
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn comments_in_statements_are_kept() {
+        let source = "\
+class A {
+    function int f(int a) {
+        var int x;
+        let x = a /* inline */ + 1;
+        if (/* never */ x < 0) {
+            do Output.printInt(f(/* first */ a, x));
+        }
+        return x // line
+            - 1;
+    }
+}
+";
+        // line comments would swallow the rest of the statement, so they move before it
+        let expected = "\
+class A {
+    function int f(int a) {
+        var int x;
+        let x = a /* inline */ + 1;
+        if (/* never */ x < 0) {
+            do Output.printInt(f(/* first */ a, x));
+        }
+        // line
+        return x - 1;
+    }
+}
+";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn extensions() {
+        let source = "\
+class Main {
+  function void main() { var int i;
+    for (let i=0;i<10;let i=i+1) { if (i=3) { continue; } else if (i=5) { break; } }
+    for (;;) { break; }
+    return;
+  }
+}
+";
+        let expected = "\
+class Main {
+    function void main() {
+        var int i;
+        for (let i = 0; i < 10; let i = i + 1) {
+            if (i = 3) {
+                continue;
+            } else if (i = 5) {
+                break;
+            }
+        }
+        for (;;) {
+            break;
+        }
+        return;
+    }
+}
+";
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let formatted = format_source_with(source, options).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source_with(&formatted, options).unwrap(), formatted);
+        assert!(format_source(source).is_err());
+    }
+
+    #[test]
+    fn literal_extensions() {
+        let source = r#"class Main {
+    function void main() {
+        do Output.printString("say \"hi\"\n");
+        do Output.printString("a\\b");
+        do Output.printChar('\'');
+        do Output.printChar('\n');
+        return;
+    }
+}
+"#;
+        let options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let formatted = format_source_with(source, options).unwrap();
+        assert_eq!(formatted, source);
+        assert_eq!(format_source_with(&formatted, options).unwrap(), formatted);
+
+        // a backslash is an ordinary character in standard Jack
+        let source = "class Main {\n    function void main() {\n        do Output.printString(\"a\\n\");\n        return;\n    }\n}\n";
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            format_source("class A { function void f() { let x = ; } }"),
+            Err(FormatError::Parse(_))
+        ));
+        assert!(matches!(
+            format_source("class A { } class B { }"),
+            Err(FormatError::TrailingToken(_))
+        ));
+    }
+}
@@ -1,5 +1,12 @@
 pub mod ast;
 pub mod control_flow_graph;
+pub mod cst;
+pub mod diagnostic;
+pub mod doc;
+pub mod formatter;
+pub mod lint;
 pub mod symbol_table;
+#[cfg(test)]
+mod test_util;
 pub mod token;
 pub mod typed_ast;
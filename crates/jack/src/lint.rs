@@ -0,0 +1,538 @@
+//! Warnings about code that compiles but is likely to be a mistake.
+
+use crate::{
+    ast::Class,
+    control_flow_graph::CfgClass,
+    diagnostic::{Diagnostic, Severity, ToDiagnostic},
+    symbol_table::VarSymbol,
+    token::{Comment, Ident, Location, WithLoc},
+    typed_ast::{
+        TypedClass, TypedExpression, TypedLetStatement, TypedStatement, TypedSubroutineCall,
+        TypedTerm,
+    },
+};
+use std::collections::HashMap;
+use thiserror::Error;
+
+mod flow;
+mod null;
+mod syntax;
+mod typed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    UnusedField,
+    ReadBeforeAssign,
+    UnreachableCode,
+    DeadAssignment,
+    FieldShadowing,
+    UnusedResult,
+    NullDereference,
+    OperatorPrecedence,
+}
+
+impl Lint {
+    pub const ALL: [Self; 10] = [
+        Self::UnusedVariable,
+        Self::UnusedParameter,
+        Self::UnusedField,
+        Self::ReadBeforeAssign,
+        Self::UnreachableCode,
+        Self::DeadAssignment,
+        Self::FieldShadowing,
+        Self::UnusedResult,
+        Self::NullDereference,
+        Self::OperatorPrecedence,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::UnusedVariable => "unused-variable",
+            Self::UnusedParameter => "unused-parameter",
+            Self::UnusedField => "unused-field",
+            Self::ReadBeforeAssign => "read-before-assign",
+            Self::UnreachableCode => "unreachable-code",
+            Self::DeadAssignment => "dead-assignment",
+            Self::FieldShadowing => "field-shadowing",
+            Self::UnusedResult => "unused-result",
+            Self::NullDereference => "null-dereference",
+            Self::OperatorPrecedence => "operator-precedence",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|lint| lint.id() == id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The level of each lint. Lints are warnings unless configured otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, Level>,
+}
+
+#[derive(Debug, Error)]
+pub enum DirectiveError {
+    #[error("invalid lint directive: {}", _0)]
+    Invalid(Location),
+    #[error("unknown lint `{}`: {}", _1, _0)]
+    UnknownLint(Location, String),
+}
+
+impl ToDiagnostic for DirectiveError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::Invalid(loc) => Diagnostic::error("invalid lint directive")
+                .with_primary(*loc, "expected `lint: allow(<lint>, ...)`"),
+            Self::UnknownLint(loc, id) => Diagnostic::error(format!("unknown lint `{}`", id))
+                .with_primary(*loc, "in this directive")
+                .with_note(format!(
+                    "available lints: all, {}",
+                    Lint::ALL.map(|lint| lint.id()).join(", ")
+                )),
+        }
+    }
+}
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn set_all(&mut self, level: Level) {
+        for lint in Lint::ALL {
+            self.set(lint, level);
+        }
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    /// Returns the levels overridden by the directives in the comments of a file.
+    ///
+    /// A directive is a comment such as `// lint: allow(unused-variable) deny(all)`, where `all`
+    /// stands for every lint. Later directives take precedence.
+    pub fn with_directives(&self, comments: &[WithLoc<Comment>]) -> Result<Self, DirectiveError> {
+        let mut levels = self.clone();
+        for comment in comments {
+            let text = comment.data.text.as_str();
+            let text = match text.strip_prefix("//") {
+                Some(text) => text,
+                None => text.trim_start_matches("/*").trim_end_matches("*/"),
+            };
+            let mut rest = match text.trim().strip_prefix("lint:") {
+                Some(rest) => rest.trim(),
+                None => continue,
+            };
+            let invalid = || DirectiveError::Invalid(comment.loc);
+            while !rest.is_empty() {
+                let (level, args) = rest.split_once('(').ok_or_else(invalid)?;
+                let level = match level.trim() {
+                    "allow" => Level::Allow,
+                    "warn" => Level::Warn,
+                    "deny" => Level::Deny,
+                    _ => return Err(invalid()),
+                };
+                let (args, tail) = args.split_once(')').ok_or_else(invalid)?;
+                for id in args.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+                    if id == "all" {
+                        levels.set_all(level);
+                        continue;
+                    }
+                    let lint = Lint::from_id(id)
+                        .ok_or_else(|| DirectiveError::UnknownLint(comment.loc, id.to_owned()))?;
+                    levels.set(lint, level);
+                }
+                rest = tail.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+            }
+        }
+        Ok(levels)
+    }
+
+    /// Converts the warnings to diagnostics, dropping allowed ones and making denied ones errors.
+    pub fn apply<'a>(&self, warnings: impl IntoIterator<Item = &'a Warning>) -> Vec<Diagnostic> {
+        warnings
+            .into_iter()
+            .filter_map(|warning| match self.level(warning.lint) {
+                Level::Allow => None,
+                Level::Warn => Some(warning.to_diagnostic()),
+                Level::Deny => Some(Diagnostic {
+                    severity: Severity::Error,
+                    ..warning.to_diagnostic()
+                }),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub loc: Location,
+    pub label: String,
+    pub secondary: Vec<(Location, String)>,
+    pub notes: Vec<String>,
+}
+
+impl Warning {
+    fn new(
+        lint: Lint,
+        message: impl Into<String>,
+        loc: Location,
+        label: impl Into<String>,
+    ) -> Self {
+        Self {
+            lint,
+            message: message.into(),
+            loc,
+            label: label.into(),
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    fn with_secondary(mut self, loc: Location, message: impl Into<String>) -> Self {
+        self.secondary.push((loc, message.into()));
+        self
+    }
+
+    fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl ToDiagnostic for Warning {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let mut diag = Diagnostic::warning(&self.message)
+            .with_code(self.lint.id())
+            .with_primary(self.loc, &self.label);
+        for (loc, message) in &self.secondary {
+            diag = diag.with_secondary(*loc, message);
+        }
+        for note in &self.notes {
+            diag = diag.with_note(note);
+        }
+        diag
+    }
+}
+
+/// Runs all lints on a class, its typed AST and its control flow graph.
+pub fn check(ast: &Class, class: &TypedClass, cfg: &CfgClass) -> Vec<Warning> {
+    let mut warnings = vec![];
+    syntax::check(ast, &mut warnings);
+    typed::check(class, &mut warnings);
+    flow::check(cfg, &mut warnings);
+    null::check(cfg, &mut warnings);
+    warnings.sort_by_key(|warning| warning.loc.start);
+    warnings
+}
+
+/// Identifies a variable within a subroutine.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum VarKey {
+    Static(Ident),
+    Field(Ident),
+    Local(Ident),
+    Param(Ident),
+}
+
+impl VarKey {
+    fn new(var: &VarSymbol) -> Self {
+        let name = var.name().data.clone();
+        match var {
+            VarSymbol::StaticVariable(_) => Self::Static(name),
+            VarSymbol::Field(_) => Self::Field(name),
+            VarSymbol::LocalVariable(_) => Self::Local(name),
+            VarSymbol::Parameter(_) => Self::Param(name),
+        }
+    }
+
+    fn name(&self) -> &Ident {
+        match self {
+            Self::Static(name) | Self::Field(name) | Self::Local(name) | Self::Param(name) => name,
+        }
+    }
+
+    /// Returns `true` for locals and parameters, which are invisible from other subroutines.
+    fn is_local(&self) -> bool {
+        matches!(self, Self::Local(_) | Self::Param(_))
+    }
+}
+
+/// Calls `f` with each variable read by the expression and the location of the read.
+fn visit_reads(expr: &WithLoc<TypedExpression>, f: &mut impl FnMut(&VarSymbol, Location)) {
+    match &*expr.data.term {
+        TypedTerm::Int(_)
+        | TypedTerm::String(_)
+        | TypedTerm::Bool(_)
+        | TypedTerm::Null
+        | TypedTerm::This => {}
+        TypedTerm::Var(var) => f(var, expr.loc),
+        TypedTerm::Index(var, index) => {
+            f(var, expr.loc);
+            visit_reads(index, f);
+        }
+        TypedTerm::SubroutineCall(call) => visit_call_reads(call, f),
+        TypedTerm::UnaryOp(_, operand) => visit_reads(operand, f),
+        TypedTerm::BinaryOp(_, lhs, rhs) => {
+            visit_reads(lhs, f);
+            visit_reads(rhs, f);
+        }
+    }
+}
+
+fn visit_call_reads(call: &WithLoc<TypedSubroutineCall>, f: &mut impl FnMut(&VarSymbol, Location)) {
+    let args = match &call.data {
+        TypedSubroutineCall::Method(receiver, _, args) => {
+            if let Some(receiver) = receiver {
+                f(receiver, call.loc);
+            }
+            args
+        }
+        TypedSubroutineCall::Function(_, _, args)
+        | TypedSubroutineCall::Constructor(_, _, args) => args,
+    };
+    for arg in args {
+        visit_reads(arg, f);
+    }
+}
+
+/// Visits the reads of a `let` statement, and returns the variable assigned without an index.
+fn visit_let<'a>(
+    stmt: &'a WithLoc<TypedLetStatement>,
+    f: &mut impl FnMut(&VarSymbol, Location),
+) -> Option<&'a VarSymbol> {
+    match &stmt.data.target_index {
+        Some(index) => {
+            f(&stmt.data.target, stmt.loc);
+            visit_reads(index, f);
+            visit_reads(&stmt.data.expr, f);
+            None
+        }
+        None => {
+            visit_reads(&stmt.data.expr, f);
+            Some(&stmt.data.target)
+        }
+    }
+}
+
+/// Calls `on_read` and `on_write` with the variables read and assigned by the statements.
+fn visit_statements(
+    stmts: &[WithLoc<TypedStatement>],
+    on_read: &mut impl FnMut(&VarSymbol, Location),
+    on_write: &mut impl FnMut(&VarSymbol, Location),
+) {
+    for stmt in stmts {
+        match &stmt.data {
+            TypedStatement::Let(stmt) => {
+                if let Some(var) = visit_let(stmt, on_read) {
+                    on_write(var, stmt.loc);
+                }
+            }
+            TypedStatement::If(stmt) => {
+                visit_reads(&stmt.data.cond, on_read);
+                visit_statements(&stmt.data.then_stmts, on_read, on_write);
+                if let Some(else_stmts) = &stmt.data.else_stmts {
+                    visit_statements(else_stmts, on_read, on_write);
+                }
+            }
+            TypedStatement::While(stmt) => {
+                visit_reads(&stmt.data.cond, on_read);
+                visit_statements(&stmt.data.stmts, on_read, on_write);
+            }
+            TypedStatement::For(stmt) => {
+                if let Some(init) = &stmt.data.init {
+                    if let Some(var) = visit_let(init, on_read) {
+                        on_write(var, init.loc);
+                    }
+                }
+                if let Some(cond) = &stmt.data.cond {
+                    visit_reads(cond, on_read);
+                }
+                visit_statements(&stmt.data.stmts, on_read, on_write);
+                if let Some(step) = &stmt.data.step {
+                    if let Some(var) = visit_let(step, on_read) {
+                        on_write(var, step.loc);
+                    }
+                }
+            }
+            TypedStatement::Do(stmt) => visit_call_reads(&stmt.data.sub_call, on_read),
+            TypedStatement::Return(stmt) => {
+                if let Some(expr) = &stmt.data.expr {
+                    visit_reads(expr, on_read);
+                }
+            }
+            TypedStatement::Break | TypedStatement::Continue => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test_util::compile, token::Tokens};
+
+    fn lint(source: &str) -> Vec<(Lint, String)> {
+        let compiled = compile(source);
+        check(&compiled.ast.data, &compiled.typed.data, &compiled.cfg.data)
+            .into_iter()
+            .map(|w| {
+                let text = &source[w.loc.start as usize..w.loc.end as usize];
+                (w.lint, text.to_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unused() {
+        let source = "\
+class Main {
+    field int used, unused;
+    static int counter;
+    method int f(int a, int b, int _c) {
+        var int x, y, z;
+        let y = 1;
+        let counter = used;
+        return a;
+    }
+}
+";
+        assert_eq!(
+            lint(source),
+            [
+                (Lint::UnusedField, "unused".into()),
+                (Lint::UnusedField, "counter".into()),
+                (Lint::UnusedParameter, "b".into()),
+                (Lint::UnusedVariable, "x".into()),
+                (Lint::UnusedVariable, "y".into()),
+                (Lint::UnusedVariable, "z".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn flow() {
+        let source = "\
+class Main {
+    function int f(boolean c) {
+        var int x, y;
+        let x = 1;
+        if (c) {
+            let y = 2;
+        }
+        let x = x + y;
+        let y = 5;
+        return x;
+        let y = 0;
+    }
+}
+";
+        assert_eq!(
+            lint(source),
+            [
+                (Lint::ReadBeforeAssign, "y".into()),
+                (Lint::DeadAssignment, "let y = 5;".into()),
+                (Lint::UnreachableCode, "let y = 0;".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowing_and_results() {
+        let source = "\
+class Main {
+    field int x;
+    static int s;
+    method int get() {
+        return x + s;
+    }
+    function void f(int x) {
+        do Main.g(x);
+        return;
+    }
+    method void h() {
+        var int x, s;
+        let x = 0;
+        let s = x;
+        do get();
+        do Main.g(s);
+        return;
+    }
+    function int g(int a) {
+        return a;
+    }
+}
+";
+        assert_eq!(
+            lint(source),
+            [
+                (Lint::UnusedResult, "Main.g(x)".into()),
+                (Lint::FieldShadowing, "x".into()),
+                (Lint::FieldShadowing, "s".into()),
+                (Lint::UnusedResult, "get()".into()),
+                (Lint::UnusedResult, "Main.g(s)".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let source = "\
+class Main {
+    function boolean f(int a, int b) {
+        var int x;
+        let x = a * b + 1;
+        let x = (a + b) * x;
+        let x = a + b * x;
+        return (a < b) & (b < x) | (a = (1 - b / 2));
+    }
+}
+";
+        assert_eq!(
+            lint(source),
+            [
+                (Lint::OperatorPrecedence, "*".into()),
+                (Lint::OperatorPrecedence, "/".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn directives() {
+        let source = "\
+// lint: allow(unused-variable, unused-parameter) deny(dead-assignment)
+/* lint: warn(unused-parameter) */
+class Main {}
+";
+        let mut tokens = Tokens::from_reader(source.as_bytes());
+        for token in &mut tokens {
+            token.unwrap();
+        }
+        let levels = LintLevels::default()
+            .with_directives(tokens.comments())
+            .unwrap();
+        assert_eq!(levels.level(Lint::UnusedVariable), Level::Allow);
+        assert_eq!(levels.level(Lint::UnusedParameter), Level::Warn);
+        assert_eq!(levels.level(Lint::DeadAssignment), Level::Deny);
+        assert_eq!(levels.level(Lint::UnusedResult), Level::Warn);
+
+        let mut tokens = Tokens::from_reader("// lint: allow(unused)\n".as_bytes());
+        assert!(tokens.next().is_none());
+        assert!(matches!(
+            LintLevels::default().with_directives(tokens.comments()),
+            Err(DirectiveError::UnknownLint(_, id)) if id == "unused"
+        ));
+    }
+}
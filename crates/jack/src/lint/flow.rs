@@ -0,0 +1,235 @@
+use super::*;
+use crate::{
+    ast::Type,
+    control_flow_graph::{BasicBlock, BbId, CfgStatement, CfgSubroutine, Exit},
+};
+use std::collections::{HashMap, HashSet};
+
+pub(super) fn check(class: &CfgClass, warnings: &mut Vec<Warning>) {
+    for sub in &class.subs {
+        let blocks = reachable_blocks(&sub.data)
+            .into_iter()
+            .map(|block| (&block.data, events(&block.data)))
+            .collect::<Vec<_>>();
+        check_read_before_assign(&sub.data, &blocks, warnings);
+        check_dead_assignments(&blocks, warnings);
+    }
+}
+
+#[derive(Debug)]
+enum Event {
+    Read(VarKey, Location),
+    Write(VarKey, Location),
+}
+
+/// Returns the reads and writes of variables in the block, in execution order.
+fn events(block: &BasicBlock) -> Vec<Event> {
+    fn on_read(events: &mut Vec<Event>) -> impl FnMut(&VarSymbol, Location) + '_ {
+        |var, loc| events.push(Event::Read(VarKey::new(var), loc))
+    }
+
+    let mut events = vec![];
+    for stmt in &block.stmts {
+        match stmt {
+            CfgStatement::Let(stmt) => {
+                let target = visit_let(stmt, &mut on_read(&mut events));
+                if let Some(var) = target {
+                    events.push(Event::Write(VarKey::new(var), stmt.loc));
+                }
+            }
+            CfgStatement::Do(stmt) => {
+                visit_call_reads(&stmt.data.sub_call, &mut on_read(&mut events))
+            }
+        }
+    }
+    match &block.exit {
+        Exit::Return(Some(expr)) | Exit::If(expr, _, _) => {
+            visit_reads(expr, &mut on_read(&mut events))
+        }
+        Exit::Return(None) | Exit::Goto(_) | Exit::Unreachable => {}
+    }
+    events
+}
+
+pub(super) fn successors(exit: &Exit) -> Vec<BbId> {
+    match exit {
+        Exit::Goto(id) => vec![*id],
+        Exit::If(_, then_id, else_id) => vec![*then_id, *else_id],
+        Exit::Return(_) | Exit::Unreachable => vec![],
+    }
+}
+
+/// Returns the blocks reachable from the entry, in their original order.
+pub(super) fn reachable_blocks(sub: &CfgSubroutine) -> Vec<&WithLoc<BasicBlock>> {
+    let mut reachable = HashSet::new();
+    let mut visit_list = vec![sub.entry_id];
+    while let Some(id) = visit_list.pop() {
+        if reachable.insert(id) {
+            let block = &sub.blocks[sub.block_index_map[&id]];
+            visit_list.extend(successors(&block.data.exit));
+        }
+    }
+    sub.blocks
+        .iter()
+        .filter(|block| reachable.contains(&block.data.id))
+        .collect()
+}
+
+/// Reports primitive locals read before being assigned on some path from the entry.
+fn check_read_before_assign(
+    sub: &CfgSubroutine,
+    blocks: &[(&BasicBlock, Vec<Event>)],
+    warnings: &mut Vec<Warning>,
+) {
+    // locals assigned on every path (`must`) and on some path (`may`) at the end of each block
+    let mut must_out = HashMap::<BbId, HashSet<Ident>>::new();
+    let mut may_out = HashMap::<BbId, HashSet<Ident>>::new();
+    let entry_state = |block: &BasicBlock,
+                       must_out: &HashMap<BbId, HashSet<Ident>>,
+                       may_out: &HashMap<BbId, HashSet<Ident>>| {
+        if block.id == sub.entry_id {
+            return Some((HashSet::new(), HashSet::new()));
+        }
+        let mut must: Option<HashSet<Ident>> = None;
+        let mut may = HashSet::new();
+        for src_id in &block.src_ids {
+            if let Some(src_must) = must_out.get(src_id) {
+                must = Some(match must {
+                    Some(must) => must.intersection(src_must).cloned().collect(),
+                    None => src_must.clone(),
+                });
+            }
+            may.extend(may_out.get(src_id).into_iter().flatten().cloned());
+        }
+        must.map(|must| (must, may))
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (block, events) in blocks {
+            let (mut must, mut may) = match entry_state(block, &must_out, &may_out) {
+                Some(state) => state,
+                None => continue,
+            };
+            for event in events {
+                if let Event::Write(VarKey::Local(name), _) = event {
+                    must.insert(name.clone());
+                    may.insert(name.clone());
+                }
+            }
+            if must_out.get(&block.id) != Some(&must) {
+                must_out.insert(block.id, must);
+                changed = true;
+            }
+            if may_out.get(&block.id) != Some(&may) {
+                may_out.insert(block.id, may);
+                changed = true;
+            }
+        }
+    }
+
+    // objects and arrays are checked by the null-safety analysis instead
+    let mut reported = sub
+        .vars
+        .iter()
+        .filter(|var| matches!(var.data.ty.data, Type::Class(_)))
+        .map(|var| var.data.name.data.clone())
+        .collect::<HashSet<_>>();
+    for (block, events) in blocks {
+        let (mut must, mut may) = match entry_state(block, &must_out, &may_out) {
+            Some(state) => state,
+            None => continue,
+        };
+        for event in events {
+            match event {
+                Event::Read(VarKey::Local(name), loc) => {
+                    if must.contains(name) || !reported.insert(name.clone()) {
+                        continue;
+                    }
+                    let message = if may.contains(name) {
+                        format!("`{}` may be read before being assigned", name)
+                    } else {
+                        format!("`{}` is read before being assigned", name)
+                    };
+                    let mut warning =
+                        Warning::new(Lint::ReadBeforeAssign, message, *loc, "read here")
+                            .with_note("locals are implicitly initialized to 0");
+                    if let Some(var) = sub.vars.iter().find(|v| v.data.name.data == *name) {
+                        warning = warning.with_secondary(var.data.name.loc, "declared here");
+                    }
+                    warnings.push(warning);
+                }
+                Event::Write(VarKey::Local(name), _) => {
+                    must.insert(name.clone());
+                    may.insert(name.clone());
+                }
+                Event::Read(..) | Event::Write(..) => {}
+            }
+        }
+    }
+}
+
+/// Reports assignments to locals and parameters that are never read afterwards.
+fn check_dead_assignments(blocks: &[(&BasicBlock, Vec<Event>)], warnings: &mut Vec<Warning>) {
+    // variables never read are reported as unused instead
+    let read = blocks
+        .iter()
+        .flat_map(|(_, events)| events)
+        .filter_map(|event| match event {
+            Event::Read(key, _) => Some(key),
+            Event::Write(..) => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let live_out = |block: &BasicBlock, live_in: &HashMap<BbId, HashSet<VarKey>>| {
+        successors(&block.exit)
+            .iter()
+            .flat_map(|id| live_in.get(id).into_iter().flatten().cloned())
+            .collect::<HashSet<_>>()
+    };
+
+    let mut live_in = HashMap::<BbId, HashSet<VarKey>>::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (block, events) in blocks.iter().rev() {
+            let mut live = live_out(block, &live_in);
+            for event in events.iter().rev() {
+                match event {
+                    Event::Read(key, _) => live.insert(key.clone()),
+                    Event::Write(key, _) => live.remove(key),
+                };
+            }
+            if live_in.get(&block.id) != Some(&live) {
+                live_in.insert(block.id, live);
+                changed = true;
+            }
+        }
+    }
+
+    for (block, events) in blocks {
+        let mut live = live_out(block, &live_in);
+        for event in events.iter().rev() {
+            match event {
+                Event::Read(key, _) => {
+                    live.insert(key.clone());
+                }
+                Event::Write(key, loc) => {
+                    if key.is_local() && read.contains(key) && !live.contains(key) {
+                        warnings.push(
+                            Warning::new(
+                                Lint::DeadAssignment,
+                                format!("value assigned to `{}` is never read", key.name()),
+                                *loc,
+                                "assigned here",
+                            )
+                            .with_note("the value is overwritten or discarded before being read"),
+                        );
+                    }
+                    live.remove(key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Null-safety of object and array locals, which start as null.
+
+use super::{flow::reachable_blocks, *};
+use crate::{
+    ast::{BinaryOp, Type, UnaryOp},
+    control_flow_graph::{BasicBlock, BbId, CfgStatement, CfgSubroutine, Exit},
+};
+use std::collections::{HashMap, HashSet};
+
+pub(super) fn check(class: &CfgClass, warnings: &mut Vec<Warning>) {
+    for sub in &class.subs {
+        NullAnalysis::new(&sub.data).check(warnings);
+    }
+}
+
+/// Why a variable is null.
+#[derive(Debug, Clone, Copy)]
+enum Origin {
+    Declared(Location),
+    Assigned(Location),
+    Compared(Location),
+}
+
+impl Origin {
+    fn loc(&self) -> Location {
+        match self {
+            Self::Declared(loc) | Self::Assigned(loc) | Self::Compared(loc) => *loc,
+        }
+    }
+
+    fn label(&self, name: &Ident) -> (Location, String) {
+        match self {
+            Self::Declared(loc) => (
+                *loc,
+                format!("`{}` is declared here and starts as null", name),
+            ),
+            Self::Assigned(loc) => (*loc, format!("`{}` is set to null here", name)),
+            Self::Compared(loc) => (
+                *loc,
+                format!("`{}` is null when this condition holds", name),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Null {
+    No,
+    Yes(Origin),
+    Maybe(Origin),
+}
+
+impl Null {
+    fn join(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::No, Self::No) => Self::No,
+            (Self::Yes(origin), Self::Yes(_)) => Self::Yes(origin),
+            (Self::Yes(origin) | Self::Maybe(origin), _)
+            | (_, Self::Yes(origin) | Self::Maybe(origin)) => Self::Maybe(origin),
+        }
+    }
+
+    fn origin(&self) -> Option<Origin> {
+        match self {
+            Self::No => None,
+            Self::Yes(origin) | Self::Maybe(origin) => Some(*origin),
+        }
+    }
+}
+
+/// The nullness of the tracked locals. Untracked variables are assumed not to be null.
+type State = HashMap<Ident, Null>;
+
+fn same_state(a: &State, b: &State) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(name, null)| {
+            b.get(name)
+                .map(|other| std::mem::discriminant(null) == std::mem::discriminant(other))
+                .unwrap_or(false)
+        })
+}
+
+struct NullAnalysis<'a> {
+    sub: &'a CfgSubroutine,
+    blocks: Vec<&'a BasicBlock>,
+    /// The state at the end of each block.
+    out_states: HashMap<BbId, State>,
+}
+
+impl<'a> NullAnalysis<'a> {
+    fn new(sub: &'a CfgSubroutine) -> Self {
+        let blocks = reachable_blocks(sub)
+            .into_iter()
+            .map(|block| &block.data)
+            .collect();
+        let mut analysis = Self {
+            sub,
+            blocks,
+            out_states: HashMap::new(),
+        };
+        analysis.solve();
+        analysis
+    }
+
+    fn block(&self, id: BbId) -> &'a BasicBlock {
+        &self.sub.blocks[self.sub.block_index_map[&id]].data
+    }
+
+    fn is_reachable(&self, id: BbId) -> bool {
+        self.blocks.iter().any(|block| block.id == id)
+    }
+
+    /// Returns the state on the edge from `src_id` to `dest_id`, refined by the branch condition.
+    fn edge_state(&self, src_id: BbId, dest_id: BbId) -> Option<State> {
+        let mut state = self.out_states.get(&src_id)?.clone();
+        if let Exit::If(cond, then_id, else_id) = &self.block(src_id).exit {
+            if then_id != else_id {
+                refine(&mut state, cond, dest_id == *then_id);
+            }
+        }
+        Some(state)
+    }
+
+    fn in_state(&self, block: &BasicBlock) -> Option<State> {
+        if block.id == self.sub.entry_id {
+            return Some(
+                self.sub
+                    .vars
+                    .iter()
+                    .filter(|var| matches!(var.data.ty.data, Type::Class(_)))
+                    .map(|var| {
+                        let name = &var.data.name;
+                        (name.data.clone(), Null::Yes(Origin::Declared(name.loc)))
+                    })
+                    .collect(),
+            );
+        }
+        block
+            .src_ids
+            .iter()
+            .filter(|id| self.is_reachable(**id))
+            .filter_map(|id| self.edge_state(*id, block.id))
+            .reduce(|mut state, other| {
+                for (name, null) in &mut state {
+                    *null = null.join(other.get(name).copied().unwrap_or(Null::No));
+                }
+                state
+            })
+    }
+
+    fn solve(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.blocks.clone() {
+                let mut state = match self.in_state(block) {
+                    Some(state) => state,
+                    None => continue,
+                };
+                transfer(block, &mut state, &mut |_, _, _, _| {});
+                if !self
+                    .out_states
+                    .get(&block.id)
+                    .map(|out| same_state(out, &state))
+                    .unwrap_or(false)
+                {
+                    self.out_states.insert(block.id, state);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn check(&self, warnings: &mut Vec<Warning>) {
+        let mut reported = HashSet::new();
+        for block in &self.blocks {
+            let mut state = match self.in_state(block) {
+                Some(state) => state,
+                None => continue,
+            };
+            transfer(block, &mut state, &mut |name, null, loc, what| {
+                let (certain, origin) = match null {
+                    Null::No => return,
+                    Null::Yes(origin) => (true, origin),
+                    Null::Maybe(origin) => (false, origin),
+                };
+                if !reported.insert(name.clone()) {
+                    return;
+                }
+                let (message, label) = if certain {
+                    (format!("`{}` is null {}", name, what), "null here")
+                } else {
+                    (
+                        format!("`{}` may be null {}", name, what),
+                        "may be null here",
+                    )
+                };
+                let mut warning = Warning::new(Lint::NullDereference, message, loc, label)
+                    .with_note("null is the address 0, so the access reads or writes RAM[0]");
+                for (loc, message) in self.explain(block, name, origin) {
+                    warning = warning.with_secondary(loc, message);
+                }
+                warnings.push(warning);
+            });
+        }
+    }
+
+    /// Returns the labels of a path from the entry along which `name` is null at `block`.
+    fn explain(&self, block: &BasicBlock, name: &Ident, origin: Origin) -> Vec<(Location, String)> {
+        let mut steps = vec![];
+        let mut origin = origin;
+        let mut current = block;
+        let mut visited = HashSet::new();
+        while visited.insert(current.id) && current.id != self.sub.entry_id {
+            let null_in = |src_id: &BbId| {
+                self.edge_state(*src_id, current.id)
+                    .and_then(|state| state.get(name).copied())
+                    .filter(|null| null.origin().is_some())
+            };
+            // the null value may come from the block itself
+            let in_origin = self
+                .in_state(current)
+                .and_then(|state| state.get(name).and_then(|null| null.origin()));
+            match in_origin {
+                Some(_) if current.id != block.id => {}
+                Some(in_origin) if in_origin.loc().start == origin.loc().start => {}
+                _ => break,
+            }
+            let src = current
+                .src_ids
+                .iter()
+                .filter(|id| self.is_reachable(**id) && !visited.contains(*id))
+                .filter_map(|id| null_in(id).map(|null| (*id, null)))
+                .min_by_key(|(_, null)| matches!(null, Null::Maybe(_)));
+            let (src_id, null) = match src {
+                Some(src) => src,
+                None => break,
+            };
+            let src = self.block(src_id);
+            if let Exit::If(cond, then_id, else_id) = &src.exit {
+                if then_id != else_id {
+                    let taken = current.id == *then_id;
+                    let message = if matches!(src.id.label, "WHILE_EXP" | "FOR_EXP") {
+                        // the exit condition of loops is negated
+                        format!("assuming the loop condition is {}", !taken)
+                    } else {
+                        format!("assuming this condition is {}", taken)
+                    };
+                    steps.push((cond.loc, message));
+                }
+            }
+            origin = null.origin().unwrap_or(origin);
+            current = src;
+        }
+        steps.push(origin.label(name));
+        steps.reverse();
+        steps
+    }
+}
+
+/// Updates the state with the statements of the block, calling `on_deref` for each dereference of
+/// a tracked variable with its nullness.
+fn transfer(
+    block: &BasicBlock,
+    state: &mut State,
+    on_deref: &mut impl FnMut(&Ident, Null, Location, String),
+) {
+    let mut deref = |state: &State, var: &VarSymbol, loc: Location, what: String| {
+        if let Some(null) = state.get(&var.name().data) {
+            on_deref(&var.name().data, *null, loc, what);
+        }
+    };
+    for stmt in &block.stmts {
+        match stmt {
+            CfgStatement::Let(stmt) => {
+                let s = &stmt.data;
+                if s.target_index.is_some() {
+                    deref(state, &s.target, stmt.loc, "when indexed".into());
+                }
+                for expr in s.target_index.iter().chain([&s.expr]) {
+                    visit_derefs(expr, &mut |var, loc, what| deref(state, var, loc, what));
+                }
+                let name = &s.target.name().data;
+                if s.target_index.is_none() && state.contains_key(name) {
+                    let null = match &*s.expr.data.term {
+                        TypedTerm::Null | TypedTerm::Int(WithLoc { data: 0, .. }) => {
+                            Null::Yes(Origin::Assigned(stmt.loc))
+                        }
+                        TypedTerm::Var(var) => {
+                            state.get(&var.name().data).copied().unwrap_or(Null::No)
+                        }
+                        _ => Null::No,
+                    };
+                    state.insert(name.clone(), null);
+                }
+            }
+            CfgStatement::Do(stmt) => {
+                visit_call_derefs(&stmt.data.sub_call, &mut |var, loc, what| {
+                    deref(state, var, loc, what)
+                });
+            }
+        }
+    }
+    match &block.exit {
+        Exit::Return(Some(expr)) | Exit::If(expr, _, _) => {
+            visit_derefs(expr, &mut |var, loc, what| deref(state, var, loc, what));
+        }
+        Exit::Return(None) | Exit::Goto(_) | Exit::Unreachable => {}
+    }
+}
+
+/// Calls `f` with the variables indexed or used as receivers in the expression.
+fn visit_derefs(expr: &WithLoc<TypedExpression>, f: &mut impl FnMut(&VarSymbol, Location, String)) {
+    match &*expr.data.term {
+        TypedTerm::Int(_)
+        | TypedTerm::String(_)
+        | TypedTerm::Bool(_)
+        | TypedTerm::Null
+        | TypedTerm::This
+        | TypedTerm::Var(_) => {}
+        TypedTerm::Index(var, index) => {
+            visit_derefs(index, f);
+            f(var, expr.loc, "when indexed".into());
+        }
+        TypedTerm::SubroutineCall(call) => visit_call_derefs(call, f),
+        TypedTerm::UnaryOp(_, operand) => visit_derefs(operand, f),
+        TypedTerm::BinaryOp(_, lhs, rhs) => {
+            visit_derefs(lhs, f);
+            visit_derefs(rhs, f);
+        }
+    }
+}
+
+fn visit_call_derefs(
+    call: &WithLoc<TypedSubroutineCall>,
+    f: &mut impl FnMut(&VarSymbol, Location, String),
+) {
+    let args = match &call.data {
+        TypedSubroutineCall::Method(_, _, args)
+        | TypedSubroutineCall::Function(_, _, args)
+        | TypedSubroutineCall::Constructor(_, _, args) => args,
+    };
+    for arg in args {
+        visit_derefs(arg, f);
+    }
+    if let TypedSubroutineCall::Method(Some(receiver), name, _) = &call.data {
+        let what = format!(
+            "when calling `{}.{}`",
+            receiver.ty().data.as_str(),
+            name.data
+        );
+        f(receiver, call.loc, what);
+    }
+}
+
+/// Refines the state with the value of the condition on a branch.
+fn refine(state: &mut State, cond: &WithLoc<TypedExpression>, value: bool) {
+    match &*cond.data.term {
+        TypedTerm::UnaryOp(op, operand) if matches!(op.data, UnaryOp::Not) => {
+            refine(state, operand, !value)
+        }
+        TypedTerm::BinaryOp(op, lhs, rhs) if matches!(op.data, BinaryOp::Eq) => {
+            let is_null = |expr: &WithLoc<TypedExpression>| {
+                matches!(
+                    &*expr.data.term,
+                    TypedTerm::Null | TypedTerm::Int(WithLoc { data: 0, .. })
+                )
+            };
+            let var = match (&*lhs.data.term, &*rhs.data.term) {
+                (TypedTerm::Var(var), _) if is_null(rhs) => var,
+                (_, TypedTerm::Var(var)) if is_null(lhs) => var,
+                _ => return,
+            };
+            if let Some(null) = state.get_mut(&var.name().data) {
+                *null = if value {
+                    Null::Yes(Origin::Compared(cond.loc))
+                } else {
+                    Null::No
+                };
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{diagnostic::ToDiagnostic, test_util::compile};
+    use std::path::Path;
+
+    fn check_source(source: &str) -> Vec<String> {
+        let cfg = compile(source).cfg;
+        let mut warnings = vec![];
+        check(&cfg.data, &mut warnings);
+        warnings
+            .iter()
+            .map(|w| w.to_diagnostic().render(Path::new("Main.jack"), source))
+            .collect()
+    }
+
+    #[test]
+    fn null_array() {
+        let source = "\
+class Main {
+    function void main() {
+        var Array a;
+        let a[0] = 1;
+        return;
+    }
+}
+";
+        assert_eq!(
+            check_source(source),
+            ["\
+warning[null-dereference]: `a` is null when indexed
+ --> Main.jack:4:9
+  |
+3 |         var Array a;
+  |                   - `a` is declared here and starts as null
+  |
+4 |         let a[0] = 1;
+  |         ^^^^^^^^^^^^^ null here
+  = note: null is the address 0, so the access reads or writes RAM[0]
+"]
+        );
+    }
+
+    #[test]
+    fn path() {
+        let source = "\
+class Main {
+    method void f(boolean c) {
+        var Main m, n;
+        if (c) {
+            let m = this;
+        }
+        do m.f(c);
+        let n = m;
+        if (~(n = null)) {
+            do n.f(c);
+        }
+        return;
+    }
+}
+";
+        assert_eq!(
+            check_source(source),
+            ["\
+warning[null-dereference]: `m` may be null when calling `Main.f`
+ --> Main.jack:7:12
+  |
+3 |         var Main m, n;
+  |                  - `m` is declared here and starts as null
+  |
+4 |         if (c) {
+  |             - assuming this condition is false
+  |
+7 |         do m.f(c);
+  |            ^^^^^^ may be null here
+  = note: null is the address 0, so the access reads or writes RAM[0]
+"]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use super::*;
+use crate::ast::{Class, Expression, LetStatement, Statement, SubroutineCall, Term};
+
+pub(super) fn check(class: &Class, warnings: &mut Vec<Warning>) {
+    for constant in &class.consts {
+        check_expression(&constant.data.expr.data, warnings);
+    }
+    for sub in &class.subs {
+        check_statements(&sub.data.body.data.stmts.data.0, warnings);
+    }
+}
+
+fn check_statements(stmts: &[WithLoc<Statement>], warnings: &mut Vec<Warning>) {
+    for stmt in stmts {
+        match &stmt.data {
+            Statement::Let(stmt) => check_let(&stmt.data, warnings),
+            Statement::If(stmt) => {
+                check_expression(&stmt.data.cond.data, warnings);
+                check_statements(&stmt.data.then_stmts.data.0, warnings);
+                if let Some(else_stmts) = &stmt.data.else_stmts {
+                    check_statements(&else_stmts.data.0, warnings);
+                }
+            }
+            Statement::While(stmt) => {
+                check_expression(&stmt.data.cond.data, warnings);
+                check_statements(&stmt.data.stmts.data.0, warnings);
+            }
+            Statement::For(stmt) => {
+                if let Some(init) = &stmt.data.init {
+                    check_let(&init.data, warnings);
+                }
+                if let Some(cond) = &stmt.data.cond {
+                    check_expression(&cond.data, warnings);
+                }
+                if let Some(step) = &stmt.data.step {
+                    check_let(&step.data, warnings);
+                }
+                check_statements(&stmt.data.stmts.data.0, warnings);
+            }
+            Statement::Do(stmt) => check_call(&stmt.data.sub_call.data, warnings),
+            Statement::Return(stmt) => {
+                if let Some(expr) = &stmt.data.expr {
+                    check_expression(&expr.data, warnings);
+                }
+            }
+            Statement::Break | Statement::Continue => {}
+        }
+    }
+}
+
+fn check_let(stmt: &LetStatement, warnings: &mut Vec<Warning>) {
+    if let Some(index) = &stmt.target_index {
+        check_expression(&index.data, warnings);
+    }
+    check_expression(&stmt.expr.data, warnings);
+}
+
+/// Reports expressions whose left-to-right evaluation differs from the conventional precedence,
+/// such as `a + b * c`.
+fn check_expression(expr: &Expression, warnings: &mut Vec<Warning>) {
+    check_term(&expr.term.data, warnings);
+    for (_, operand) in &expr.binary_ops {
+        check_term(&operand.data, warnings);
+    }
+
+    let ops = expr.binary_ops.iter().map(|(op, _)| op).collect::<Vec<_>>();
+    let misleading = ops
+        .windows(2)
+        .find(|pair| pair[1].data.precedence() > pair[0].data.precedence());
+    if let Some(pair) = misleading {
+        let (first, second) = (pair[0].data.symbol(), pair[1].data.symbol());
+        warnings.push(
+            Warning::new(
+                Lint::OperatorPrecedence,
+                format!("`{}` is evaluated before `{}`", first, second),
+                pair[1].loc,
+                format!("applied to the result of `{}`", first),
+            )
+            .with_secondary(pair[0].loc, "evaluated first")
+            .with_note(format!(
+                "operators are evaluated from left to right, so `a {0} b {1} c` is `(a {0} b) {1} c`",
+                first, second
+            ))
+            .with_note("add parentheses to make the grouping explicit"),
+        );
+    }
+}
+
+fn check_term(term: &Term, warnings: &mut Vec<Warning>) {
+    match term {
+        Term::IntConstant(_)
+        | Term::StringConstant(_)
+        | Term::CharConstant(_)
+        | Term::KeywordConstant(_)
+        | Term::Variable(_)
+        | Term::ClassConstant(..) => {}
+        Term::Index(_, index) => check_expression(&index.data, warnings),
+        Term::SubroutineCall(call) => check_call(&call.data, warnings),
+        Term::Expression(expr) => check_expression(&expr.data, warnings),
+        Term::UnaryOp(_, operand) => check_term(&operand.data, warnings),
+    }
+}
+
+fn check_call(call: &SubroutineCall, warnings: &mut Vec<Warning>) {
+    let args = match call {
+        SubroutineCall::SubroutineCall(_, args) | SubroutineCall::PropertyCall(_, _, args) => args,
+    };
+    for arg in &args.data.0 {
+        check_expression(&arg.data, warnings);
+    }
+}
@@ -0,0 +1,218 @@
+use super::*;
+use crate::{
+    ast::{ReturnType, SubroutineKind},
+    typed_ast::{TypedSubroutine, Variable},
+};
+use std::collections::HashSet;
+
+pub(super) fn check(class: &TypedClass, warnings: &mut Vec<Warning>) {
+    let mut class_reads = HashSet::new();
+    for sub in &class.subs {
+        let mut reads = HashSet::new();
+        let mut writes = HashSet::new();
+        visit_statements(
+            &sub.data.stmts,
+            &mut |var, _| {
+                reads.insert(VarKey::new(var));
+            },
+            &mut |var, _| {
+                writes.insert(VarKey::new(var));
+            },
+        );
+        check_locals(&sub.data, &reads, &writes, warnings);
+        check_shadowing(class, &sub.data, warnings);
+        check_statements(class, &sub.data.stmts, warnings);
+        class_reads.extend(reads.into_iter().filter(|key| !key.is_local()));
+    }
+
+    let unread = |vars: &'_ [WithLoc<Variable>], key: fn(Ident) -> VarKey| {
+        vars.iter()
+            .filter(|var| !class_reads.contains(&key(var.data.name.data.clone())))
+            .map(|var| var.data.name.clone())
+            .collect::<Vec<_>>()
+    };
+    for name in unread(&class.fields, VarKey::Field) {
+        warnings.push(Warning::new(
+            Lint::UnusedField,
+            format!("field `{}` is never read", name.data),
+            name.loc,
+            "never read",
+        ));
+    }
+    for name in unread(&class.static_vars, VarKey::Static) {
+        warnings.push(Warning::new(
+            Lint::UnusedField,
+            format!("static variable `{}` is never read", name.data),
+            name.loc,
+            "never read",
+        ));
+    }
+}
+
+/// Reports unused parameters and locals. Names starting with `_` are exempt.
+fn check_locals(
+    sub: &TypedSubroutine,
+    reads: &HashSet<VarKey>,
+    writes: &HashSet<VarKey>,
+    warnings: &mut Vec<Warning>,
+) {
+    let exempt = |name: &Ident| name.as_str().starts_with('_');
+    for param in &sub.params {
+        let name = &param.data.name;
+        if exempt(&name.data) || reads.contains(&VarKey::Param(name.data.clone())) {
+            continue;
+        }
+        warnings.push(Warning::new(
+            Lint::UnusedParameter,
+            format!("unused parameter `{}`", name.data),
+            name.loc,
+            "never read",
+        ));
+    }
+    for var in &sub.vars {
+        let name = &var.data.name;
+        let key = VarKey::Local(name.data.clone());
+        if exempt(&name.data) || reads.contains(&key) {
+            continue;
+        }
+        let warning = if writes.contains(&key) {
+            Warning::new(
+                Lint::UnusedVariable,
+                format!("variable `{}` is assigned but never read", name.data),
+                name.loc,
+                "never read",
+            )
+        } else {
+            Warning::new(
+                Lint::UnusedVariable,
+                format!("unused variable `{}`", name.data),
+                name.loc,
+                "never used",
+            )
+        };
+        warnings.push(warning);
+    }
+}
+
+/// Reports parameters and locals hiding the class variables accessible from the subroutine.
+fn check_shadowing(class: &TypedClass, sub: &TypedSubroutine, warnings: &mut Vec<Warning>) {
+    let has_this = !matches!(sub.kind.data, SubroutineKind::Function);
+    let class_var = |name: &Ident| {
+        let field = class
+            .fields
+            .iter()
+            .filter(|_| has_this)
+            .find(|f| f.data.name.data == *name)
+            .map(|f| ("field", &f.data.name));
+        field.or_else(|| {
+            class
+                .static_vars
+                .iter()
+                .find(|v| v.data.name.data == *name)
+                .map(|v| ("static variable", &v.data.name))
+        })
+    };
+    let vars = sub
+        .params
+        .iter()
+        .map(|p| ("parameter", p))
+        .chain(sub.vars.iter().map(|v| ("local variable", v)));
+    for (kind, var) in vars {
+        let name = &var.data.name;
+        if let Some((class_kind, class_name)) = class_var(&name.data) {
+            warnings.push(
+                Warning::new(
+                    Lint::FieldShadowing,
+                    format!("{} `{}` shadows a {}", kind, name.data, class_kind),
+                    name.loc,
+                    format!("shadows the {}", class_kind),
+                )
+                .with_secondary(class_name.loc, format!("{} declared here", class_kind)),
+            );
+        }
+    }
+}
+
+/// Reports unreachable statements and discarded results in the statement list.
+fn check_statements(
+    class: &TypedClass,
+    stmts: &[WithLoc<TypedStatement>],
+    warnings: &mut Vec<Warning>,
+) {
+    for (i, stmt) in stmts.iter().enumerate() {
+        match &stmt.data {
+            TypedStatement::Let(_)
+            | TypedStatement::Return(_)
+            | TypedStatement::Break
+            | TypedStatement::Continue => {}
+            TypedStatement::If(stmt) => {
+                check_statements(class, &stmt.data.then_stmts, warnings);
+                if let Some(else_stmts) = &stmt.data.else_stmts {
+                    check_statements(class, else_stmts, warnings);
+                }
+            }
+            TypedStatement::While(stmt) => check_statements(class, &stmt.data.stmts, warnings),
+            TypedStatement::For(stmt) => check_statements(class, &stmt.data.stmts, warnings),
+            TypedStatement::Do(stmt) => {
+                if let ReturnType::Type(ty) = &stmt.data.return_type {
+                    let call = &stmt.data.sub_call;
+                    warnings.push(Warning::new(
+                        Lint::UnusedResult,
+                        format!("unused return value of `{}`", call_name(class, &call.data)),
+                        call.loc,
+                        format!("the returned `{}` is discarded", ty.data.as_str()),
+                    ));
+                }
+            }
+        }
+
+        let rest = &stmts[i + 1..];
+        if diverges(stmt) && !rest.is_empty() {
+            let loc = rest[0].loc.to(rest[rest.len() - 1].loc);
+            warnings.push(
+                Warning::new(
+                    Lint::UnreachableCode,
+                    "unreachable statement",
+                    loc,
+                    "unreachable statement",
+                )
+                .with_secondary(stmt.loc, "any code following this is unreachable"),
+            );
+            break;
+        }
+    }
+}
+
+/// Returns `true` if the statement always returns or jumps out of the enclosing statement list.
+fn diverges(stmt: &WithLoc<TypedStatement>) -> bool {
+    let list_diverges = |stmts: &[WithLoc<TypedStatement>]| stmts.iter().any(diverges);
+    match &stmt.data {
+        TypedStatement::Return(_) | TypedStatement::Break | TypedStatement::Continue => true,
+        TypedStatement::If(stmt) => match &stmt.data.else_stmts {
+            Some(else_stmts) => list_diverges(&stmt.data.then_stmts) && list_diverges(else_stmts),
+            None => false,
+        },
+        TypedStatement::Let(_)
+        | TypedStatement::While(_)
+        | TypedStatement::For(_)
+        | TypedStatement::Do(_) => false,
+    }
+}
+
+fn call_name(class: &TypedClass, call: &TypedSubroutineCall) -> String {
+    let (class_name, name) = match call {
+        TypedSubroutineCall::Method(Some(receiver), name, _) => {
+            (receiver.ty().data.as_str().to_owned(), name)
+        }
+        TypedSubroutineCall::Method(None, name, _)
+        | TypedSubroutineCall::Function(None, name, _)
+        | TypedSubroutineCall::Constructor(None, name, _) => {
+            (class.name.data.as_str().to_owned(), name)
+        }
+        TypedSubroutineCall::Function(Some(class_name), name, _)
+        | TypedSubroutineCall::Constructor(Some(class_name), name, _) => {
+            (class_name.data.as_str().to_owned(), name)
+        }
+    };
+    format!("{}.{}", class_name, name.data)
+}
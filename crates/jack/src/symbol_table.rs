@@ -1,11 +1,18 @@
 use vm::Segment;
 
 use crate::{
-    ast::{ReturnType, SubroutineKind, Type},
+    ast::{Expression, ReturnType, SubroutineKind, Type},
     token::{Ident, WithLoc},
     typed_ast::Variable,
 };
-use std::{borrow::Borrow, collections::HashMap, hash::Hash, path::PathBuf};
+use serde::{Serialize, Serializer};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    path::{Path, PathBuf},
+};
 
 mod builtin;
 pub(crate) mod extend;
@@ -21,6 +28,7 @@ pub(crate) struct ExternalClassSymbolTable {
     path: PathBuf,
     methods: HashMap<Ident, Method>,
     class_methods: HashMap<Ident, ClassMethod>,
+    consts: HashMap<Ident, Constant>,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +55,7 @@ pub(crate) enum Symbol {
     ClassMethod(ClassMethod),
     LocalVariable(LocalVariable),
     Parameter(Parameter),
+    Constant(Constant),
 }
 
 impl Symbol {
@@ -56,7 +65,9 @@ impl Symbol {
             Self::Field(f) => Some(VarSymbol::Field(f.clone())),
             Self::LocalVariable(v) => Some(VarSymbol::LocalVariable(v.clone())),
             Self::Parameter(p) => Some(VarSymbol::Parameter(p.clone())),
-            Self::Class(_) | Symbol::Method(_) | Symbol::ClassMethod(_) => None,
+            Self::Class(_) | Symbol::Method(_) | Symbol::ClassMethod(_) | Symbol::Constant(_) => {
+                None
+            }
         }
     }
 
@@ -68,7 +79,8 @@ impl Symbol {
             | Self::StaticVariable(_)
             | Self::Field(_)
             | Self::LocalVariable(_)
-            | Self::Parameter(_) => None,
+            | Self::Parameter(_)
+            | Self::Constant(_) => None,
         }
     }
 
@@ -95,6 +107,15 @@ pub enum SubroutineSymbol {
 }
 
 impl VarSymbol {
+    pub(crate) fn name(&self) -> &WithLoc<Ident> {
+        match self {
+            Self::StaticVariable(v) => &v.name,
+            Self::Field(f) => &f.name,
+            Self::LocalVariable(v) => &v.name,
+            Self::Parameter(p) => &p.name,
+        }
+    }
+
     pub(crate) fn ty(&self) -> &WithLoc<Type> {
         match self {
             Self::StaticVariable(v) => &v.ty,
@@ -114,6 +135,31 @@ impl VarSymbol {
     }
 }
 
+/// Serialized with the segment and slot the variable is resolved to.
+impl Serialize for VarSymbol {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Resolved<'a> {
+            name: &'a WithLoc<Ident>,
+            ty: &'a WithLoc<Type>,
+            segment: &'static str,
+            slot: usize,
+        }
+
+        let (segment, slot) = self.segment_slot();
+        Resolved {
+            name: self.name(),
+            ty: self.ty(),
+            segment: segment.as_str(),
+            slot,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl SubroutineSymbol {
     pub(crate) fn params(&self) -> &[WithLoc<Variable>] {
         match self {
@@ -144,6 +190,14 @@ pub(crate) enum ClassMethodKind {
     Function,
 }
 
+/// A compile-time constant, substituted by its value wherever it is used.
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub(crate) name: WithLoc<Ident>,
+    pub(crate) ty: WithLoc<Type>,
+    pub(crate) expr: WithLoc<Expression>,
+}
+
 #[derive(Debug, Clone)]
 pub struct StaticVariable {
     pub name: WithLoc<Ident>,
@@ -176,7 +230,7 @@ impl SubroutineSymbolTable<'_> {
     pub(crate) fn get<Q>(&self, name: &Q) -> Option<&Symbol>
     where
         Ident: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         let is_function = matches!(self.kind, SubroutineKind::Function);
         let sym = self.table.get(name).or_else(|| self.outer.get(name))?;
@@ -186,7 +240,8 @@ impl SubroutineSymbolTable<'_> {
                 | Symbol::StaticVariable(_)
                 | Symbol::ClassMethod(_)
                 | Symbol::LocalVariable(_)
-                | Symbol::Parameter(_),
+                | Symbol::Parameter(_)
+                | Symbol::Constant(_),
                 _,
             )
             | (Symbol::Method(_) | Symbol::Field(_), false) => Some(sym),
@@ -205,13 +260,163 @@ impl SubroutineSymbolTable<'_> {
     pub(crate) fn class_name(&self) -> &WithLoc<Ident> {
         self.outer.class_name()
     }
+
+    pub(crate) fn class(&self) -> &InternalClassSymbolTable<'_> {
+        self.outer
+    }
+}
+
+/// A class registered in the global symbol table.
+#[derive(Debug, Clone, Copy)]
+pub struct ClassSymbol<'a>(&'a ExternalClassSymbolTable);
+
+/// The signature of a subroutine visible from other classes.
+#[derive(Debug, Clone, Copy)]
+pub struct SubroutineSignature<'a> {
+    pub class_name: &'a Ident,
+    pub kind: SubroutineKind,
+    pub name: &'a WithLoc<Ident>,
+    pub return_type: &'a ReturnType,
+    pub params: &'a [WithLoc<Variable>],
+}
+
+impl fmt::Display for SubroutineSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}.{}(",
+            self.kind.as_str(),
+            self.return_type.as_str(),
+            self.class_name.as_str(),
+            self.name.data.as_str()
+        )?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "{} {}",
+                param.data.ty.data.as_str(),
+                param.data.name.data.as_str()
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl<'a> ClassSymbol<'a> {
+    pub fn name(&self) -> &'a WithLoc<Ident> {
+        &self.0.class_name
+    }
+
+    pub fn path(&self) -> &'a Path {
+        &self.0.path
+    }
+
+    pub fn subroutine(&self, name: &str) -> Option<SubroutineSignature<'a>> {
+        let class_name = &self.0.class_name.data;
+        self.0
+            .method(name)
+            .map(|m| m.signature(class_name))
+            .or_else(|| self.0.class_method(name).map(|m| m.signature(class_name)))
+    }
+
+    /// Returns the subroutines of the class, sorted by name.
+    pub fn subroutines(&self) -> Vec<SubroutineSignature<'a>> {
+        let class_name = &self.0.class_name.data;
+        let mut subs = self
+            .0
+            .methods
+            .values()
+            .map(|m| m.signature(class_name))
+            .chain(
+                self.0
+                    .class_methods
+                    .values()
+                    .map(|m| m.signature(class_name)),
+            )
+            .collect::<Vec<_>>();
+        subs.sort_by(|a, b| a.name.data.as_str().cmp(b.name.data.as_str()));
+        subs
+    }
+}
+
+impl Method {
+    fn signature<'a>(&'a self, class_name: &'a Ident) -> SubroutineSignature<'a> {
+        SubroutineSignature {
+            class_name,
+            kind: SubroutineKind::Method,
+            name: &self.name,
+            return_type: &self.return_type.data,
+            params: &self.params,
+        }
+    }
+}
+
+impl ClassMethod {
+    fn signature<'a>(&'a self, class_name: &'a Ident) -> SubroutineSignature<'a> {
+        SubroutineSignature {
+            class_name,
+            kind: match self.kind {
+                ClassMethodKind::Constructor => SubroutineKind::Constructor,
+                ClassMethodKind::Function => SubroutineKind::Function,
+            },
+            name: &self.name,
+            return_type: &self.return_type.data,
+            params: &self.params,
+        }
+    }
 }
 
 impl GlobalSymbolTable {
+    pub fn class(&self, name: &str) -> Option<ClassSymbol<'_>> {
+        self.get(name).and_then(Symbol::to_class).map(ClassSymbol)
+    }
+
+    /// Returns the classes in the table, sorted by name.
+    pub fn classes(&self) -> Vec<ClassSymbol<'_>> {
+        let mut classes = self
+            .table
+            .values()
+            .filter_map(Symbol::to_class)
+            .map(ClassSymbol)
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.name().data.as_str().cmp(b.name().data.as_str()));
+        classes
+    }
+
+    /// Returns what other classes depend on in the class: the signatures of its subroutines and
+    /// the values of its constants, one per line. The code generated for a class only changes
+    /// with its own source and the interfaces of the classes it uses.
+    pub fn class_interface(&self, name: &str) -> Option<String> {
+        let class = self.get(name)?.to_class()?;
+        let mut lines = ClassSymbol(class)
+            .subroutines()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let mut consts = class.consts.values().collect::<Vec<_>>();
+        consts.sort_by(|a, b| a.name.data.as_str().cmp(b.name.data.as_str()));
+        for constant in consts {
+            let value = match self.constant_value(class, constant) {
+                Ok((ty, n)) => format!("{} {}", ty.as_str(), n),
+                Err(_) => "?".to_owned(),
+            };
+            lines.push(format!(
+                "const {}.{} = {}",
+                class.class_name.data.as_str(),
+                constant.name.data.as_str(),
+                value
+            ));
+        }
+        Some(lines.join("\n"))
+    }
+
     pub(crate) fn get<Q>(&self, name: &Q) -> Option<&Symbol>
     where
         Ident: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self.table.get(name)
     }
@@ -228,7 +433,7 @@ impl ExternalClassSymbolTable {
     pub(crate) fn method<Q>(&self, name: &Q) -> Option<&Method>
     where
         Ident: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self.methods.get(name)
     }
@@ -236,26 +441,54 @@ impl ExternalClassSymbolTable {
     pub(crate) fn class_method<Q>(&self, name: &Q) -> Option<&ClassMethod>
     where
         Ident: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self.class_methods.get(name)
     }
+
+    pub(crate) fn constant<Q>(&self, name: &Q) -> Option<&Constant>
+    where
+        Ident: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.consts.get(name)
+    }
+
+    pub(crate) fn class_name(&self) -> &WithLoc<Ident> {
+        &self.class_name
+    }
 }
 
 impl InternalClassSymbolTable<'_> {
     pub(crate) fn get<Q>(&self, name: &Q) -> Option<&Symbol>
     where
         Ident: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
         self.table.get(name).or_else(|| self.outer.get(name))
     }
 
+    /// Returns a constant of this class, ignoring the symbols of other classes.
+    pub(crate) fn constant<Q>(&self, name: &Q) -> Option<&Constant>
+    where
+        Ident: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.table.get(name) {
+            Some(Symbol::Constant(c)) => Some(c),
+            _ => None,
+        }
+    }
+
     pub(crate) fn is_valid_type(&self, ty: &Type) -> bool {
         self.outer.is_valid_type(ty)
     }
 
-    fn class_name(&self) -> &WithLoc<Ident> {
+    pub(crate) fn global(&self) -> &GlobalSymbolTable {
+        self.outer
+    }
+
+    pub(crate) fn class_name(&self) -> &WithLoc<Ident> {
         &self.class_name
     }
 }
@@ -270,6 +503,19 @@ impl Symbol {
             Symbol::ClassMethod(m) => &m.name,
             Symbol::LocalVariable(v) => &v.name,
             Symbol::Parameter(p) => &p.name,
+            Symbol::Constant(c) => &c.name,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shareable() {
+        // classes are resolved concurrently against the same table
+        fn assert_shareable<T: Send + Sync>() {}
+        assert_shareable::<GlobalSymbolTable>();
+    }
+}
@@ -210,6 +210,7 @@ impl ClassDef<'_> {
                 path,
                 methods,
                 class_methods,
+                consts: HashMap::new(),
             }),
         );
     }
@@ -1,8 +1,10 @@
 use super::*;
 use crate::{
     ast::{
-        Class, ClassVarDec, ClassVarKind, ParameterList, Subroutine, SubroutineBody, SubroutineKind,
+        Class, ClassVarDec, ClassVarKind, ConstDec, ParameterList, Subroutine, SubroutineBody,
+        SubroutineKind,
     },
+    diagnostic::{Diagnostic, ToDiagnostic},
     token::Location,
 };
 use either::Either;
@@ -31,6 +33,42 @@ pub enum SymbolTableExtendError {
     DuplicateSubroutineSymbol(WithLoc<Ident>, Location),
 }
 
+impl ToDiagnostic for SymbolTableExtendError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let duplicate = |what: &str, name: &WithLoc<Ident>, first: &Location| {
+            Diagnostic::error(format!("{} `{}` is already defined", what, name.data))
+                .with_primary(name.loc, "redefined here")
+                .with_secondary(*first, "first defined here")
+        };
+        match self {
+            Self::InvalidPath(_) => Diagnostic::error(self.to_string()),
+            Self::ClassNameMismatch(file_name, name) => Diagnostic::error(format!(
+                "class name `{}` does not match file name `{}`",
+                name.data, file_name
+            ))
+            .with_primary(name.loc, format!("expected `{}`", file_name)),
+            Self::DuplicateClass(name, path, first) => {
+                Diagnostic::error(format!("class `{}` is already defined", name.data))
+                    .with_primary(name.loc, "redefined here")
+                    .with_secondary_in(path, *first, "first defined here")
+            }
+            Self::DuplicateClassMethod(name, first) => duplicate("class method", name, first),
+            Self::DuplicateMethod(name, first) => duplicate("method", name, first),
+            Self::InvalidConstructorReturnType(ty) => {
+                Diagnostic::error("constructor must return its class").with_primary(
+                    ty.loc,
+                    format!("invalid return type `{}`", ty.data.as_str()),
+                )
+            }
+            Self::DuplicateProperty(name, first) => duplicate("property", name, first),
+            Self::DuplicateClassSymbol(name, first) => duplicate("class symbol", name, first),
+            Self::DuplicateSubroutineSymbol(name, first) => {
+                duplicate("subroutine symbol", name, first)
+            }
+        }
+    }
+}
+
 fn convert_params(params: &WithLoc<ParameterList>) -> Vec<WithLoc<Variable>> {
     params
         .data
@@ -45,6 +83,15 @@ fn convert_params(params: &WithLoc<ParameterList>) -> Vec<WithLoc<Variable>> {
         .collect()
 }
 
+fn convert_const(constant: &WithLoc<ConstDec>) -> Constant {
+    let ConstDec { ty, name, expr } = &constant.data;
+    Constant {
+        name: name.clone(),
+        ty: ty.clone(),
+        expr: expr.clone(),
+    }
+}
+
 impl GlobalSymbolTable {
     pub fn extend_with_class(
         &mut self,
@@ -54,6 +101,7 @@ impl GlobalSymbolTable {
         let path = path.as_ref();
         let Class {
             name: class_name,
+            consts: _,
             vars: _,
             subs: _,
         } = class;
@@ -96,12 +144,28 @@ impl ExternalClassSymbolTable {
     fn from_class(class: &Class, path: &Path) -> Result<Self, SymbolTableExtendError> {
         let Class {
             name: class_name,
+            consts: const_decs,
             vars: _,
             subs,
         } = class;
 
         let mut methods = HashMap::<Ident, Method>::new();
         let mut class_methods = HashMap::<Ident, ClassMethod>::new();
+        let mut consts = HashMap::<Ident, Constant>::new();
+
+        for constant in const_decs.iter().map(convert_const) {
+            match consts.entry(constant.name.data.clone()) {
+                Entry::Occupied(ent) => {
+                    return Err(SymbolTableExtendError::DuplicateClassSymbol(
+                        constant.name.clone(),
+                        ent.get().name.loc,
+                    ))
+                }
+                Entry::Vacant(ent) => {
+                    ent.insert(constant);
+                }
+            }
+        }
 
         for sub in subs {
             let Subroutine {
@@ -167,6 +231,7 @@ impl ExternalClassSymbolTable {
             path: path.to_owned(),
             methods,
             class_methods,
+            consts,
         })
     }
 }
@@ -178,6 +243,7 @@ impl<'a> InternalClassSymbolTable<'a> {
     ) -> Result<Self, SymbolTableExtendError> {
         let Class {
             name: class_name,
+            consts,
             vars,
             subs,
         } = class;
@@ -250,9 +316,11 @@ impl<'a> InternalClassSymbolTable<'a> {
             }
         }
 
-        let tables_syms = static_vars
-            .into_iter()
-            .map(Symbol::StaticVariable)
+        let tables_syms = consts
+            .iter()
+            .map(convert_const)
+            .map(Symbol::Constant)
+            .chain(static_vars.into_iter().map(Symbol::StaticVariable))
             .chain(fields.into_iter().map(Symbol::Field))
             .chain(class_methods.into_iter().map(Symbol::ClassMethod))
             .chain(methods.into_iter().map(Symbol::Method));
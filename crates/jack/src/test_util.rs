@@ -0,0 +1,46 @@
+//! Fixtures shared by the tests of the passes after the parser.
+
+use crate::{
+    ast::{Class, ParseOptions},
+    control_flow_graph::CfgClass,
+    symbol_table::GlobalSymbolTable,
+    token::{Tokens, WithLoc},
+    typed_ast::{CfgOptions, ToControlFlowGraph, TypedClass},
+};
+
+/// A class compiled up to the control flow graph.
+pub(crate) struct Compiled {
+    pub(crate) ast: WithLoc<Class>,
+    pub(crate) typed: WithLoc<TypedClass>,
+    pub(crate) cfg: WithLoc<CfgClass>,
+}
+
+/// Compiles `source` as `Main.jack` with the default options.
+pub(crate) fn compile(source: &str) -> Compiled {
+    compile_with(source, ParseOptions::default(), &CfgOptions::default())
+}
+
+/// Compiles `source` as `Main.jack`. An empty `Array` class is defined, as the builtin classes do
+/// not include it.
+pub(crate) fn compile_with(
+    source: &str,
+    parse_options: ParseOptions,
+    cfg_options: &CfgOptions,
+) -> Compiled {
+    let parse = |source: &str| {
+        let tokens =
+            Tokens::from_reader(source.as_bytes()).with_extensions(parse_options.extensions);
+        Class::from_tokens_with(tokens, parse_options)
+            .into_result()
+            .unwrap()
+    };
+    let ast = parse(source);
+    let mut table = GlobalSymbolTable::with_builtin();
+    table
+        .extend_with_class("Array.jack", &parse("class Array {}").data)
+        .unwrap();
+    table.extend_with_class("Main.jack", &ast.data).unwrap();
+    let typed = ast.resolve(&table).unwrap();
+    let cfg = typed.to_control_flow_graph_with(cfg_options).unwrap();
+    Compiled { ast, typed, cfg }
+}
@@ -1,11 +1,13 @@
-use std::borrow::Cow;
+use std::borrow::{Borrow, Cow};
 
-pub use self::lexer::*;
+pub use self::{lexer::*, trivia::*};
 use parse_display::{Display, FromStr};
+use serde::{Serialize, Serializer};
 
 mod lexer;
+mod trivia;
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Serialize)]
 pub enum Token {
     #[display("{0}")]
     Keyword(Keyword),
@@ -15,6 +17,9 @@ pub enum Token {
     Int(u16),
     #[display("{0}")]
     String(String),
+    /// A character literal, only with the language extensions.
+    #[display("'{0}'")]
+    Char(char),
     #[display("{0}")]
     Ident(Ident),
 }
@@ -26,16 +31,18 @@ impl Token {
             Token::Symbol(_) => TokenKind::Symbol,
             Token::Int(_) => TokenKind::Int,
             Token::String(_) => TokenKind::String,
+            Token::Char(_) => TokenKind::Char,
             Token::Ident(_) => TokenKind::Ident,
         }
     }
 
-    pub fn to_cow_str(&self) -> Cow<str> {
+    pub fn to_cow_str(&self) -> Cow<'_, str> {
         match self {
             Token::Keyword(keyword) => Cow::from(keyword.as_str()),
             Token::Symbol(symbol) => Cow::from(symbol.as_str()),
             Token::Int(int) => Cow::from(int.to_string()),
             Token::String(string) => Cow::from(string.as_str()),
+            Token::Char(ch) => Cow::from(ch.to_string()),
             Token::Ident(ident) => Cow::from(ident.as_str()),
         }
     }
@@ -47,6 +54,7 @@ pub enum TokenKind {
     Symbol,
     Int,
     String,
+    Char,
     Ident,
 }
 
@@ -94,6 +102,14 @@ pub enum Keyword {
     While,
     #[display("return")]
     Return,
+    #[display("for")]
+    For,
+    #[display("break")]
+    Break,
+    #[display("continue")]
+    Continue,
+    #[display("const")]
+    Const,
 }
 
 impl Keyword {
@@ -120,10 +136,23 @@ impl Keyword {
             Self::Else => "else",
             Self::While => "while",
             Self::Return => "return",
+            Self::For => "for",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::Const => "const",
         }
     }
 }
 
+impl Serialize for Keyword {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Display, FromStr)]
 pub enum Symbol {
     #[display("(")]
@@ -192,7 +221,16 @@ impl Symbol {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Display, Hash)]
+impl Serialize for Symbol {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Display, Hash, Serialize)]
 #[display("{0}")]
 pub struct Ident(String);
 
@@ -205,3 +243,9 @@ impl Ident {
         &self.0
     }
 }
+
+impl Borrow<str> for Ident {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
@@ -1,4 +1,6 @@
 use super::{Ident, Keyword, Symbol, Token};
+use crate::diagnostic::{Diagnostic, ToDiagnostic};
+use serde::{Deserialize, Serialize};
 use std::{
     fmt,
     io::{self, prelude::*},
@@ -7,7 +9,7 @@ use std::{
 };
 use thiserror::Error;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct WithLoc<T> {
     pub data: T,
     pub loc: Location,
@@ -68,10 +70,14 @@ impl<T> WithLoc<Option<T>> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Location {
     pub line_num: u32,
     pub column: u32,
+    /// Byte offset of the start of the span from the beginning of the source.
+    pub start: u32,
+    /// Byte offset of the end (exclusive) of the span from the beginning of the source.
+    pub end: u32,
 }
 
 impl fmt::Display for Location {
@@ -85,8 +91,34 @@ impl Location {
         Self {
             line_num: 0,
             column: 0,
+            start: 0,
+            end: 0,
         }
     }
+
+    pub fn is_builtin(&self) -> bool {
+        self.line_num == 0
+    }
+
+    /// Returns the span which starts at `self` and ends at the end of `other`.
+    pub fn to(self, other: Location) -> Location {
+        Location {
+            end: u32::max(self.end, other.end),
+            ..self
+        }
+    }
+}
+
+/// A comment with its delimiters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+}
+
+impl Comment {
+    pub fn is_line_comment(&self) -> bool {
+        self.text.starts_with("//")
+    }
 }
 
 #[derive(Debug)]
@@ -95,7 +127,10 @@ pub struct Tokens<R> {
     line_buf: String,
     line_num: u32,
     line_index: usize,
+    line_offset: usize,
     in_multiline_comment: bool,
+    comments: Vec<WithLoc<Comment>>,
+    extensions: bool,
 }
 
 impl<R> Tokens<R> {
@@ -105,9 +140,25 @@ impl<R> Tokens<R> {
             line_buf: String::new(),
             line_num: 0,
             line_index: 0,
+            line_offset: 0,
             in_multiline_comment: false,
+            comments: vec![],
+            extensions: false,
         }
     }
+
+    /// Enables the extensions: the `for`, `break`, `continue` and `const` keywords, `'a'`
+    /// character literals, `0x1f` and `0b101` integers, and `\n`, `\"`, `\'` and `\\` escapes
+    /// in strings and characters.
+    pub fn with_extensions(mut self, extensions: bool) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Returns the comments read so far.
+    pub fn comments(&self) -> &[WithLoc<Comment>] {
+        &self.comments
+    }
 }
 
 impl<R> Iterator for Tokens<R>
@@ -117,18 +168,18 @@ where
     type Item = Result<WithLoc<Token>, ParseTokenError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next_token()
-            .map_err(|e| ParseTokenError {
-                loc: Location {
-                    line_num: self.line_num,
-                    column: self.line_index as u32,
-                },
-                kind: e,
-            })
-            .transpose()
+        self.next_token().transpose()
     }
 }
 
+/// The newline in the Hack character set.
+const HACK_NEWLINE: char = '\u{80}';
+
+/// Returns `true` if `ch` is a printable ASCII character or the Hack newline.
+fn is_hack_char(ch: char) -> bool {
+    matches!(ch, ' '..='~' | HACK_NEWLINE)
+}
+
 fn split_start_matches(s: &str, pat: impl FnMut(char) -> bool) -> Option<&str> {
     let rest = s.trim_start_matches(pat);
     let match_end = s.len() - rest.len();
@@ -150,43 +201,72 @@ impl<R> Tokens<R>
 where
     R: BufRead,
 {
-    fn next_token(&mut self) -> Result<Option<WithLoc<Token>>, ParseTokenErrorKind> {
+    fn next_token(&mut self) -> Result<Option<WithLoc<Token>>, ParseTokenError> {
         loop {
             if self.line_index == self.line_buf.len() {
                 self.line_num += 1;
+                self.line_offset += self.line_buf.len();
                 self.line_buf.clear();
-                if self.reader.read_line(&mut self.line_buf)? == 0 {
+                self.line_index = 0;
+                let loc = self.location();
+                let len =
+                    self.reader
+                        .read_line(&mut self.line_buf)
+                        .map_err(|e| ParseTokenError {
+                            loc,
+                            kind: e.into(),
+                        })?;
+                if len == 0 {
                     return Ok(None);
                 }
-                self.line_index = 0;
             }
 
             if self.skip_spaces_or_comments() {
                 continue;
             }
-            let loc = Location {
-                line_num: self.line_num,
-                column: self.line_index as u32,
+            let mut loc = self.location();
+            let token = self.token();
+            loc.end = (self.line_offset + self.line_index) as u32;
+            return match token {
+                Ok(token) => Ok(Some(WithLoc { data: token, loc })),
+                Err(kind) => Err(ParseTokenError { loc, kind }),
             };
-            if let Some(token) = self.keyword_or_ident() {
-                return Ok(Some(WithLoc { data: token, loc }));
-            }
-            if let Some(token) = self.integer()? {
-                return Ok(Some(WithLoc { data: token, loc }));
-            }
-            if let Some(token) = self.string()? {
-                return Ok(Some(WithLoc { data: token, loc }));
-            }
-            if let Some(token) = self.symbol()? {
-                return Ok(Some(WithLoc { data: token, loc }));
-            }
-            let ch = self.line().chars().next().unwrap();
-            return Err(ParseTokenErrorKind::UnexpectedChar(ch));
         }
     }
+
+    fn token(&mut self) -> Result<Token, ParseTokenErrorKind> {
+        if let Some(token) = self.keyword_or_ident() {
+            return Ok(token);
+        }
+        if let Some(token) = self.integer()? {
+            return Ok(token);
+        }
+        if let Some(token) = self.string()? {
+            return Ok(token);
+        }
+        if let Some(token) = self.char()? {
+            return Ok(token);
+        }
+        if let Some(token) = self.symbol()? {
+            return Ok(token);
+        }
+        let ch = self.line().chars().next().unwrap();
+        self.line_index += ch.len_utf8();
+        Err(ParseTokenErrorKind::UnexpectedChar(ch))
+    }
 }
 
 impl<R> Tokens<R> {
+    fn location(&self) -> Location {
+        let offset = (self.line_offset + self.line_index) as u32;
+        Location {
+            line_num: self.line_num,
+            column: self.line_index as u32,
+            start: offset,
+            end: offset,
+        }
+    }
+
     fn line(&self) -> &str {
         &self.line_buf[self.line_index..]
     }
@@ -200,12 +280,18 @@ impl<R> Tokens<R> {
     fn skip_spaces_or_comments(&mut self) -> bool {
         let mut skipped = false;
         if self.in_multiline_comment {
-            if let Some(end_index) = self.line().find("*/") {
-                self.line_index += end_index + "*/".len();
-                self.in_multiline_comment = false;
-                return true;
-            }
-            self.line_index = self.line_buf.len();
+            let len = match self.line().find("*/") {
+                Some(end_index) => {
+                    self.in_multiline_comment = false;
+                    end_index + "*/".len()
+                }
+                None => self.line().len(),
+            };
+            let text = &self.line_buf[self.line_index..self.line_index + len];
+            let comment = self.comments.last_mut().unwrap();
+            comment.data.text.push_str(text);
+            self.line_index += len;
+            comment.loc.end = (self.line_offset + self.line_index) as u32;
             return true;
         }
 
@@ -217,12 +303,25 @@ impl<R> Tokens<R> {
         }
 
         if self.line().starts_with("//") {
+            let mut loc = self.location();
+            let text = self.line().trim_end_matches(&['\r', '\n'][..]).to_owned();
+            loc.end = loc.start + text.len() as u32;
+            self.comments.push(WithLoc {
+                data: Comment { text },
+                loc,
+            });
             self.line_index = self.line_buf.len();
             return true;
         }
 
         if self.line().starts_with("/*") {
+            let mut loc = self.location();
             self.line_index += "/*".len();
+            loc.end = loc.start + "/*".len() as u32;
+            self.comments.push(WithLoc {
+                data: Comment { text: "/*".into() },
+                loc,
+            });
             self.in_multiline_comment = true;
             return true;
         }
@@ -232,9 +331,14 @@ impl<R> Tokens<R> {
 
     fn keyword_or_ident(&mut self) -> Option<Token> {
         if self.line().starts_with(is_identifier_start) {
+            let extensions = self.extensions;
             let ident = self.eat_matches(is_identifier_continue).unwrap();
-            if let Ok(keyword) = Keyword::from_str(ident) {
-                return Some(Token::Keyword(keyword));
+            match Keyword::from_str(ident) {
+                // without the extensions, their keywords are ordinary identifiers
+                Ok(Keyword::For | Keyword::Break | Keyword::Continue | Keyword::Const)
+                    if !extensions => {}
+                Ok(keyword) => return Some(Token::Keyword(keyword)),
+                Err(_) => {}
             }
             return Some(Token::Ident(Ident(ident.into())));
         }
@@ -242,8 +346,19 @@ impl<R> Tokens<R> {
     }
 
     fn integer(&mut self) -> Result<Option<Token>, ParseTokenErrorKind> {
-        if let Some(integer) = self.eat_matches(|ch| ch.is_numeric()) {
-            let n = u16::from_str(integer)
+        let radix = match self.line().get(..2) {
+            Some("0x" | "0X") if self.extensions => 16,
+            Some("0b" | "0B") if self.extensions => 2,
+            _ => 10,
+        };
+        let integer = if radix == 10 {
+            self.eat_matches(|ch| ch.is_numeric())
+        } else {
+            self.eat_matches(|ch| ch.is_ascii_alphanumeric())
+        };
+        if let Some(integer) = integer {
+            let digits = if radix == 10 { integer } else { &integer[2..] };
+            let n = u16::from_str_radix(digits, radix)
                 .map_err(|e| ParseTokenErrorKind::Integer(e, integer.into()))?;
             if n > 32767 {
                 return Err(ParseTokenErrorKind::IntegerOverflow(n));
@@ -254,8 +369,11 @@ impl<R> Tokens<R> {
     }
 
     fn string(&mut self) -> Result<Option<Token>, ParseTokenErrorKind> {
-        if self.line().starts_with('"') {
-            self.line_index += '"'.len_utf8();
+        if !self.line().starts_with('"') {
+            return Ok(None);
+        }
+        self.line_index += '"'.len_utf8();
+        if !self.extensions {
             let string = self.eat_matches(|ch| ch != '"').unwrap_or("").to_string();
             if !self.line().starts_with('"') {
                 return Err(ParseTokenErrorKind::UnterminatedString);
@@ -263,7 +381,61 @@ impl<R> Tokens<R> {
             self.line_index += '"'.len_utf8();
             return Ok(Some(Token::String(string)));
         }
-        Ok(None)
+
+        let mut string = String::new();
+        loop {
+            match self.line().chars().next() {
+                Some('"') => break,
+                Some('\\') => string.push(self.escape()?),
+                Some(ch) if ch != '\n' => {
+                    self.line_index += ch.len_utf8();
+                    string.push(ch);
+                }
+                _ => return Err(ParseTokenErrorKind::UnterminatedString),
+            }
+        }
+        self.line_index += '"'.len_utf8();
+        Ok(Some(Token::String(string)))
+    }
+
+    fn char(&mut self) -> Result<Option<Token>, ParseTokenErrorKind> {
+        if !self.extensions || !self.line().starts_with('\'') {
+            return Ok(None);
+        }
+        self.line_index += '\''.len_utf8();
+        let ch = match self.line().chars().next() {
+            Some('\\') => self.escape()?,
+            Some(ch) if ch != '\'' && ch != '\n' => {
+                self.line_index += ch.len_utf8();
+                ch
+            }
+            _ => return Err(ParseTokenErrorKind::InvalidChar),
+        };
+        if !self.line().starts_with('\'') {
+            return Err(ParseTokenErrorKind::InvalidChar);
+        }
+        self.line_index += '\''.len_utf8();
+        if !is_hack_char(ch) {
+            return Err(ParseTokenErrorKind::NonHackChar(ch));
+        }
+        Ok(Some(Token::Char(ch)))
+    }
+
+    /// Reads an escape sequence starting with `\`.
+    ///
+    /// A newline is mapped to the Hack character 128.
+    fn escape(&mut self) -> Result<char, ParseTokenErrorKind> {
+        self.line_index += '\\'.len_utf8();
+        let ch = match self.line().chars().next() {
+            Some(ch) if ch != '\n' => ch,
+            _ => return Err(ParseTokenErrorKind::UnterminatedString),
+        };
+        self.line_index += ch.len_utf8();
+        match ch {
+            'n' => Ok(HACK_NEWLINE),
+            '"' | '\'' | '\\' => Ok(ch),
+            _ => Err(ParseTokenErrorKind::UnknownEscape(ch)),
+        }
     }
 
     fn symbol(&mut self) -> Result<Option<Token>, ParseTokenErrorKind> {
@@ -287,6 +459,32 @@ pub struct ParseTokenError {
     kind: ParseTokenErrorKind,
 }
 
+impl ParseTokenError {
+    pub fn loc(&self) -> Location {
+        self.loc
+    }
+
+    pub fn kind(&self) -> &ParseTokenErrorKind {
+        &self.kind
+    }
+}
+
+impl ToDiagnostic for ParseTokenError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let label = match self.kind {
+            ParseTokenErrorKind::Io(_) => return Diagnostic::error(self.kind.to_string()),
+            ParseTokenErrorKind::Integer(..) => "invalid integer",
+            ParseTokenErrorKind::IntegerOverflow(_) => "must be at most 32767",
+            ParseTokenErrorKind::UnterminatedString => "missing closing `\"`",
+            ParseTokenErrorKind::InvalidChar => "must be a single character between `'`",
+            ParseTokenErrorKind::NonHackChar(_) => "not in the Hack character set",
+            ParseTokenErrorKind::UnknownEscape(_) => "expected `\\n`, `\\\"`, `\\'` or `\\\\`",
+            ParseTokenErrorKind::UnexpectedChar(_) => "unexpected char",
+        };
+        Diagnostic::error(self.kind.to_string()).with_primary(self.loc, label)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ParseTokenErrorKind {
     #[error("IO error")]
@@ -297,6 +495,98 @@ pub enum ParseTokenErrorKind {
     IntegerOverflow(u16),
     #[error("unterminated string")]
     UnterminatedString,
+    #[error("invalid character literal")]
+    InvalidChar,
+    #[error("character cannot be represented in Hack: {:?}", _0)]
+    NonHackChar(char),
+    #[error("unknown escape sequence: \\{}", _0)]
+    UnknownEscape(char),
     #[error("unexpected char: {}", _0)]
     UnexpectedChar(char),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(source: &str, extensions: bool) -> Vec<Result<Token, String>> {
+        Tokens::from_reader(source.as_bytes())
+            .with_extensions(extensions)
+            .map(|token| {
+                token
+                    .map(|token| token.data)
+                    .map_err(|e| e.kind.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keyword_extensions() {
+        let source = "for break continue const while";
+        let expected = [
+            Token::Keyword(Keyword::For),
+            Token::Keyword(Keyword::Break),
+            Token::Keyword(Keyword::Continue),
+            Token::Keyword(Keyword::Const),
+            Token::Keyword(Keyword::While),
+        ];
+        let expected = expected.into_iter().map(Ok).collect::<Vec<_>>();
+        assert_eq!(tokens(source, true), expected);
+
+        // standard Jack has no such keywords
+        let expected = [
+            Token::Ident(Ident::new("for")),
+            Token::Ident(Ident::new("break")),
+            Token::Ident(Ident::new("continue")),
+            Token::Ident(Ident::new("const")),
+            Token::Keyword(Keyword::While),
+        ];
+        let expected = expected.into_iter().map(Ok).collect::<Vec<_>>();
+        assert_eq!(tokens(source, false), expected);
+    }
+
+    #[test]
+    fn literal_extensions() {
+        let source = r#"'a' '\n' '\'' 0x7FFF 0b101 "say \"hi\"\n" "\\""#;
+        let expected = [
+            Token::Char('a'),
+            Token::Char(HACK_NEWLINE),
+            Token::Char('\''),
+            Token::Int(32767),
+            Token::Int(5),
+            Token::String(format!("say \"hi\"{}", HACK_NEWLINE)),
+            Token::String("\\".into()),
+        ];
+        let expected = expected.into_iter().map(Ok).collect::<Vec<_>>();
+        assert_eq!(tokens(source, true), expected);
+
+        // standard Jack has no escapes
+        let expected = [
+            Token::Int(0),
+            Token::Ident(Ident::new("x1F")),
+            Token::String("a\\n".into()),
+        ];
+        let expected = expected.into_iter().map(Ok).collect::<Vec<_>>();
+        assert_eq!(tokens(r#"0x1F "a\n""#, false), expected);
+
+        let errors = [
+            "''", "'ab'", "'한'", "'😀'", "'\t'", r#""\t""#, "0x8000", "0b2",
+        ]
+        .iter()
+        .map(|source| tokens(source, true)[0].clone().unwrap_err())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "invalid character literal",
+                "invalid character literal",
+                "character cannot be represented in Hack: '한'",
+                "character cannot be represented in Hack: '😀'",
+                "character cannot be represented in Hack: '\\t'",
+                "unknown escape sequence: \\t",
+                "integer is too large: 32768",
+                "invalid integer: 0b2",
+            ]
+        );
+    }
+}
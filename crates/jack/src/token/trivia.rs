@@ -0,0 +1,141 @@
+use super::{Comment, ParseTokenError, Token, Tokens, WithLoc};
+use crate::token::Location;
+use std::fmt;
+
+/// Whitespace or a comment between tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace(String),
+    Comment(Comment),
+}
+
+impl Trivia {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Whitespace(text) => text,
+            Self::Comment(comment) => &comment.text,
+        }
+    }
+}
+
+/// A token with its source text and the trivia preceding it.
+#[derive(Debug, Clone)]
+pub struct TriviaToken {
+    pub leading: Vec<WithLoc<Trivia>>,
+    pub token: WithLoc<Token>,
+    /// The text of the token as written, e.g. `0x1F` for `Token::Int(31)`.
+    pub text: String,
+}
+
+impl TriviaToken {
+    /// Returns the comments right before the token.
+    pub fn comments(&self) -> impl Iterator<Item = WithLoc<&Comment>> + '_ {
+        self.leading.iter().filter_map(|trivia| match &trivia.data {
+            Trivia::Comment(comment) => Some(WithLoc {
+                data: comment,
+                loc: trivia.loc,
+            }),
+            Trivia::Whitespace(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for TriviaToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading {
+            f.write_str(trivia.data.as_str())?;
+        }
+        f.write_str(&self.text)
+    }
+}
+
+/// The tokens of a source with all of its whitespace and comments, so that the source can be
+/// written back unchanged.
+#[derive(Debug, Clone)]
+pub struct LosslessTokens {
+    pub tokens: Vec<TriviaToken>,
+    /// The trivia after the last token.
+    pub trailing: Vec<WithLoc<Trivia>>,
+}
+
+impl LosslessTokens {
+    /// Lexes `source`, with the literal extensions if `extensions` is set.
+    pub fn from_source(source: &str, extensions: bool) -> Result<Self, ParseTokenError> {
+        let mut lexer = Tokens::from_reader(source.as_bytes()).with_extensions(extensions);
+        let tokens = lexer.by_ref().collect::<Result<Vec<_>, _>>()?;
+        let lines = LineStarts::new(source);
+        let mut comments = lexer.comments().iter().peekable();
+        let mut trivia = |start: usize, end: usize| {
+            let mut trivia = vec![];
+            let mut offset = start;
+            while offset < end {
+                let comment = comments.next_if(|comment| (comment.loc.start as usize) < end);
+                let ws_end = comment.map_or(end, |comment| comment.loc.start as usize);
+                if offset < ws_end {
+                    trivia.push(WithLoc {
+                        data: Trivia::Whitespace(source[offset..ws_end].to_owned()),
+                        loc: lines.location(offset, ws_end),
+                    });
+                }
+                if let Some(comment) = comment {
+                    trivia.push(comment.clone().map(Trivia::Comment));
+                    offset = comment.loc.end as usize;
+                } else {
+                    offset = end;
+                }
+            }
+            trivia
+        };
+
+        let mut offset = 0;
+        let tokens = tokens
+            .into_iter()
+            .map(|token| {
+                let (start, end) = (token.loc.start as usize, token.loc.end as usize);
+                let leading = trivia(offset, start);
+                offset = end;
+                TriviaToken {
+                    leading,
+                    text: source[start..end].to_owned(),
+                    token,
+                }
+            })
+            .collect();
+        let trailing = trivia(offset, source.len());
+        Ok(Self { tokens, trailing })
+    }
+}
+
+impl fmt::Display for LosslessTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "{}", token)?;
+        }
+        for trivia in &self.trailing {
+            f.write_str(trivia.data.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Byte offsets of the line starts, to locate the whitespace the lexer does not report.
+struct LineStarts(Vec<usize>);
+
+impl LineStarts {
+    fn new(source: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self(starts)
+    }
+
+    fn location(&self, start: usize, end: usize) -> Location {
+        let line = self.0.partition_point(|&line_start| line_start <= start) - 1;
+        Location {
+            line_num: line as u32 + 1,
+            column: (start - self.0[line]) as u32,
+            start: start as u32,
+            end: end as u32,
+        }
+    }
+}
@@ -7,8 +7,9 @@ use crate::{
     symbol_table::VarSymbol,
     token::{Ident, WithLoc},
 };
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedClass {
     pub name: WithLoc<Ident>,
     pub static_vars: Vec<WithLoc<Variable>>,
@@ -16,13 +17,13 @@ pub struct TypedClass {
     pub subs: Vec<WithLoc<TypedSubroutine>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Variable {
     pub name: WithLoc<Ident>,
     pub ty: WithLoc<Type>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedSubroutine {
     pub name: WithLoc<Ident>,
     pub kind: WithLoc<SubroutineKind>,
@@ -32,53 +33,67 @@ pub struct TypedSubroutine {
     pub stmts: Vec<WithLoc<TypedStatement>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum TypedStatement {
     Let(WithLoc<TypedLetStatement>),
     If(WithLoc<TypedIfStatement>),
     While(WithLoc<TypedWhileStatement>),
     Do(WithLoc<TypedDoStatement>),
     Return(WithLoc<TypedReturnStatement>),
+    For(Box<WithLoc<TypedForStatement>>),
+    Break,
+    Continue,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedLetStatement {
     pub target: VarSymbol,
     pub target_index: Option<WithLoc<TypedExpression>>,
     pub expr: WithLoc<TypedExpression>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedIfStatement {
     pub cond: WithLoc<TypedExpression>,
     pub then_stmts: Vec<WithLoc<TypedStatement>>,
     pub else_stmts: Option<Vec<WithLoc<TypedStatement>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedWhileStatement {
     pub cond: WithLoc<TypedExpression>,
     pub stmts: Vec<WithLoc<TypedStatement>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct TypedForStatement {
+    pub init: Option<WithLoc<TypedLetStatement>>,
+    pub cond: Option<WithLoc<TypedExpression>>,
+    pub step: Option<WithLoc<TypedLetStatement>>,
+    pub stmts: Vec<WithLoc<TypedStatement>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedReturnStatement {
     pub expr: Option<WithLoc<TypedExpression>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedDoStatement {
     pub sub_call: WithLoc<TypedSubroutineCall>,
+    /// The return type of the called subroutine, whose value is discarded.
+    pub return_type: ReturnType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypedExpression {
     pub ty: Type,
     pub term: Box<TypedTerm>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum TypedTerm {
+    /// An integer constant, or a character constant as its code if the expression is a `char`.
     Int(WithLoc<u16>),
     String(WithLoc<String>),
     Bool(WithLoc<bool>),
@@ -95,7 +110,7 @@ pub enum TypedTerm {
     ),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum TypedSubroutineCall {
     Method(
         Option<VarSymbol>,
@@ -1,18 +1,46 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, iter, mem};
 
 use super::*;
 use crate::{
     control_flow_graph::{BasicBlock, BbId, CfgClass, CfgStatement, CfgSubroutine, Exit},
+    diagnostic::{Diagnostic, ToDiagnostic},
+    symbol_table::LocalVariable,
     token::Location,
 };
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub enum ToCfgError {}
+pub enum ToCfgError {
+    #[error("`{}` outside of a loop", _0.data)]
+    OutsideLoop(WithLoc<&'static str>),
+}
+
+impl ToDiagnostic for ToCfgError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::OutsideLoop(keyword) => Diagnostic::error(self.to_string()).with_primary(
+                keyword.loc,
+                format!("`{}` is only allowed in loops", keyword.data),
+            ),
+        }
+    }
+}
+
+/// Language options affecting the lowering to the control flow graph.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CfgOptions {
+    /// Lowers `&` and `|` on booleans to branches, so that the right operand is only evaluated
+    /// when the left one does not decide the result.
+    pub short_circuit: bool,
+}
 
 pub trait ToControlFlowGraph {
     type Output;
-    fn to_control_flow_graph(&self) -> Result<Self::Output, ToCfgError>;
+    fn to_control_flow_graph_with(&self, options: &CfgOptions) -> Result<Self::Output, ToCfgError>;
+
+    fn to_control_flow_graph(&self) -> Result<Self::Output, ToCfgError> {
+        self.to_control_flow_graph_with(&CfgOptions::default())
+    }
 }
 
 impl<T> ToControlFlowGraph for WithLoc<T>
@@ -20,17 +48,17 @@ where
     T: ToControlFlowGraph,
 {
     type Output = WithLoc<T::Output>;
-    fn to_control_flow_graph(&self) -> Result<Self::Output, ToCfgError> {
+    fn to_control_flow_graph_with(&self, options: &CfgOptions) -> Result<Self::Output, ToCfgError> {
         Ok(WithLoc {
             loc: self.loc,
-            data: self.data.to_control_flow_graph()?,
+            data: self.data.to_control_flow_graph_with(options)?,
         })
     }
 }
 
 impl ToControlFlowGraph for TypedClass {
     type Output = CfgClass;
-    fn to_control_flow_graph(&self) -> Result<Self::Output, ToCfgError> {
+    fn to_control_flow_graph_with(&self, options: &CfgOptions) -> Result<Self::Output, ToCfgError> {
         Ok(CfgClass {
             name: self.name.clone(),
             static_vars: self.static_vars.clone(),
@@ -38,7 +66,7 @@ impl ToControlFlowGraph for TypedClass {
             subs: self
                 .subs
                 .iter()
-                .map(|sub| sub.to_control_flow_graph())
+                .map(|sub| sub.to_control_flow_graph_with(options))
                 .collect::<Result<_, _>>()?,
         })
     }
@@ -46,21 +74,26 @@ impl ToControlFlowGraph for TypedClass {
 
 impl ToControlFlowGraph for TypedSubroutine {
     type Output = CfgSubroutine;
-    fn to_control_flow_graph(&self) -> Result<Self::Output, ToCfgError> {
-        let mut label_map = HashMap::new();
+    fn to_control_flow_graph_with(&self, options: &CfgOptions) -> Result<Self::Output, ToCfgError> {
+        let mut ctx = Context {
+            options,
+            label_map: HashMap::new(),
+            vars: self.vars.clone(),
+            loops: vec![],
+        };
         let mut blocks = vec![];
         let entry_block = new_block(self.name.loc, "ENTRY", 0);
         let entry_id = entry_block.data.id;
         blocks.push(entry_block);
 
-        generate_blocks(&mut label_map, &self.stmts, &mut blocks)?;
+        generate_blocks(&mut ctx, &self.stmts, &mut blocks)?;
 
         let mut sub = CfgSubroutine {
             name: self.name.clone(),
             kind: self.kind,
             return_type: self.return_type.clone(),
             params: self.params.clone(),
-            vars: self.vars.clone(),
+            vars: ctx.vars,
             entry_id,
             block_index_map: HashMap::new(),
             blocks,
@@ -86,14 +119,14 @@ fn new_block(loc: Location, label: &'static str, label_index: u32) -> WithLoc<Ba
     }
 }
 
-fn push_stmt(blocks: &mut Vec<WithLoc<BasicBlock>>, stmt: CfgStatement) {
+fn push_stmt(blocks: &mut [WithLoc<BasicBlock>], stmt: CfgStatement) {
     let last_block = blocks.last_mut().unwrap();
     last_block.data.stmts.push(stmt);
 }
 
 fn update_exit(blocks: &mut [WithLoc<BasicBlock>], exit: Exit) {
     assert!(!matches!(exit, Exit::Unreachable));
-    let mut last = blocks.last_mut().unwrap();
+    let last = blocks.last_mut().unwrap();
     assert!(
         matches!(last.data.exit, Exit::Unreachable),
         "unexpected exit at {}: {:?}",
@@ -103,21 +136,110 @@ fn update_exit(blocks: &mut [WithLoc<BasicBlock>], exit: Exit) {
     last.data.exit = exit;
 }
 
+struct Context<'a> {
+    options: &'a CfgOptions,
+    label_map: HashMap<&'static str, u32>,
+    /// The local variables, followed by the temporaries introduced by the lowering.
+    vars: Vec<WithLoc<Variable>>,
+    /// The enclosing loops, innermost last.
+    loops: Vec<Loop>,
+}
+
+/// The jump targets of `continue` and `break` in a loop.
+struct Loop {
+    continue_id: BbId,
+    break_id: BbId,
+}
+
+impl Context<'_> {
+    fn next_label_index(&mut self, key: &'static str) -> u32 {
+        *self
+            .label_map
+            .entry(key)
+            .and_modify(|i| *i += 1)
+            .or_insert(0)
+    }
+
+    /// Returns `true` if the expression is a boolean `&` or `|` lowered to branches.
+    fn short_circuits(&self, expr: &TypedExpression) -> bool {
+        self.options.short_circuit
+            && expr.ty == Type::Boolean
+            && matches!(
+                &*expr.term,
+                TypedTerm::BinaryOp(op, _, _) if matches!(op.data, BinaryOp::And | BinaryOp::Or)
+            )
+    }
+
+    fn contains_short_circuit(&self, expr: &TypedExpression) -> bool {
+        let args = |call: &TypedSubroutineCall| match call {
+            TypedSubroutineCall::Method(_, _, args)
+            | TypedSubroutineCall::Function(_, _, args)
+            | TypedSubroutineCall::Constructor(_, _, args) => args
+                .iter()
+                .any(|arg| self.contains_short_circuit(&arg.data)),
+        };
+        self.short_circuits(expr)
+            || match &*expr.term {
+                TypedTerm::Index(_, index) => self.contains_short_circuit(&index.data),
+                TypedTerm::SubroutineCall(call) => args(&call.data),
+                TypedTerm::UnaryOp(_, operand) => self.contains_short_circuit(&operand.data),
+                TypedTerm::BinaryOp(_, lhs, rhs) => {
+                    self.contains_short_circuit(&lhs.data) || self.contains_short_circuit(&rhs.data)
+                }
+                TypedTerm::Int(_)
+                | TypedTerm::String(_)
+                | TypedTerm::Bool(_)
+                | TypedTerm::Null
+                | TypedTerm::This
+                | TypedTerm::Var(_) => false,
+            }
+    }
+
+    fn new_temp(&mut self, loc: Location, ty: Type) -> VarSymbol {
+        let slot_index = self.vars.len();
+        let name = WithLoc {
+            data: Ident::new(format!("$tmp{}", slot_index)),
+            loc,
+        };
+        let ty = WithLoc { data: ty, loc };
+        self.vars.push(WithLoc {
+            data: Variable {
+                name: name.clone(),
+                ty: ty.clone(),
+            },
+            loc,
+        });
+        VarSymbol::LocalVariable(LocalVariable {
+            name,
+            ty,
+            slot_index,
+        })
+    }
+}
+
 fn generate_blocks(
-    label_map: &mut HashMap<&'static str, u32>,
+    ctx: &mut Context,
     stmts: &[WithLoc<TypedStatement>],
     blocks: &mut Vec<WithLoc<BasicBlock>>,
 ) -> Result<(), ToCfgError> {
     for stmt in stmts {
         match &stmt.data {
-            TypedStatement::Let(stmt) => {
-                push_stmt(blocks, CfgStatement::Let(stmt.clone()));
-            }
+            TypedStatement::Let(stmt) => lower_let(ctx, blocks, stmt),
             TypedStatement::Do(stmt) => {
-                push_stmt(blocks, CfgStatement::Do(stmt.clone()));
+                let data = TypedDoStatement {
+                    sub_call: lower_call(ctx, blocks, &stmt.data.sub_call),
+                    return_type: stmt.data.return_type.clone(),
+                };
+                push_stmt(
+                    blocks,
+                    CfgStatement::Do(WithLoc {
+                        loc: stmt.loc,
+                        data,
+                    }),
+                );
             }
             TypedStatement::If(stmt) => {
-                let label_index = *label_map.entry("if").and_modify(|i| *i += 1).or_insert(0);
+                let label_index = ctx.next_label_index("if");
                 let has_else = stmt.data.else_stmts.is_some();
                 let end_block = new_block(
                     stmt.loc,
@@ -131,25 +253,26 @@ fn generate_blocks(
                 let then_id = then_block.data.id;
                 let else_id = else_block.as_ref().map(|b| b.data.id).unwrap_or(end_id);
 
-                update_exit(blocks, Exit::If(stmt.data.cond.clone(), then_id, else_id));
+                if ctx.contains_short_circuit(&stmt.data.cond.data) {
+                    generate_branch(ctx, blocks, &stmt.data.cond, then_id, else_id, then_id);
+                } else {
+                    update_exit(blocks, Exit::If(stmt.data.cond.clone(), then_id, else_id));
+                }
 
                 blocks.push(then_block);
-                generate_blocks(label_map, &stmt.data.then_stmts, blocks)?;
+                generate_blocks(ctx, &stmt.data.then_stmts, blocks)?;
                 update_exit(blocks, Exit::Goto(end_id));
 
                 if let Some(else_stmts) = &stmt.data.else_stmts {
                     blocks.push(else_block.unwrap());
-                    generate_blocks(label_map, else_stmts, blocks)?;
+                    generate_blocks(ctx, else_stmts, blocks)?;
                     update_exit(blocks, Exit::Goto(end_id));
                 }
 
                 blocks.push(end_block);
             }
             TypedStatement::While(stmt) => {
-                let label_index = *label_map
-                    .entry("while")
-                    .and_modify(|i| *i += 1)
-                    .or_insert(0);
+                let label_index = ctx.next_label_index("while");
                 let end_block = new_block(stmt.loc, "WHILE_END", label_index);
                 let body_block = new_block(stmt.loc, "WHILE_BODY", label_index);
                 let cond_block = new_block(stmt.loc, "WHILE_EXP", label_index);
@@ -161,39 +284,530 @@ fn generate_blocks(
                 update_exit(blocks, Exit::Goto(cond_id));
 
                 blocks.push(cond_block);
-                let cond = {
-                    let loc = stmt.data.cond.loc;
-                    WithLoc {
-                        loc,
-                        data: TypedExpression {
-                            ty: Type::Boolean,
-                            term: Box::new(TypedTerm::UnaryOp(
-                                WithLoc {
-                                    loc,
-                                    data: UnaryOp::Not,
-                                },
-                                stmt.data.cond.clone(),
-                            )),
-                        },
+                if ctx.contains_short_circuit(&stmt.data.cond.data) {
+                    generate_branch(ctx, blocks, &stmt.data.cond, body_id, end_id, body_id);
+                } else {
+                    update_exit(
+                        blocks,
+                        Exit::If(not(stmt.data.cond.clone()), end_id, body_id),
+                    );
+                }
+
+                blocks.push(body_block);
+                ctx.loops.push(Loop {
+                    continue_id: cond_id,
+                    break_id: end_id,
+                });
+                generate_blocks(ctx, &stmt.data.stmts, blocks)?;
+                ctx.loops.pop();
+                update_exit(blocks, Exit::Goto(cond_id));
+
+                blocks.push(end_block);
+            }
+            TypedStatement::For(stmt) => {
+                let label_index = ctx.next_label_index("for");
+                let end_block = new_block(stmt.loc, "FOR_END", label_index);
+                let step_block = new_block(stmt.loc, "FOR_STEP", label_index);
+                let body_block = new_block(stmt.loc, "FOR_BODY", label_index);
+                let cond_block = new_block(stmt.loc, "FOR_EXP", label_index);
+
+                let end_id = end_block.data.id;
+                let step_id = step_block.data.id;
+                let body_id = body_block.data.id;
+                let cond_id = cond_block.data.id;
+
+                if let Some(init) = &stmt.data.init {
+                    lower_let(ctx, blocks, init);
+                }
+                update_exit(blocks, Exit::Goto(cond_id));
+
+                blocks.push(cond_block);
+                match &stmt.data.cond {
+                    Some(cond) if ctx.contains_short_circuit(&cond.data) => {
+                        generate_branch(ctx, blocks, cond, body_id, end_id, body_id);
                     }
-                };
-                update_exit(blocks, Exit::If(cond, end_id, body_id));
+                    Some(cond) => {
+                        update_exit(blocks, Exit::If(not(cond.clone()), end_id, body_id));
+                    }
+                    None => update_exit(blocks, Exit::Goto(body_id)),
+                }
 
                 blocks.push(body_block);
-                generate_blocks(label_map, &stmt.data.stmts, blocks)?;
+                ctx.loops.push(Loop {
+                    continue_id: step_id,
+                    break_id: end_id,
+                });
+                generate_blocks(ctx, &stmt.data.stmts, blocks)?;
+                ctx.loops.pop();
+                update_exit(blocks, Exit::Goto(step_id));
+
+                blocks.push(step_block);
+                if let Some(step) = &stmt.data.step {
+                    lower_let(ctx, blocks, step);
+                }
                 update_exit(blocks, Exit::Goto(cond_id));
 
                 blocks.push(end_block);
             }
+            TypedStatement::Break | TypedStatement::Continue => {
+                let is_break = matches!(stmt.data, TypedStatement::Break);
+                let keyword = WithLoc {
+                    data: if is_break { "break" } else { "continue" },
+                    loc: stmt.loc,
+                };
+                let target = ctx.loops.last().ok_or(ToCfgError::OutsideLoop(keyword))?;
+                let target_id = if is_break {
+                    target.break_id
+                } else {
+                    target.continue_id
+                };
+                update_exit(blocks, Exit::Goto(target_id));
+                let label_index = ctx.next_label_index("unreachable");
+                blocks.push(new_block(stmt.loc, "UNREACHABLE", label_index));
+            }
             TypedStatement::Return(stmt) => {
-                let label_index = *label_map
-                    .entry("return")
-                    .and_modify(|i| *i += 1)
-                    .or_insert(0);
-                update_exit(blocks, Exit::Return(stmt.data.expr.clone()));
+                let label_index = ctx.next_label_index("unreachable");
+                let expr = stmt
+                    .data
+                    .expr
+                    .as_ref()
+                    .map(|expr| lower_value(ctx, blocks, expr));
+                update_exit(blocks, Exit::Return(expr));
                 blocks.push(new_block(stmt.loc, "UNREACHABLE", label_index));
             }
         }
     }
     Ok(())
 }
+
+fn lower_let(
+    ctx: &mut Context,
+    blocks: &mut Vec<WithLoc<BasicBlock>>,
+    stmt: &WithLoc<TypedLetStatement>,
+) {
+    let TypedLetStatement {
+        target,
+        target_index,
+        expr,
+    } = &stmt.data;
+    // the value is evaluated before the index
+    let mut operands = lower_operands(ctx, blocks, iter::once(expr).chain(target_index));
+    let target_index = target_index.as_ref().map(|_| operands.pop().unwrap());
+    let expr = operands.pop().unwrap();
+    let data = TypedLetStatement {
+        target: target.clone(),
+        target_index,
+        expr,
+    };
+    push_stmt(
+        blocks,
+        CfgStatement::Let(WithLoc {
+            loc: stmt.loc,
+            data,
+        }),
+    );
+}
+
+/// Ends the current block with a jump to `then_id` or `else_id` depending on the condition.
+/// Short-circuiting operators are lowered to nested branches, and the block `next_id` pushed
+/// next is reached by falling through.
+fn generate_branch(
+    ctx: &mut Context,
+    blocks: &mut Vec<WithLoc<BasicBlock>>,
+    cond: &WithLoc<TypedExpression>,
+    then_id: BbId,
+    else_id: BbId,
+    next_id: BbId,
+) {
+    match &*cond.data.term {
+        TypedTerm::BinaryOp(op, lhs, rhs) if ctx.short_circuits(&cond.data) => {
+            let is_and = matches!(op.data, BinaryOp::And);
+            let (key, label) = if is_and {
+                ("and", "AND_RHS")
+            } else {
+                ("or", "OR_RHS")
+            };
+            let label_index = ctx.next_label_index(key);
+            let rhs_block = new_block(rhs.loc, label, label_index);
+            let rhs_id = rhs_block.data.id;
+            if is_and {
+                generate_branch(ctx, blocks, lhs, rhs_id, else_id, rhs_id);
+            } else {
+                generate_branch(ctx, blocks, lhs, then_id, rhs_id, rhs_id);
+            }
+            blocks.push(rhs_block);
+            generate_branch(ctx, blocks, rhs, then_id, else_id, next_id);
+        }
+        TypedTerm::UnaryOp(op, operand)
+            if matches!(op.data, UnaryOp::Not) && ctx.short_circuits(&operand.data) =>
+        {
+            generate_branch(ctx, blocks, operand, else_id, then_id, next_id);
+        }
+        _ => {
+            let cond = lower_value(ctx, blocks, cond);
+            let exit = if then_id == next_id {
+                Exit::If(not(cond), else_id, then_id)
+            } else {
+                Exit::If(cond, then_id, else_id)
+            };
+            update_exit(blocks, exit);
+        }
+    }
+}
+
+/// Returns the expression with the short-circuiting operators evaluated into temporaries
+/// by branches placed before it.
+fn lower_value(
+    ctx: &mut Context,
+    blocks: &mut Vec<WithLoc<BasicBlock>>,
+    expr: &WithLoc<TypedExpression>,
+) -> WithLoc<TypedExpression> {
+    if ctx.short_circuits(&expr.data) {
+        let loc = expr.loc;
+        let label_index = ctx.next_label_index("cond");
+        let true_block = new_block(loc, "COND_TRUE", label_index);
+        let false_block = new_block(loc, "COND_FALSE", label_index);
+        let end_block = new_block(loc, "COND_END", label_index);
+        let true_id = true_block.data.id;
+        let end_id = end_block.data.id;
+
+        let temp = ctx.new_temp(loc, Type::Boolean);
+        generate_branch(ctx, blocks, expr, true_id, false_block.data.id, true_id);
+        for (block, value) in [(true_block, true), (false_block, false)] {
+            blocks.push(block);
+            let data = TypedLetStatement {
+                target: temp.clone(),
+                target_index: None,
+                expr: typed_expr(
+                    loc,
+                    Type::Boolean,
+                    TypedTerm::Bool(WithLoc { data: value, loc }),
+                ),
+            };
+            push_stmt(blocks, CfgStatement::Let(WithLoc { loc, data }));
+            update_exit(blocks, Exit::Goto(end_id));
+        }
+        blocks.push(end_block);
+        return typed_expr(loc, Type::Boolean, TypedTerm::Var(temp));
+    }
+
+    let term = match &*expr.data.term {
+        TypedTerm::Index(var, index) => {
+            TypedTerm::Index(var.clone(), lower_value(ctx, blocks, index))
+        }
+        TypedTerm::SubroutineCall(call) => TypedTerm::SubroutineCall(lower_call(ctx, blocks, call)),
+        TypedTerm::UnaryOp(op, operand) => {
+            TypedTerm::UnaryOp(*op, lower_value(ctx, blocks, operand))
+        }
+        TypedTerm::BinaryOp(op, lhs, rhs) => {
+            let mut operands = lower_operands(ctx, blocks, [lhs, rhs]);
+            let rhs = operands.pop().unwrap();
+            let lhs = operands.pop().unwrap();
+            TypedTerm::BinaryOp(*op, lhs, rhs)
+        }
+        term => term.clone(),
+    };
+    typed_expr(expr.loc, expr.data.ty.clone(), term)
+}
+
+/// Lowers the operands evaluated in order. The operands preceding one with branches are saved
+/// into temporaries first, so that their evaluation order is kept.
+fn lower_operands<'a>(
+    ctx: &mut Context,
+    blocks: &mut Vec<WithLoc<BasicBlock>>,
+    exprs: impl IntoIterator<Item = &'a WithLoc<TypedExpression>>,
+) -> Vec<WithLoc<TypedExpression>> {
+    let mut lowered: Vec<WithLoc<TypedExpression>> = vec![];
+    let mut saved = 0;
+    for expr in exprs {
+        if ctx.contains_short_circuit(&expr.data) {
+            for operand in &mut lowered[saved..] {
+                if matches!(
+                    &*operand.data.term,
+                    TypedTerm::Int(_) | TypedTerm::Bool(_) | TypedTerm::Null
+                ) {
+                    continue;
+                }
+                let temp = ctx.new_temp(operand.loc, operand.data.ty.clone());
+                let var = typed_expr(
+                    operand.loc,
+                    operand.data.ty.clone(),
+                    TypedTerm::Var(temp.clone()),
+                );
+                let data = TypedLetStatement {
+                    target: temp,
+                    target_index: None,
+                    expr: mem::replace(operand, var),
+                };
+                push_stmt(
+                    blocks,
+                    CfgStatement::Let(WithLoc {
+                        loc: data.expr.loc,
+                        data,
+                    }),
+                );
+            }
+            saved = lowered.len();
+        }
+        lowered.push(lower_value(ctx, blocks, expr));
+    }
+    lowered
+}
+
+fn lower_call(
+    ctx: &mut Context,
+    blocks: &mut Vec<WithLoc<BasicBlock>>,
+    call: &WithLoc<TypedSubroutineCall>,
+) -> WithLoc<TypedSubroutineCall> {
+    let data = match &call.data {
+        TypedSubroutineCall::Method(receiver, name, args) => TypedSubroutineCall::Method(
+            receiver.clone(),
+            name.clone(),
+            lower_operands(ctx, blocks, args),
+        ),
+        TypedSubroutineCall::Function(class_name, name, args) => TypedSubroutineCall::Function(
+            class_name.clone(),
+            name.clone(),
+            lower_operands(ctx, blocks, args),
+        ),
+        TypedSubroutineCall::Constructor(class_name, name, args) => {
+            TypedSubroutineCall::Constructor(
+                class_name.clone(),
+                name.clone(),
+                lower_operands(ctx, blocks, args),
+            )
+        }
+    };
+    WithLoc {
+        loc: call.loc,
+        data,
+    }
+}
+
+fn typed_expr(loc: Location, ty: Type, term: TypedTerm) -> WithLoc<TypedExpression> {
+    WithLoc {
+        loc,
+        data: TypedExpression {
+            ty,
+            term: Box::new(term),
+        },
+    }
+}
+
+fn not(expr: WithLoc<TypedExpression>) -> WithLoc<TypedExpression> {
+    let loc = expr.loc;
+    let op = WithLoc {
+        loc,
+        data: UnaryOp::Not,
+    };
+    typed_expr(loc, Type::Boolean, TypedTerm::UnaryOp(op, expr))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast::ParseOptions, test_util};
+
+    fn compile(source: &str) -> String {
+        let parse_options = ParseOptions {
+            extensions: true,
+            ..ParseOptions::default()
+        };
+        let cfg_options = CfgOptions {
+            short_circuit: true,
+        };
+        let mut cfg = test_util::compile_with(source, parse_options, &cfg_options).cfg;
+        cfg.optimize().unwrap();
+        cfg.to_vm()
+            .iter()
+            .map(|command| format!("{}\n", command))
+            .collect()
+    }
+
+    #[test]
+    fn short_circuit_condition() {
+        let source = "\
+class Main {
+    function int find(Array a, int len, int x) {
+        var int i;
+        while ((i < len) & ~(a[i] = x)) {
+            let i = i + 1;
+        }
+        return i;
+    }
+}
+";
+        let expected = "\
+function Main.find 1
+label WHILE_EXP0
+push local 0
+push argument 1
+lt
+not
+if-goto WHILE_END0
+push local 0
+push argument 0
+add
+pop pointer 1
+push that 0
+push argument 2
+eq
+if-goto WHILE_END0
+push local 0
+push constant 1
+add
+pop local 0
+goto WHILE_EXP0
+label WHILE_END0
+push local 0
+return
+";
+        assert_eq!(compile(source), expected);
+    }
+
+    #[test]
+    fn short_circuit_value() {
+        let source = "\
+class Main {
+    function int f(int x) {
+        return Main.g(x) + Main.h((x > 0) | Main.b(x));
+    }
+    function int g(int x) {
+        return x;
+    }
+    function boolean b(int x) {
+        return x = 0;
+    }
+    function int h(boolean b) {
+        return 0;
+    }
+}
+";
+        let expected = "\
+function Main.f 2
+push argument 0
+call Main.g 1
+pop local 0
+push argument 0
+push constant 0
+gt
+if-goto COND_TRUE0
+push argument 0
+call Main.b 1
+not
+if-goto COND_FALSE0
+label COND_TRUE0
+push constant 0
+not
+pop local 1
+goto COND_END0
+label COND_FALSE0
+push constant 0
+pop local 1
+label COND_END0
+push local 0
+push local 1
+call Main.h 1
+add
+return
+function Main.g 0
+push argument 0
+return
+function Main.b 0
+push argument 0
+push constant 0
+eq
+return
+function Main.h 0
+push constant 0
+return
+";
+        assert_eq!(compile(source), expected);
+    }
+
+    #[test]
+    fn for_break_continue() {
+        let source = "\
+class Main {
+    function int f(int n) {
+        var int i, sum;
+        for (let i = 0; i < n; let i = i + 1) {
+            if (i = 3) {
+                continue;
+            } else if (i = 5) {
+                break;
+            }
+            let sum = sum + i;
+        }
+        return sum;
+    }
+}
+";
+        let expected = "\
+function Main.f 2
+push constant 0
+pop local 0
+label FOR_EXP0
+push local 0
+push argument 0
+lt
+not
+if-goto FOR_END0
+push local 0
+push constant 3
+eq
+if-goto FOR_STEP0
+push local 0
+push constant 5
+eq
+if-goto FOR_END0
+push local 1
+push local 0
+add
+pop local 1
+label FOR_STEP0
+push local 0
+push constant 1
+add
+pop local 0
+goto FOR_EXP0
+label FOR_END0
+push local 1
+return
+";
+        assert_eq!(compile(source), expected);
+    }
+
+    #[test]
+    fn constants() {
+        let source = "\
+class Main {
+    const int SIZE = Main.HALF * 2;
+    const int HALF = 'A' - 1;
+    const char NEWLINE = 128;
+    const boolean DEBUG = SIZE > 100;
+    const int MIN = -32767 - 1;
+    function int f() {
+        var int size;
+        if (DEBUG) {
+            let size = SIZE;
+        }
+        return size + NEWLINE + MIN;
+    }
+}
+";
+        let expected = "\
+function Main.f 1
+push constant 128
+pop local 0
+push local 0
+push constant 128
+add
+push constant 32767
+not
+add
+return
+";
+        assert_eq!(compile(source), expected);
+    }
+}
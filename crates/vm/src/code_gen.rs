@@ -28,10 +28,27 @@ impl<'a> CodeGen<'a> {
         }
     }
 
-    pub(crate) fn bootstrap(&mut self, name: &FuncName) {
-        self.load_imm_d(256);
-        self.store_d_address(AsmLabel::SP);
+    pub(crate) fn bootstrap(
+        &mut self,
+        registers: &[(AsmLabel, Imm)],
+        name: &FuncName,
+        with_frame: bool,
+    ) {
+        for (register, value) in registers {
+            self.load_imm_d(value.value());
+            self.store_d_address(register.clone());
+        }
+        if !with_frame {
+            // a call would overwrite LCL and ARG, so enter the function with the registers as set
+            let label = self.make_function_label(name);
+            self.jump(label);
+            return;
+        }
         self.call(name, 0);
+        // halt after the entry point returns
+        let label_end = self.make_internal_label("bootstrap", "end");
+        self.stmts.push(S::label(label_end.clone()));
+        self.jump(label_end);
     }
 
     pub(crate) fn push_imm(&mut self, imm: u16) {
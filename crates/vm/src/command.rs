@@ -1,5 +1,6 @@
 pub use self::parser::*;
 use asm::hack::Imm;
+use serde::{Serialize, Serializer};
 use std::borrow::Borrow;
 
 mod display;
@@ -34,6 +35,33 @@ impl Command {
             Command::Goto(..) | Command::IfGoto(..) | Command::Call(..) | Command::Return
         )
     }
+
+    /// Returns the number of values popped from and pushed to the stack by this command.
+    pub(crate) fn stack_effect(&self) -> (u16, u16) {
+        match self {
+            Command::Add
+            | Command::Sub
+            | Command::Eq
+            | Command::Gt
+            | Command::Lt
+            | Command::And
+            | Command::Or => (2, 1),
+            Command::Neg | Command::Not => (1, 1),
+            Command::Push(..) => (0, 1),
+            Command::Pop(..) | Command::IfGoto(..) | Command::Return => (1, 0),
+            Command::Label(..) | Command::Goto(..) | Command::Function(..) => (0, 0),
+            Command::Call(_, arity) => (u16::from(*arity), 1),
+        }
+    }
+}
+
+impl Serialize for Command {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -60,7 +88,7 @@ impl Segment {
         }
     }
 
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             Self::Argument => "argument",
             Self::Local => "local",
@@ -77,7 +105,7 @@ impl Segment {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Ident(String);
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct FuncName(String);
 
 impl Borrow<str> for FuncName {
@@ -116,7 +144,7 @@ impl FuncName {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct Label(String);
 
 impl Borrow<str> for Label {
@@ -132,6 +160,10 @@ impl From<Ident> for Label {
 }
 
 impl Label {
+    pub(crate) fn new(label: impl Into<String>) -> Self {
+        Self(label.into())
+    }
+
     pub fn as_str(&self) -> &str {
         &self.0
     }
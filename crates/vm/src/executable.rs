@@ -1,11 +1,84 @@
+pub use self::graph::*;
 pub(crate) use self::parser::*;
-use crate::{Command, FuncName, ModuleName};
-use std::collections::BTreeMap;
+pub use self::translator::*;
+use crate::{Command, FuncName, ModuleName, ParseModuleWarning};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+mod graph;
+mod inliner;
 mod parser;
 mod translator;
 
 #[derive(Debug, Clone)]
 pub struct Executable {
     functions: BTreeMap<FuncName, (ModuleName, Vec<Command>)>,
+    warnings: Vec<ParseModuleWarning>,
+}
+
+impl Executable {
+    /// Returns the function executed first.
+    ///
+    /// This is `name` if given, otherwise `Sys.init` if defined, otherwise the only function of the
+    /// executable.
+    fn entry_point(&self, name: Option<&str>) -> Result<&FuncName, TranslateError> {
+        if let Some(name) = name {
+            return self
+                .functions
+                .get_key_value(name)
+                .map(|(func_name, _)| func_name)
+                .ok_or_else(|| TranslateError::EntryPointNotDefined(name.to_string()));
+        }
+        if let Some((func_name, _)) = self.functions.get_key_value(&FuncName::entry_point()) {
+            return Ok(func_name);
+        }
+        match self.functions.keys().next() {
+            Some(func_name) if self.functions.len() == 1 => Ok(func_name),
+            _ => Err(TranslateError::NoEntryPoint),
+        }
+    }
+
+    /// Returns the functions transitively called from `roots`.
+    fn reachable_functions<'a>(
+        &'a self,
+        roots: impl IntoIterator<Item = &'a FuncName>,
+    ) -> BTreeSet<&'a FuncName> {
+        let mut reachable = BTreeSet::new();
+        let mut to_visit = roots.into_iter().collect::<VecDeque<_>>();
+        while let Some(func_name) = to_visit.pop_front() {
+            if !reachable.insert(func_name) {
+                continue;
+            }
+            let (_, commands) = &self.functions[func_name];
+            for command in commands {
+                if let Command::Call(callee, _) = command {
+                    let (callee, _) = self.functions.get_key_value(callee).unwrap();
+                    to_visit.push_back(callee);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Returns the warnings found while parsing the modules.
+    pub fn warnings(&self) -> &[ParseModuleWarning] {
+        &self.warnings
+    }
+
+    pub fn contains_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+}
+
+/// Builds an executable from modules given as pairs of a path and lines of commands.
+#[cfg(test)]
+fn exec(modules: &[(&str, &[&str])]) -> Executable {
+    use std::{io::Cursor, path::PathBuf};
+
+    Executable::from_readers(modules.iter().map(|(path, lines)| {
+        (
+            PathBuf::from(path),
+            Cursor::new(lines.join("\n").into_bytes()),
+        )
+    }))
+    .unwrap()
 }
@@ -0,0 +1,408 @@
+use super::{Executable, TranslateOptions};
+use crate::{Command, FuncName, Label, ModuleName};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+/// Functions of an executable and the calls between them.
+#[derive(Debug, Clone, Serialize)]
+pub struct CallGraph {
+    pub entry_point: Option<FuncName>,
+    pub functions: Vec<FunctionNode>,
+    pub calls: Vec<CallEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionNode {
+    pub name: FuncName,
+    pub module: ModuleName,
+    /// Number of arguments passed by callers, or `None` if the function is never called.
+    pub arity: Option<u8>,
+    pub num_locals: u8,
+    pub num_commands: usize,
+    pub reachable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallEdge {
+    pub caller: FuncName,
+    pub callee: FuncName,
+    pub arity: u8,
+    /// Number of `call` commands from the caller to the callee.
+    pub count: usize,
+}
+
+/// Control-flow graphs of every function of an executable.
+#[derive(Debug, Clone, Serialize)]
+pub struct ControlFlowGraph {
+    pub functions: Vec<FunctionCfg>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionCfg {
+    pub name: FuncName,
+    pub module: ModuleName,
+    pub reachable: bool,
+    pub blocks: Vec<BasicBlock>,
+}
+
+/// A sequence of commands starting at a `label` or after a jump, and ending with a jump or before a
+/// `label`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BasicBlock {
+    pub label: Option<Label>,
+    pub commands: Vec<Command>,
+    /// Indices of the blocks to which the control flows from the end of this block.
+    pub successors: Vec<usize>,
+}
+
+impl Executable {
+    /// Returns the call graph, marking the functions reachable from the entry point of `options`.
+    pub fn call_graph(&self, options: &TranslateOptions) -> CallGraph {
+        let entry_point = self.entry_point(options.entry_point.as_deref()).ok();
+        let reachable = self.reachable_functions(entry_point);
+
+        let mut calls = BTreeMap::<(&FuncName, &FuncName), CallEdge>::new();
+        for (caller, (_, commands)) in &self.functions {
+            for command in commands {
+                if let Command::Call(callee, arity) = command {
+                    calls
+                        .entry((caller, callee))
+                        .or_insert_with(|| CallEdge {
+                            caller: caller.clone(),
+                            callee: callee.clone(),
+                            arity: *arity,
+                            count: 0,
+                        })
+                        .count += 1;
+                }
+            }
+        }
+        let arities = calls
+            .values()
+            .map(|call| (&call.callee, call.arity))
+            .collect::<HashMap<_, _>>();
+
+        let functions = self
+            .functions
+            .iter()
+            .map(|(name, (module, commands))| FunctionNode {
+                name: name.clone(),
+                module: module.clone(),
+                arity: arities.get(name).copied(),
+                num_locals: match commands.first() {
+                    Some(Command::Function(_, num_locals)) => *num_locals,
+                    _ => 0,
+                },
+                num_commands: commands.len(),
+                reachable: reachable.contains(name),
+            })
+            .collect();
+
+        CallGraph {
+            entry_point: entry_point.cloned(),
+            functions,
+            calls: calls.into_values().collect(),
+        }
+    }
+
+    /// Returns the control-flow graphs, marking the functions reachable from the entry point of
+    /// `options`.
+    pub fn control_flow_graph(&self, options: &TranslateOptions) -> ControlFlowGraph {
+        let entry_point = self.entry_point(options.entry_point.as_deref()).ok();
+        let reachable = self.reachable_functions(entry_point);
+        let functions = self
+            .functions
+            .iter()
+            .map(|(name, (module, commands))| FunctionCfg {
+                name: name.clone(),
+                module: module.clone(),
+                reachable: reachable.contains(name),
+                blocks: split_blocks(commands),
+            })
+            .collect();
+        ControlFlowGraph { functions }
+    }
+}
+
+fn split_blocks(commands: &[Command]) -> Vec<BasicBlock> {
+    let mut blocks = vec![];
+    let mut current = None;
+    for command in commands {
+        if let Command::Label(label) = command {
+            blocks.extend(current.take());
+            current = Some(BasicBlock {
+                label: Some(label.clone()),
+                commands: vec![],
+                successors: vec![],
+            });
+        }
+        let block = current.get_or_insert_with(|| BasicBlock {
+            label: None,
+            commands: vec![],
+            successors: vec![],
+        });
+        block.commands.push(command.clone());
+        if matches!(
+            command,
+            Command::Goto(..) | Command::IfGoto(..) | Command::Return
+        ) {
+            blocks.extend(current.take());
+        }
+    }
+    blocks.extend(current);
+
+    let labels = blocks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, block)| block.label.clone().map(|label| (label, index)))
+        .collect::<HashMap<_, _>>();
+    let num_blocks = blocks.len();
+    for (index, block) in blocks.iter_mut().enumerate() {
+        let fall_through = (index + 1 < num_blocks).then_some(index + 1);
+        block.successors = match block.commands.last() {
+            Some(Command::Goto(label)) => vec![labels[label]],
+            Some(Command::IfGoto(label)) => {
+                let mut successors = vec![labels[label]];
+                successors.extend(fall_through.filter(|next| *next != labels[label]));
+                successors
+            }
+            Some(Command::Return) => vec![],
+            _ => fall_through.into_iter().collect(),
+        };
+    }
+    blocks
+}
+
+impl CallGraph {
+    pub fn write_dot(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "digraph call_graph {{")?;
+        writeln!(writer, "  node [shape=box];")?;
+        for func in &self.functions {
+            let arity = func
+                .arity
+                .map(|arity| arity.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            write!(
+                writer,
+                "  {} [label={}",
+                quote(func.name.as_str()),
+                quote(&format!(
+                    "{}\n{}, arity {}, {} locals",
+                    func.name, func.module, arity, func.num_locals
+                ))
+            )?;
+            if self.entry_point.as_ref() == Some(&func.name) {
+                write!(writer, ", peripheries=2")?;
+            }
+            if !func.reachable {
+                write!(writer, ", style=dashed, color=gray, fontcolor=gray")?;
+            }
+            writeln!(writer, "];")?;
+        }
+        for call in &self.calls {
+            write!(
+                writer,
+                "  {} -> {}",
+                quote(call.caller.as_str()),
+                quote(call.callee.as_str())
+            )?;
+            if call.count > 1 {
+                write!(writer, " [label={}]", quote(&format!("x{}", call.count)))?;
+            }
+            writeln!(writer, ";")?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        write_json(self, writer)
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn write_dot(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "digraph control_flow_graph {{")?;
+        writeln!(writer, "  node [shape=box, fontname=monospace];")?;
+        for (func_index, func) in self.functions.iter().enumerate() {
+            let node_id = |block_index| format!("f{}_b{}", func_index, block_index);
+            writeln!(writer, "  subgraph cluster_{} {{", func_index)?;
+            writeln!(writer, "    label={};", quote(func.name.as_str()))?;
+            if !func.reachable {
+                writeln!(writer, "    style=dashed; color=gray; fontcolor=gray;")?;
+            }
+            for (block_index, block) in func.blocks.iter().enumerate() {
+                let label = block
+                    .commands
+                    .iter()
+                    .map(|command| format!("{}\\l", escape(&command.to_string())))
+                    .collect::<String>();
+                writeln!(
+                    writer,
+                    "    {} [label=\"{}\"];",
+                    node_id(block_index),
+                    label
+                )?;
+            }
+            for (block_index, block) in func.blocks.iter().enumerate() {
+                for successor in &block.successors {
+                    writeln!(
+                        writer,
+                        "    {} -> {};",
+                        node_id(block_index),
+                        node_id(*successor)
+                    )?;
+                }
+            }
+            writeln!(writer, "  }}")?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        write_json(self, writer)
+    }
+}
+
+fn write_json(value: &impl Serialize, mut writer: impl Write) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writeln!(writer)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executable::exec;
+
+    #[test]
+    fn call_graph() {
+        let exec = exec(&[
+            (
+                "Sys.vm",
+                &[
+                    "function Sys.init 0",
+                    "push constant 1",
+                    "call Main.f 1",
+                    "push constant 2",
+                    "call Main.f 1",
+                    "return",
+                ],
+            ),
+            (
+                "Main.vm",
+                &[
+                    "function Main.f 1",
+                    "push argument 0",
+                    "return",
+                    "function Main.g 0",
+                    "push constant 0",
+                    "call Main.g 0",
+                    "return",
+                ],
+            ),
+        ]);
+
+        let graph = exec.call_graph(&TranslateOptions::default());
+        assert_eq!(graph.entry_point.as_ref().unwrap().as_str(), "Sys.init");
+        let functions = graph
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.module.as_str(), f.arity, f.reachable))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            functions,
+            [
+                ("Main.f", "Main", Some(1), true),
+                ("Main.g", "Main", Some(0), false),
+                ("Sys.init", "Sys", None, true),
+            ]
+        );
+        let calls = graph
+            .calls
+            .iter()
+            .map(|c| (c.caller.as_str(), c.callee.as_str(), c.count))
+            .collect::<Vec<_>>();
+        assert_eq!(calls, [("Main.g", "Main.g", 1), ("Sys.init", "Main.f", 2)]);
+
+        let mut dot = vec![];
+        graph.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains("\"Sys.init\" -> \"Main.f\" [label=\"x2\"];"));
+        assert!(dot.contains("style=dashed"));
+
+        let options = TranslateOptions {
+            entry_point: Some("Main.g".to_string()),
+            ..TranslateOptions::default()
+        };
+        let graph = exec.call_graph(&options);
+        assert_eq!(graph.entry_point.as_ref().unwrap().as_str(), "Main.g");
+        let reachable = graph
+            .functions
+            .iter()
+            .filter(|f| f.reachable)
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(reachable, ["Main.g"]);
+        let cfg = exec.control_flow_graph(&options);
+        let reachable = cfg
+            .functions
+            .iter()
+            .filter(|f| f.reachable)
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(reachable, ["Main.g"]);
+    }
+
+    #[test]
+    fn basic_blocks() {
+        let commands = [
+            "function f 0",
+            "push argument 0",
+            "if-goto A",
+            "push constant 1",
+            "goto B",
+            "label A",
+            "push constant 2",
+            "label B",
+            "return",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect::<Vec<Command>>();
+
+        let blocks = split_blocks(&commands);
+        let summary = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.label.as_ref().map(|label| label.as_str()),
+                    block.commands.len(),
+                    block.successors.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (None, 3, vec![2, 1]),
+                (None, 2, vec![3]),
+                (Some("A"), 2, vec![3]),
+                (Some("B"), 2, vec![]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use super::Executable;
+use crate::{module, Command, FuncName, Label, ModuleName, Segment};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+#[derive(Debug)]
+pub(super) struct Inliner<'a> {
+    candidates: BTreeMap<&'a FuncName, Candidate<'a>>,
+}
+
+#[derive(Debug)]
+struct Candidate<'a> {
+    name: &'a FuncName,
+    module_name: &'a ModuleName,
+    num_locals: u8,
+    num_args: u16,
+    saved_pointers: Vec<u16>,
+    body: &'a [Command],
+}
+
+impl<'a> Inliner<'a> {
+    pub(super) fn new(exec: &'a Executable, budget: usize) -> Self {
+        let candidates = exec
+            .functions
+            .iter()
+            .filter_map(|(func_name, (module_name, commands))| {
+                Candidate::new(func_name, module_name, commands, budget)
+                    .map(|candidate| (func_name, candidate))
+            })
+            .collect();
+        Self { candidates }
+    }
+
+    pub(super) fn expand<'b>(
+        &'b self,
+        module_name: &'b ModuleName,
+        commands: &[Command],
+    ) -> Vec<(&'b ModuleName, Command)> {
+        let not_inlined = || {
+            commands
+                .iter()
+                .map(|command| (module_name, command.clone()))
+                .collect()
+        };
+
+        // toplevel functions have no frame to hold the callee's arguments and locals
+        let (func_name, num_locals) = match commands.first() {
+            Some(Command::Function(func_name, num_locals)) => (func_name, *num_locals),
+            _ => return not_inlined(),
+        };
+
+        let frame_size = commands
+            .iter()
+            .filter_map(|command| self.candidate_for(command))
+            .map(|(candidate, arity)| candidate.frame_size(arity))
+            .max();
+        let frame_size = match frame_size {
+            Some(frame_size) if u16::from(num_locals) + frame_size <= u16::from(u8::MAX) => {
+                frame_size
+            }
+            _ => return not_inlined(),
+        };
+
+        let mut expanded = vec![(
+            module_name,
+            Command::Function(
+                func_name.clone(),
+                num_locals + u8::try_from(frame_size).unwrap(),
+            ),
+        )];
+        let mut site_count = 0;
+        for command in &commands[1..] {
+            if let Some((candidate, arity)) = self.candidate_for(command) {
+                candidate.inline(site_count, arity, u16::from(num_locals), &mut expanded);
+                site_count += 1;
+            } else {
+                expanded.push((module_name, command.clone()));
+            }
+        }
+        expanded
+    }
+
+    fn candidate_for(&self, command: &Command) -> Option<(&Candidate<'a>, u8)> {
+        match command {
+            Command::Call(callee, arity) => self
+                .candidates
+                .get(callee)
+                .filter(|candidate| candidate.num_args <= u16::from(*arity))
+                .map(|candidate| (candidate, *arity)),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Candidate<'a> {
+    fn new(
+        name: &'a FuncName,
+        module_name: &'a ModuleName,
+        commands: &'a [Command],
+        budget: usize,
+    ) -> Option<Self> {
+        let num_locals = match commands.first() {
+            Some(Command::Function(_, num_locals)) => *num_locals,
+            _ => return None,
+        };
+        let body = &commands[1..];
+        if body.len() > budget || !returns_single_value(commands) {
+            return None;
+        }
+
+        let mut num_args = 0;
+        let mut saved_pointers = BTreeSet::new();
+        for command in body {
+            match command {
+                Command::Call(..) | Command::Function(..) => return None,
+                Command::Push(Segment::Argument, index)
+                | Command::Pop(Segment::Argument, index) => {
+                    num_args = u16::max(num_args, index + 1);
+                }
+                Command::Pop(Segment::Pointer, index) => {
+                    saved_pointers.insert(*index);
+                }
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            name,
+            module_name,
+            num_locals,
+            num_args,
+            saved_pointers: saved_pointers.into_iter().collect(),
+            body,
+        })
+    }
+
+    fn frame_size(&self, arity: u8) -> u16 {
+        u16::from(arity) + u16::from(self.num_locals) + self.saved_pointers.len() as u16
+    }
+
+    fn inline<'b>(
+        &'b self,
+        site: usize,
+        arity: u8,
+        base: u16,
+        expanded: &mut Vec<(&'b ModuleName, Command)>,
+    ) {
+        let arg_base = base;
+        let local_base = arg_base + u16::from(arity);
+        let saved_base = local_base + u16::from(self.num_locals);
+        let module_name = self.module_name;
+        let mut emit = |command| expanded.push((module_name, command));
+
+        // move arguments from the stack into the caller's frame
+        for index in (0..u16::from(arity)).rev() {
+            emit(Command::Pop(Segment::Local, arg_base + index));
+        }
+        // locals are implicitly initialized with zero
+        for index in 0..u16::from(self.num_locals) {
+            emit(Command::Push(Segment::Constant, 0));
+            emit(Command::Pop(Segment::Local, local_base + index));
+        }
+        // THIS/THAT are restored on return, so keep the caller's values
+        for (slot, pointer) in (saved_base..).zip(&self.saved_pointers) {
+            emit(Command::Push(Segment::Pointer, *pointer));
+            emit(Command::Pop(Segment::Local, slot));
+        }
+
+        let make_label =
+            |label: &dyn fmt::Display| Label::new(format!("{}${}:{}", self.name, site, label));
+        let return_label = make_label(&"$return");
+        let mut has_early_return = false;
+        for (index, command) in self.body.iter().enumerate() {
+            let command = match command {
+                Command::Push(Segment::Argument, index) => {
+                    Command::Push(Segment::Local, arg_base + index)
+                }
+                Command::Pop(Segment::Argument, index) => {
+                    Command::Pop(Segment::Local, arg_base + index)
+                }
+                Command::Push(Segment::Local, index) => {
+                    Command::Push(Segment::Local, local_base + index)
+                }
+                Command::Pop(Segment::Local, index) => {
+                    Command::Pop(Segment::Local, local_base + index)
+                }
+                Command::Label(label) => Command::Label(make_label(label)),
+                Command::Goto(label) => Command::Goto(make_label(label)),
+                Command::IfGoto(label) => Command::IfGoto(make_label(label)),
+                Command::Return if index + 1 == self.body.len() => continue,
+                Command::Return => {
+                    has_early_return = true;
+                    Command::Goto(return_label.clone())
+                }
+                command => command.clone(),
+            };
+            emit(command);
+        }
+        if has_early_return {
+            emit(Command::Label(return_label));
+        }
+
+        for (slot, pointer) in (saved_base..).zip(&self.saved_pointers) {
+            emit(Command::Push(Segment::Local, slot));
+            emit(Command::Pop(Segment::Pointer, *pointer));
+        }
+    }
+}
+
+/// Checks that every `return` leaves exactly one value on the callee's stack, so that the inlined
+/// body leaves only the return value behind.
+fn returns_single_value(commands: &[Command]) -> bool {
+    let depths = match module::stack_depths(commands, &mut vec![]) {
+        Ok(depths) => depths,
+        Err(_) => return false,
+    };
+    commands
+        .iter()
+        .zip(depths)
+        .all(|(command, depth)| !matches!(command, Command::Return) || depth.is_none_or(|d| d == 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executable::exec;
+    use std::str::FromStr;
+
+    fn commands(lines: &[&str]) -> Vec<Command> {
+        lines
+            .iter()
+            .map(|s| Command::from_str(s).unwrap())
+            .collect()
+    }
+
+    fn expand(exec: &Executable, budget: usize, func_name: &str) -> Vec<String> {
+        let inliner = Inliner::new(exec, budget);
+        let (module_name, commands) = &exec.functions[func_name];
+        inliner
+            .expand(module_name, commands)
+            .into_iter()
+            .map(|(module_name, command)| format!("{} {}", module_name, command))
+            .collect()
+    }
+
+    #[test]
+    fn stack_discipline() {
+        assert!(returns_single_value(&commands(&[
+            "function f 0",
+            "push argument 0",
+            "return",
+        ])));
+        assert!(returns_single_value(&commands(&[
+            "function f 0",
+            "push argument 0",
+            "if-goto A",
+            "push constant 1",
+            "return",
+            "label A",
+            "push constant 2",
+            "return",
+        ])));
+        assert!(!returns_single_value(&commands(&[
+            "function f 0",
+            "push constant 1",
+            "push constant 2",
+            "return",
+        ])));
+        assert!(!returns_single_value(&commands(&[
+            "function f 0",
+            "label A",
+            "push constant 1",
+            "goto A",
+        ])));
+    }
+
+    #[test]
+    fn inline_leaf_function() {
+        let exec = exec(&[
+            (
+                "Sys.vm",
+                &[
+                    "function Sys.init 1",
+                    "push constant 3",
+                    "call Math.abs 1",
+                    "pop local 0",
+                    "push local 0",
+                    "call Math.abs 1",
+                    "return",
+                ],
+            ),
+            (
+                "Math.vm",
+                &[
+                    "function Math.abs 0",
+                    "push argument 0",
+                    "push constant 0",
+                    "lt",
+                    "if-goto NEG",
+                    "push argument 0",
+                    "return",
+                    "label NEG",
+                    "push argument 0",
+                    "neg",
+                    "return",
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            expand(&exec, 0, "Sys.init"),
+            commands(&[
+                "function Sys.init 1",
+                "push constant 3",
+                "call Math.abs 1",
+                "pop local 0",
+                "push local 0",
+                "call Math.abs 1",
+                "return",
+            ])
+            .iter()
+            .map(|command| format!("Sys {}", command))
+            .collect::<Vec<_>>()
+        );
+
+        let expanded = expand(&exec, 10, "Sys.init");
+        assert_eq!(expanded[0], "Sys function Sys.init 2");
+        assert!(expanded.iter().all(|s| !s.contains("call")));
+        assert!(expanded.contains(&"Math pop local 1".to_string()));
+        assert!(expanded.contains(&"Math goto Math.abs$1:$return".to_string()));
+        assert!(expanded.contains(&"Math label Math.abs$1:NEG".to_string()));
+        assert_eq!(expanded.last().unwrap(), "Sys return");
+    }
+
+    #[test]
+    fn save_pointers() {
+        let exec = exec(&[
+            (
+                "Sys.vm",
+                &[
+                    "function Sys.init 0",
+                    "push constant 8000",
+                    "call Memory.peek 1",
+                    "return",
+                ],
+            ),
+            (
+                "Memory.vm",
+                &[
+                    "function Memory.peek 0",
+                    "push argument 0",
+                    "pop pointer 1",
+                    "push that 0",
+                    "return",
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            expand(&exec, 10, "Sys.init"),
+            [
+                "Sys function Sys.init 2",
+                "Sys push constant 8000",
+                "Memory pop local 0",
+                "Memory push pointer 1",
+                "Memory pop local 1",
+                "Memory push local 0",
+                "Memory pop pointer 1",
+                "Memory push that 0",
+                "Memory push local 1",
+                "Memory pop pointer 1",
+                "Sys return",
+            ]
+        );
+    }
+}
@@ -18,7 +18,14 @@ impl Executable {
             return Err(ParseExecutableError::NoModules);
         }
         let functions = functions.finish()?;
-        Ok(Self { functions })
+        let warnings = modules
+            .into_iter()
+            .flat_map(|module| module.warnings)
+            .collect();
+        Ok(Self {
+            functions,
+            warnings,
+        })
     }
 }
 
@@ -52,8 +59,6 @@ pub enum ParseExecutableError {
         _2.arity
     )]
     ArityMismatch(FuncName, FuncProp, FuncProp),
-    #[error("multiple function found, but there is not entry point")]
-    NoEntryPoint,
 }
 
 #[derive(Debug, Clone)]
@@ -145,10 +150,6 @@ impl FunctionTable {
                 _ => None,
             })
             .unwrap_or(Ok(()))?;
-
-        if !functions.contains_key(&FuncName::entry_point()) && functions.len() > 1 {
-            return Err(ParseExecutableError::NoEntryPoint);
-        }
         Ok(functions)
     }
 }
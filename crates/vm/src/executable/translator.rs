@@ -1,53 +1,512 @@
-use super::Executable;
+use super::{inliner::Inliner, Executable};
 use crate::{code_gen::CodeGen, Command, FuncName, ModuleName};
-use asm::Statement;
-use std::collections::{BTreeSet, VecDeque};
+use asm::{hack::Imm, Label as AsmLabel, Statement};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Default)]
+pub struct TranslateOptions {
+    /// Maximum number of commands in the body of a leaf function inlined into its callers.
+    /// Inlining is disabled if this is zero.
+    pub inline_budget: usize,
+    /// Translates all functions, including ones unreachable from the entry point.
+    pub no_prune: bool,
+    /// Functions translated even if they are unreachable from the entry point.
+    /// Names of undefined functions are ignored.
+    pub keep: Vec<String>,
+    /// Function called by the bootstrap code instead of `Sys.init`.
+    pub entry_point: Option<String>,
+    /// Omits the bootstrap code, so that the code of the entry point is placed at address 0.
+    pub no_bootstrap: bool,
+    pub bootstrap: Bootstrap,
+}
+
+/// Initial values of the registers set by the bootstrap code before calling the entry point.
+#[derive(Debug, Clone)]
+pub struct Bootstrap {
+    pub sp: Imm,
+    /// Registers set to `None` are left uninitialized.
+    ///
+    /// If `lcl` or `arg` is set, the bootstrap code jumps to the entry point without pushing a
+    /// call frame, so that the entry point sees both registers as given. Its `return` then uses
+    /// the frame found below `LCL`, which the caller of the program must have prepared.
+    pub lcl: Option<Imm>,
+    pub arg: Option<Imm>,
+    pub this: Option<Imm>,
+    pub that: Option<Imm>,
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self {
+            sp: Imm::try_new(256).unwrap(),
+            lcl: None,
+            arg: None,
+            this: None,
+            that: None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TranslateError {
+    #[error("entry point is not defined: {}", _0)]
+    EntryPointNotDefined(String),
+    #[error("multiple functions found, but there is no entry point")]
+    NoEntryPoint,
+}
+
+/// Functions removed from the output of [`Executable::translate`].
+#[derive(Debug, Clone)]
+pub struct PruneReport {
+    pub removed: Vec<RemovedFunction>,
+    /// Modules none of whose functions are reachable from the entry point or kept functions.
+    /// This is reported even if pruning is disabled.
+    pub unreachable_modules: Vec<ModuleName>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemovedFunction {
+    pub name: FuncName,
+    pub module: ModuleName,
+    pub num_commands: usize,
+    /// Number of Hack instructions the function would have been translated into.
+    pub num_instructions: usize,
+}
 
 impl Executable {
-    pub fn translate(&self) -> Vec<Statement> {
+    pub fn translate(&self, options: &TranslateOptions) -> Result<Vec<Statement>, TranslateError> {
         let mut stmts = Vec::new();
-        let entry_point = self.bootstrap(&mut stmts);
-
-        let mut visited = BTreeSet::new();
-        let mut to_visit = VecDeque::new();
-        if let Some(entry_point) = entry_point {
-            to_visit.push_front(entry_point);
+        let entry_point = self.entry_point(options.entry_point.as_deref())?;
+        // a single function without `Sys.init` is a test program run without the bootstrap code
+        if !options.no_bootstrap
+            && (options.entry_point.is_some() || *entry_point == FuncName::entry_point())
+        {
+            bootstrap(&options.bootstrap, entry_point, &mut stmts);
         }
+        let inliner = Inliner::new(self, options.inline_budget);
+
+        let mut bodies = BTreeMap::new();
+        let mut to_visit = [entry_point]
+            .into_iter()
+            .chain(self.roots(options))
+            .collect::<VecDeque<_>>();
         while let Some(func_name) = to_visit.pop_front() {
-            visited.insert(func_name);
-            let (_, commands) = self.functions.get(func_name).unwrap();
-            for command in commands {
+            if bodies.contains_key(func_name) {
+                continue;
+            }
+            let (module_name, commands) = self.functions.get(func_name).unwrap();
+            let body = inliner.expand(module_name, commands);
+            for (_, command) in &body {
                 if let Command::Call(callee, _) = command {
-                    if !visited.contains(callee) {
+                    let (callee, _) = self.functions.get_key_value(callee).unwrap();
+                    if !bodies.contains_key(callee) {
                         to_visit.push_back(callee);
                     }
                 }
             }
+            bodies.insert(func_name, body);
         }
 
-        for func_name in visited {
-            let (module_name, commands) = self.functions.get(func_name).unwrap();
+        // the entry point comes first to be executed when there is no bootstrap code
+        let entry_body = bodies.remove(entry_point).map(|body| (entry_point, body));
+        for (func_name, body) in entry_body.into_iter().chain(bodies) {
+            for (index, (module_name, command)) in body.iter().enumerate() {
+                command.translate(module_name, func_name, index, &mut stmts);
+            }
+        }
+
+        Ok(stmts)
+    }
+
+    /// Reports the functions removed by [`Executable::translate`] with the given options.
+    ///
+    /// Functions unreachable only because all of their calls are inlined are not reported.
+    pub fn prune_report(&self, options: &TranslateOptions) -> Result<PruneReport, TranslateError> {
+        let mut roots = self.roots(&TranslateOptions {
+            no_prune: false,
+            ..options.clone()
+        });
+        roots.push(self.entry_point(options.entry_point.as_deref())?);
+        let reachable = self.reachable_functions(roots);
+
+        let mut removed = vec![];
+        let mut live_modules = BTreeSet::new();
+        for (func_name, (module_name, commands)) in &self.functions {
+            if reachable.contains(func_name) {
+                live_modules.insert(module_name);
+                continue;
+            }
+            if options.no_prune {
+                continue;
+            }
+            let mut stmts = vec![];
             for (index, command) in commands.iter().enumerate() {
                 command.translate(module_name, func_name, index, &mut stmts);
             }
+            removed.push(RemovedFunction {
+                name: func_name.clone(),
+                module: module_name.clone(),
+                num_commands: commands.len(),
+                num_instructions: stmts
+                    .iter()
+                    .filter(|stmt| !matches!(stmt, Statement::Label(_)))
+                    .count(),
+            });
         }
 
-        stmts
+        let unreachable_modules = self
+            .functions
+            .values()
+            .map(|(module_name, _)| module_name)
+            .filter(|module_name| !live_modules.contains(module_name))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .cloned()
+            .collect();
+
+        Ok(PruneReport {
+            removed,
+            unreachable_modules,
+        })
     }
 
-    fn bootstrap(&self, stmts: &mut Vec<Statement>) -> Option<&FuncName> {
-        let module_name = ModuleName::builtin();
-        let func_name = FuncName::bootstrap();
-        let mut gen = CodeGen::new(&module_name, &func_name, 0, stmts);
-        let entry_point = if let Some((entry_point, _)) =
-            self.functions.get_key_value(&FuncName::entry_point())
-        {
-            gen.bootstrap(entry_point);
-            Some(entry_point)
-        } else {
-            assert!(self.functions.len() <= 1);
-            self.functions.keys().next()
+    /// Returns the functions translated in addition to the ones reachable from the entry point.
+    fn roots(&self, options: &TranslateOptions) -> Vec<&FuncName> {
+        if options.no_prune {
+            return self.functions.keys().collect();
+        }
+        options
+            .keep
+            .iter()
+            .filter_map(|name| self.functions.get_key_value(name.as_str()))
+            .map(|(func_name, _)| func_name)
+            .collect()
+    }
+}
+
+fn bootstrap(bootstrap: &Bootstrap, entry_point: &FuncName, stmts: &mut Vec<Statement>) {
+    let module_name = ModuleName::builtin();
+    let func_name = FuncName::bootstrap();
+    let mut gen = CodeGen::new(&module_name, &func_name, 0, stmts);
+    let registers = [
+        (AsmLabel::SP, Some(bootstrap.sp)),
+        (AsmLabel::LCL, bootstrap.lcl),
+        (AsmLabel::ARG, bootstrap.arg),
+        (AsmLabel::THIS, bootstrap.this),
+        (AsmLabel::THAT, bootstrap.that),
+    ]
+    .into_iter()
+    .filter_map(|(register, value)| value.map(|value| (register, value)))
+    .collect::<Vec<_>>();
+    let with_frame = bootstrap.lcl.is_none() && bootstrap.arg.is_none();
+    gen.bootstrap(&registers, entry_point, with_frame);
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for func in &self.removed {
+            writeln!(
+                f,
+                "removed {} ({}): {} commands, {} instructions",
+                func.name, func.module, func.num_commands, func.num_instructions
+            )?;
+        }
+        writeln!(
+            f,
+            "removed {} functions: {} commands, {} instructions",
+            self.removed.len(),
+            self.removed
+                .iter()
+                .map(|func| func.num_commands)
+                .sum::<usize>(),
+            self.removed
+                .iter()
+                .map(|func| func.num_instructions)
+                .sum::<usize>()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::executable::exec;
+    use std::io::Cursor;
+
+    fn translated_functions(exec: &Executable, options: &TranslateOptions) -> Vec<String> {
+        exec.translate(options)
+            .unwrap()
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Label(label) => Some(label.to_string()),
+                _ => None,
+            })
+            .filter(|label| !label.contains(':') && !label.starts_with('$'))
+            .collect()
+    }
+
+    #[test]
+    fn prune() {
+        let exec = exec(&[
+            (
+                "Sys.vm",
+                &["function Sys.init 0", "call Main.main 0", "return"],
+            ),
+            (
+                "Main.vm",
+                &[
+                    "function Main.main 0",
+                    "push constant 0",
+                    "return",
+                    "function Main.unused 0",
+                    "push constant 1",
+                    "return",
+                ],
+            ),
+            (
+                "Dead.vm",
+                &["function Dead.f 0", "push constant 2", "return"],
+            ),
+        ]);
+
+        let options = TranslateOptions::default();
+        assert_eq!(
+            translated_functions(&exec, &options),
+            ["Sys.init", "Main.main"]
+        );
+        let report = exec.prune_report(&options).unwrap();
+        let removed = report
+            .removed
+            .iter()
+            .map(|func| (func.name.as_str(), func.num_commands))
+            .collect::<Vec<_>>();
+        assert_eq!(removed, [("Dead.f", 3), ("Main.unused", 3)]);
+        assert!(report.removed.iter().all(|func| func.num_instructions > 0));
+        assert_eq!(report.unreachable_modules.len(), 1);
+        assert_eq!(report.unreachable_modules[0].as_str(), "Dead");
+
+        let options = TranslateOptions {
+            keep: vec!["Main.unused".to_string(), "Undefined.f".to_string()],
+            ..TranslateOptions::default()
+        };
+        assert_eq!(
+            translated_functions(&exec, &options),
+            ["Sys.init", "Main.main", "Main.unused"]
+        );
+        assert_eq!(exec.prune_report(&options).unwrap().removed.len(), 1);
+
+        let options = TranslateOptions {
+            no_prune: true,
+            ..TranslateOptions::default()
+        };
+        assert_eq!(
+            translated_functions(&exec, &options),
+            ["Sys.init", "Dead.f", "Main.main", "Main.unused"]
+        );
+        let report = exec.prune_report(&options).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.unreachable_modules.len(), 1);
+    }
+
+    #[test]
+    fn entry_point_and_bootstrap() {
+        let exec = exec(&[(
+            "Main.vm",
+            &[
+                "function Main.main 0",
+                "call Main.test 0",
+                "return",
+                "function Main.test 0",
+                "push constant 0",
+                "return",
+            ],
+        )]);
+        assert!(matches!(
+            exec.translate(&TranslateOptions::default()),
+            Err(TranslateError::NoEntryPoint)
+        ));
+        assert!(matches!(
+            exec.translate(&TranslateOptions {
+                entry_point: Some("Main.undefined".to_string()),
+                ..TranslateOptions::default()
+            }),
+            Err(TranslateError::EntryPointNotDefined(_))
+        ));
+
+        let options = TranslateOptions {
+            entry_point: Some("Main.test".to_string()),
+            bootstrap: Bootstrap {
+                sp: Imm::try_new(300).unwrap(),
+                this: Some(Imm::try_new(3000).unwrap()),
+                ..Bootstrap::default()
+            },
+            ..TranslateOptions::default()
+        };
+        let stmts = exec
+            .translate(&options)
+            .unwrap()
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(stmts[..6], ["@300", "D=A", "@SP", "M=D", "@3000", "D=A"]);
+        assert_eq!(stmts[6..8], ["@THIS", "M=D"]);
+        assert_eq!(translated_functions(&exec, &options), ["Main.test"]);
+
+        let options = TranslateOptions {
+            entry_point: Some("Main.main".to_string()),
+            no_bootstrap: true,
+            ..TranslateOptions::default()
+        };
+        assert_eq!(
+            exec.translate(&options).unwrap()[0].to_string(),
+            "(Main.main)"
+        );
+        assert_eq!(
+            translated_functions(&exec, &options),
+            ["Main.main", "Main.test"]
+        );
+    }
+
+    /// Runs the Hack program until it reaches a tight loop and returns the RAM.
+    fn run(stmts: &[Statement]) -> Vec<u16> {
+        use asm::hack::{Comp, Instruction, Jump};
+
+        let source = stmts
+            .iter()
+            .map(|stmt| format!("{}\n", stmt))
+            .collect::<String>();
+        let insts = asm::Executable::from_reader(Cursor::new(source))
+            .unwrap()
+            .assemble()
+            .unwrap();
+        let mut ram = vec![0u16; 0x6001];
+        let (mut a, mut d, mut pc) = (0u16, 0u16, 0usize);
+        for _ in 0..100_000 {
+            let inst = match insts[pc] {
+                Instruction::A(imm) => {
+                    a = imm.value();
+                    pc += 1;
+                    continue;
+                }
+                Instruction::C(inst) => inst,
+            };
+            let m = ram[usize::from(a)];
+            let out = match inst.comp() {
+                Comp::Zero => 0,
+                Comp::One => 1,
+                Comp::MinusOne => u16::MAX,
+                Comp::D => d,
+                Comp::A => a,
+                Comp::M => m,
+                Comp::NotD => !d,
+                Comp::NotA => !a,
+                Comp::NotM => !m,
+                Comp::MinusD => d.wrapping_neg(),
+                Comp::MinusA => a.wrapping_neg(),
+                Comp::MinusM => m.wrapping_neg(),
+                Comp::DPlusOne => d.wrapping_add(1),
+                Comp::APlusOne => a.wrapping_add(1),
+                Comp::MPlusOne => m.wrapping_add(1),
+                Comp::DMinusOne => d.wrapping_sub(1),
+                Comp::AMinusOne => a.wrapping_sub(1),
+                Comp::MMinusOne => m.wrapping_sub(1),
+                Comp::DPlusA => d.wrapping_add(a),
+                Comp::DPlusM => d.wrapping_add(m),
+                Comp::DMinusA => d.wrapping_sub(a),
+                Comp::DMinusM => d.wrapping_sub(m),
+                Comp::AMinusD => a.wrapping_sub(d),
+                Comp::MMinusD => m.wrapping_sub(d),
+                Comp::DAndA => d & a,
+                Comp::DAndM => d & m,
+                Comp::DOrA => d | a,
+                Comp::DOrM => d | m,
+            };
+            let dest = inst.dest() as u8;
+            let target = usize::from(a);
+            if dest & 0b001 != 0 {
+                ram[target] = out;
+            }
+            if dest & 0b010 != 0 {
+                d = out;
+            }
+            if dest & 0b100 != 0 {
+                a = out;
+            }
+            let value = out as i16;
+            let jump = match inst.jump() {
+                Jump::Null => false,
+                Jump::Gt => value > 0,
+                Jump::Eq => value == 0,
+                Jump::Ge => value >= 0,
+                Jump::Lt => value < 0,
+                Jump::Ne => value != 0,
+                Jump::Le => value <= 0,
+                Jump::Jmp => true,
+            };
+            if jump && target + 1 == pc {
+                return ram;
+            }
+            pc = if jump { target } else { pc + 1 };
+        }
+        panic!("program does not halt");
+    }
+
+    #[test]
+    fn entry_point_returns() {
+        let exec = exec(&[(
+            "Main.vm",
+            &[
+                "function Main.test 0",
+                "push static 0",
+                "push constant 1",
+                "add",
+                "pop static 0",
+                "push constant 7",
+                "return",
+            ],
+        )]);
+        let options = TranslateOptions {
+            entry_point: Some("Main.test".to_string()),
+            ..TranslateOptions::default()
+        };
+        let ram = run(&exec.translate(&options).unwrap());
+        // the body runs only once, and the return value is left on the stack
+        assert_eq!(ram[16], 1);
+        assert_eq!(ram[0], 257);
+        assert_eq!(ram[256], 7);
+    }
+
+    #[test]
+    fn entry_point_with_segments() {
+        let exec = exec(&[(
+            "Main.vm",
+            &[
+                "function Main.test 2",
+                "push constant 5",
+                "pop argument 1",
+                "push constant 6",
+                "pop local 1",
+                "label END",
+                "goto END",
+            ],
+        )]);
+        let options = TranslateOptions {
+            entry_point: Some("Main.test".to_string()),
+            bootstrap: Bootstrap {
+                lcl: Some(Imm::try_new(300).unwrap()),
+                arg: Some(Imm::try_new(400).unwrap()),
+                ..Bootstrap::default()
+            },
+            ..TranslateOptions::default()
         };
-        entry_point
+        let ram = run(&exec.translate(&options).unwrap());
+        // no call frame is pushed, so the segments start at the given addresses
+        assert_eq!(ram[..3], [258, 300, 400]);
+        assert_eq!(ram[401], 5);
+        assert_eq!(ram[301], 6);
     }
 }
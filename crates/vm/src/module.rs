@@ -1,12 +1,16 @@
-pub use self::parser::*;
+pub use self::{parser::*, verifier::*};
+use serde::Serialize;
 use std::{borrow::Borrow, fmt};
 
 mod parser;
+mod verifier;
 
 #[derive(Debug, Clone)]
-pub(crate) struct Module {}
+pub(crate) struct Module {
+    pub(crate) warnings: Vec<ParseModuleWarning>,
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct ModuleName(String);
 
 impl Borrow<str> for ModuleName {
@@ -1,3 +1,4 @@
+use super::verifier::{self, VerifyError, VerifyWarning};
 use super::{Module, ModuleName};
 use crate::{
     Command, FuncName, FuncProp, FunctionTable, Label, ParseCommandError, ParseExecutableError,
@@ -36,20 +37,14 @@ impl Module {
                 )
             })?;
             if res == 0 {
-                parser.finish(line).map_err(|e| {
-                    ParseExecutableError::ParseModule(
-                        name.clone(),
-                        ParseModuleError::new(path.clone(), line, e),
-                    )
-                })?;
-                return Ok(Self {});
+                let warnings = parser
+                    .finish(line)
+                    .map_err(|e| ParseExecutableError::ParseModule(name.clone(), e))?;
+                return Ok(Self { warnings });
             }
-            parser.parse_line(&line_buf, line).map_err(|e| {
-                ParseExecutableError::ParseModule(
-                    name.clone(),
-                    ParseModuleError::new(path.clone(), line, e),
-                )
-            })?;
+            parser
+                .parse_line(&line_buf, line)
+                .map_err(|e| ParseExecutableError::ParseModule(name.clone(), e))?;
         }
         unreachable!()
     }
@@ -82,6 +77,8 @@ struct Parser<'a> {
     arity: u8,
     labels: LabelTable,
     commands: Vec<Command>,
+    lines: Vec<u32>,
+    warnings: Vec<ParseModuleWarning>,
 }
 
 impl<'a> Parser<'a> {
@@ -95,26 +92,40 @@ impl<'a> Parser<'a> {
             arity: 0,
             labels: LabelTable::new(),
             commands: vec![],
+            lines: vec![],
+            warnings: vec![],
         }
     }
 
-    fn parse_line(&mut self, s: &str, line: u32) -> Result<(), ParseModuleErrorKind> {
-        let command = if let Some(command) = parse_line(s)? {
+    fn error(&self, line: u32, kind: impl Into<ParseModuleErrorKind>) -> ParseModuleError {
+        ParseModuleError::new(self.path.to_owned(), line, kind)
+    }
+
+    fn parse_line(&mut self, s: &str, line: u32) -> Result<(), ParseModuleError> {
+        let command = if let Some(command) = parse_line(s).map_err(|e| self.error(line, e))? {
             command
         } else {
             return Ok(());
         };
+        self.check_command(&command, line)
+            .map_err(|e| self.error(line, e))?;
+        if let Command::Function(func_name, num_locals) = &command {
+            self.finish_func(line)?;
+            self.start_func(func_name.clone(), *num_locals);
+        }
+        self.commands.push(command);
+        self.lines.push(line);
 
-        match &command {
+        Ok(())
+    }
+
+    fn check_command(&mut self, command: &Command, line: u32) -> Result<(), ParseModuleErrorKind> {
+        match command {
             Command::Label(label) => self.labels.define(label, line)?,
             Command::Goto(label) | Command::IfGoto(label) => self.labels.use_(label, line),
-            Command::Function(func_name, num_locals) => {
-                self.finish_func(line)?;
-                self.start_func(func_name.clone(), *num_locals);
-            }
             Command::Call(func_name, arity) => {
                 self.functions
-                    .call(func_name, FuncProp::new(&self.path, line, *arity))?;
+                    .call(func_name, FuncProp::new(self.path, line, *arity))?;
             }
             Command::Push(Segment::Local, index) | Command::Pop(Segment::Local, index)
                 if *index >= u16::from(self.num_locals) =>
@@ -128,8 +139,6 @@ impl<'a> Parser<'a> {
             }
             _ => {}
         }
-        self.commands.push(command);
-
         Ok(())
     }
 
@@ -139,25 +148,40 @@ impl<'a> Parser<'a> {
         self.arity = 0;
     }
 
-    fn finish_func(&mut self, line: u32) -> Result<(), ParseModuleErrorKind> {
-        self.labels.finish()?;
+    fn finish_func(&mut self, line: u32) -> Result<(), ParseModuleError> {
+        self.labels.finish().map_err(|e| self.error(line, e))?;
         if let Some(last) = self.commands.last() {
             if !self.func_name.is_toplevel() && !last.is_jump() {
-                return Err(ParseModuleErrorKind::NoJumpCommandAtEndOfFunction(
-                    self.func_name.clone(),
+                return Err(self.error(
+                    line,
+                    ParseModuleErrorKind::NoJumpCommandAtEndOfFunction(self.func_name.clone()),
                 ));
             }
-            let prop = FuncProp::new(&self.path, line, self.arity);
+            let mut warnings = vec![];
+            verifier::stack_depths(&self.commands, &mut warnings)
+                .map_err(|(index, e)| self.error(self.lines[index], e))?;
+            self.warnings.extend(
+                warnings
+                    .into_iter()
+                    .map(|(index, kind)| ParseModuleWarning {
+                        path: self.path.to_owned(),
+                        line: self.lines[index],
+                        kind,
+                    }),
+            );
+            let prop = FuncProp::new(self.path, line, self.arity);
             let body = self.commands.drain(..).collect();
+            self.lines.clear();
             self.functions
-                .define(self.module_name, &self.func_name, prop, body)?;
+                .define(self.module_name, &self.func_name, prop, body)
+                .map_err(|e| self.error(line, e))?;
         }
         Ok(())
     }
 
-    fn finish(mut self, line: u32) -> Result<(), ParseModuleErrorKind> {
+    fn finish(mut self, line: u32) -> Result<Vec<ParseModuleWarning>, ParseModuleError> {
         self.finish_func(line)?;
-        Ok(())
+        Ok(self.warnings)
     }
 }
 
@@ -185,6 +209,25 @@ impl ParseModuleError {
     }
 }
 
+/// A problem found while parsing a module which does not prevent the translation.
+#[derive(Debug, Clone, Error)]
+#[error("{}:{}: {}", path.display(), line, kind)]
+pub struct ParseModuleWarning {
+    path: PathBuf,
+    line: u32,
+    kind: VerifyWarning,
+}
+
+impl ParseModuleWarning {
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn kind(&self) -> &VerifyWarning {
+        &self.kind
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ParseModuleErrorKind {
     #[error("IO error")]
@@ -213,6 +256,8 @@ pub enum ParseModuleErrorKind {
     CallerArityMismatch(FuncName, u8, FuncProp),
     #[error("no jump command at end of function: {}", _0)]
     NoJumpCommandAtEndOfFunction(FuncName),
+    #[error(transparent)]
+    Verify(#[from] VerifyError),
 }
 
 fn parse_line(s: &str) -> Result<Option<Command>, ParseModuleErrorKind> {
@@ -330,4 +375,30 @@ mod test {
             ) if l.as_str() == "bar"
         ));
     }
+
+    #[test]
+    fn pointer_of_caller() {
+        let source = [
+            "function Sys.init 0",
+            "push constant 3000",
+            "pop pointer 0",
+            "call Foo.f 0",
+            "return",
+            "function Foo.f 0",
+            "push this 0",
+            "return",
+        ];
+        let module = Module::from_reader(
+            "Sys.vm".into(),
+            BufReader::new(source.join("\n").into_bytes().as_slice()),
+            &mut FunctionTable::new(),
+        )
+        .unwrap();
+        assert_eq!(module.warnings.len(), 1);
+        assert_eq!(module.warnings[0].line(), 7);
+        assert!(matches!(
+            module.warnings[0].kind(),
+            VerifyWarning::PointerNotSet(Segment::This, 0)
+        ));
+    }
 }
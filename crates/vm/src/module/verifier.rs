@@ -0,0 +1,271 @@
+use crate::{Command, Label, Segment};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum VerifyError {
+    #[error(
+        "stack underflow: `{}` requires {} values, but the stack has {}",
+        _0,
+        _1,
+        _2
+    )]
+    StackUnderflow(Command, u16, u16),
+    #[error("return with empty stack")]
+    ReturnWithEmptyStack,
+    #[error("inconsistent stack depth at label {}: {} and {}", _0, _1, _2)]
+    InconsistentStackDepth(Label, u16, u16),
+    #[error("control reaches end of function")]
+    FallThroughEndOfFunction,
+}
+
+/// Suspicious code which is still valid under the VM semantics.
+#[derive(Debug, Clone, Error)]
+pub enum VerifyWarning {
+    /// THIS and THAT are inherited from the caller, so this is valid if the caller sets them.
+    #[error(
+        "segment `{}` is accessed before `pop pointer {}` on some path, using the pointer of the caller",
+        _0,
+        _1
+    )]
+    PointerNotSet(Segment, u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    depth: u16,
+    // bit 0: `pointer 0` (THIS) is set, bit 1: `pointer 1` (THAT) is set
+    pointers: u8,
+}
+
+/// Computes the stack depth before each command on every control-flow path of a function body.
+///
+/// `commands` is the body of a single function, including its `function` command if any.
+/// Unreachable commands have no depth. On error, the index of the offending command is returned.
+/// Warnings are appended to `warnings` with the index of the command, at most once per command.
+pub(crate) fn stack_depths(
+    commands: &[Command],
+    warnings: &mut Vec<(usize, VerifyWarning)>,
+) -> Result<Vec<Option<u16>>, (usize, VerifyError)> {
+    let is_function = matches!(commands.first(), Some(Command::Function(..)));
+    let labels = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(index, command)| match command {
+            Command::Label(label) => Some((label, index)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let first_warning = warnings.len();
+    // toplevel code may rely on pointers set by the test script
+    let initial_pointers = if is_function { 0b00 } else { 0b11 };
+    let mut states: Vec<Option<State>> = vec![None; commands.len()];
+    let mut to_visit = vec![(
+        0,
+        State {
+            depth: 0,
+            pointers: initial_pointers,
+        },
+    )];
+    while let Some((index, state)) = to_visit.pop() {
+        let command = match commands.get(index) {
+            Some(command) => command,
+            None if is_function => {
+                return Err((commands.len() - 1, VerifyError::FallThroughEndOfFunction))
+            }
+            None => continue,
+        };
+
+        let state = match states[index] {
+            Some(visited) if visited.depth != state.depth => {
+                let label = match command {
+                    Command::Label(label) => label.clone(),
+                    _ => unreachable!(),
+                };
+                return Err((
+                    index,
+                    VerifyError::InconsistentStackDepth(label, visited.depth, state.depth),
+                ));
+            }
+            Some(visited) if visited.pointers & state.pointers == visited.pointers => continue,
+            Some(visited) => State {
+                depth: state.depth,
+                pointers: visited.pointers & state.pointers,
+            },
+            None => state,
+        };
+        states[index] = Some(state);
+
+        let (pops, pushes) = command.stack_effect();
+        if matches!(command, Command::Return) && state.depth == 0 {
+            return Err((index, VerifyError::ReturnWithEmptyStack));
+        }
+        if state.depth < pops {
+            return Err((
+                index,
+                VerifyError::StackUnderflow(command.clone(), pops, state.depth),
+            ));
+        }
+
+        let mut next = State {
+            depth: state.depth - pops + pushes,
+            pointers: state.pointers,
+        };
+        match command {
+            Command::Push(segment @ (Segment::This | Segment::That), _)
+            | Command::Pop(segment @ (Segment::This | Segment::That), _) => {
+                let pointer = if *segment == Segment::This { 0 } else { 1 };
+                if state.pointers & (1 << pointer) == 0
+                    && !warnings[first_warning..]
+                        .iter()
+                        .any(|(warned, _)| *warned == index)
+                {
+                    warnings.push((index, VerifyWarning::PointerNotSet(*segment, pointer)));
+                }
+            }
+            Command::Pop(Segment::Pointer, pointer) => next.pointers |= 1 << pointer,
+            _ => {}
+        }
+
+        match command {
+            Command::Goto(label) => to_visit.push((labels[label], next)),
+            Command::IfGoto(label) => {
+                to_visit.push((labels[label], next));
+                to_visit.push((index + 1, next));
+            }
+            Command::Return => {}
+            _ => to_visit.push((index + 1, next)),
+        }
+    }
+
+    warnings[first_warning..].sort_by_key(|(index, _)| *index);
+    Ok(states
+        .into_iter()
+        .map(|state| state.map(|state| state.depth))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    fn commands(lines: &[&str]) -> Vec<Command> {
+        lines
+            .iter()
+            .map(|s| Command::from_str(s).unwrap())
+            .collect()
+    }
+
+    fn verify(lines: &[&str]) -> Result<Vec<Option<u16>>, (usize, VerifyError)> {
+        stack_depths(&commands(lines), &mut vec![])
+    }
+
+    fn warnings(lines: &[&str]) -> Vec<(usize, VerifyWarning)> {
+        let mut warnings = vec![];
+        stack_depths(&commands(lines), &mut warnings).unwrap();
+        warnings
+    }
+
+    #[test]
+    fn depths() {
+        assert_eq!(
+            verify(&[
+                "function f 0",
+                "push argument 0",
+                "if-goto A",
+                "push constant 1",
+                "return",
+                "label A",
+                "push constant 2",
+                "return",
+            ])
+            .unwrap(),
+            [
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(0),
+                Some(1),
+                Some(0),
+                Some(0),
+                Some(1)
+            ]
+        );
+        assert_eq!(
+            verify(&["push constant 1", "return", "push constant 2"]).unwrap(),
+            [Some(0), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            verify(&["function f 0", "push constant 1", "add", "return"]),
+            Err((2, VerifyError::StackUnderflow(Command::Add, 2, 1)))
+        ));
+        assert!(matches!(
+            verify(&["function f 0", "return"]),
+            Err((1, VerifyError::ReturnWithEmptyStack))
+        ));
+        assert!(matches!(
+            verify(&[
+                "function f 0",
+                "push constant 0",
+                "if-goto A",
+                "push constant 1",
+                "label A",
+                "push constant 2",
+                "return",
+            ]),
+            Err((4, VerifyError::InconsistentStackDepth(l, _, _))) if l.as_str() == "A"
+        ));
+        assert!(matches!(
+            verify(&["function f 0", "push constant 0", "if-goto A", "label A"]),
+            Err((3, VerifyError::FallThroughEndOfFunction))
+        ));
+        assert!(verify(&["push this 0", "push that 0", "add"]).is_ok());
+    }
+
+    #[test]
+    fn pointer_not_set() {
+        let found = warnings(&[
+            "function f 0",
+            "push argument 0",
+            "if-goto A",
+            "push constant 0",
+            "pop pointer 1",
+            "label A",
+            "push that 0",
+            "push this 0",
+            "add",
+            "push this 1",
+            "add",
+            "return",
+        ]);
+        let found = found
+            .iter()
+            .map(|(index, VerifyWarning::PointerNotSet(segment, pointer))| {
+                (*index, *segment, *pointer)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (6, Segment::That, 1),
+                (7, Segment::This, 0),
+                (9, Segment::This, 0)
+            ]
+        );
+
+        assert!(warnings(&[
+            "function f 0",
+            "push constant 0",
+            "pop pointer 0",
+            "push this 0",
+            "return",
+        ])
+        .is_empty());
+    }
+}
@@ -1,25 +1,52 @@
-use color_eyre::eyre::{ensure, Context, Result};
+use color_eyre::eyre::{bail, ensure, eyre, Context, Result};
 use common::{
     fs::{DirOrFileReader, FileWriter},
     iter::TryIterator,
+    watch::Watcher,
+};
+use std::{
+    env,
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
+use vm::{
+    asm::{hack::Imm, Statement},
+    Executable, TranslateOptions,
 };
-use std::{env, io::prelude::*, path::PathBuf};
-use vm::{asm::Statement, Executable};
 
 #[derive(Debug)]
 struct Params {
     input_path: PathBuf,
     output_path: PathBuf,
+    options: TranslateOptions,
+    call_graph_path: Option<PathBuf>,
+    cfg_path: Option<PathBuf>,
+    prune_report: bool,
+    watch: bool,
 }
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let params = parse_args()?;
+    if params.watch {
+        // every output depends on every module, so any change retranslates them all
+        Watcher::new([params.input_path.clone()], "vm").run(|_| build(&params));
+    }
+    build(&params)
+}
+
+fn build(params: &Params) -> Result<()> {
     let Params {
         input_path,
         output_path,
-    } = parse_args()?;
+        options,
+        call_graph_path,
+        cfg_path,
+        prune_report,
+        watch: _,
+    } = params;
 
-    let files = DirOrFileReader::open(&input_path, "vm")
+    let files = DirOrFileReader::open(input_path, "vm")
         .wrap_err_with(|| format!("failed to open input file: {}", input_path.display()))?;
 
     let input_modules = files
@@ -28,9 +55,47 @@ fn main() -> Result<()> {
         .wrap_err_with(|| format!("failed to open input file: {}", input_path.display()))?;
 
     let exec = Executable::from_readers(input_modules).wrap_err("failed to open executable")?;
-    let stmts = exec.translate();
+    for warning in exec.warnings() {
+        eprintln!("warning: {}", warning);
+    }
+    if let Some(path) = &call_graph_path {
+        let graph = exec.call_graph(options);
+        write_graph_file(path, |writer| match GraphFormat::from_path(path) {
+            GraphFormat::Dot => graph.write_dot(writer),
+            GraphFormat::Json => graph.write_json(writer),
+        })?;
+    }
+    if let Some(path) = &cfg_path {
+        let graph = exec.control_flow_graph(options);
+        write_graph_file(path, |writer| match GraphFormat::from_path(path) {
+            GraphFormat::Dot => graph.write_dot(writer),
+            GraphFormat::Json => graph.write_json(writer),
+        })?;
+    }
 
-    let mut writer = FileWriter::open(&output_path)
+    for name in &options.keep {
+        if !exec.contains_function(name) {
+            eprintln!("warning: function to keep is not defined: {}", name);
+        }
+    }
+    let report = exec
+        .prune_report(options)
+        .wrap_err("failed to translate executable")?;
+    for module_name in &report.unreachable_modules {
+        eprintln!(
+            "warning: no function in module {} is reachable from the entry point",
+            module_name
+        );
+    }
+    if *prune_report {
+        print!("{}", report);
+    }
+
+    let stmts = exec
+        .translate(options)
+        .wrap_err("failed to translate executable")?;
+
+    let mut writer = FileWriter::open(output_path)
         .wrap_err_with(|| format!("failed to create output file: {}", output_path.display()))?;
     write_output_file(writer.writer(), &stmts)
         .wrap_err_with(|| format!("failed to write output file: {}", output_path.display()))?;
@@ -42,17 +107,95 @@ fn main() -> Result<()> {
 }
 
 fn parse_args() -> Result<Params> {
-    let args = env::args().collect::<Vec<_>>();
-    ensure!(args.len() == 2, "Usage: {} <file>", args[0]);
-    create_params(PathBuf::from(&args[1]), None)
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "vmtrans".to_string());
+    let usage = || {
+        format!(
+            "Usage: {} [--inline-budget <n>] [--no-prune] [--keep <function>]... [--prune-report] \
+             [--entry <function>] [--no-bootstrap] [--sp <address>] [--lcl <address>] \
+             [--arg <address>] [--this <address>] [--that <address>] \
+             [--call-graph <file>] [--cfg <file>] [--watch] <file>",
+            program
+        )
+    };
+
+    let mut input_path = None;
+    let mut options = TranslateOptions::default();
+    let mut call_graph_path = None;
+    let mut cfg_path = None;
+    let mut prune_report = false;
+    let mut watch = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--inline-budget" => {
+                let value = next_value(&mut args, usage)?;
+                options.inline_budget = value
+                    .parse()
+                    .wrap_err_with(|| format!("invalid inline budget: {}", value))?;
+            }
+            "--no-prune" => options.no_prune = true,
+            "--keep" => options.keep.push(next_value(&mut args, usage)?),
+            "--prune-report" => prune_report = true,
+            "--entry" => options.entry_point = Some(next_value(&mut args, usage)?),
+            "--no-bootstrap" => options.no_bootstrap = true,
+            "--sp" => options.bootstrap.sp = parse_address(next_value(&mut args, usage)?)?,
+            "--lcl" => options.bootstrap.lcl = Some(parse_address(next_value(&mut args, usage)?)?),
+            "--arg" => options.bootstrap.arg = Some(parse_address(next_value(&mut args, usage)?)?),
+            "--this" => {
+                options.bootstrap.this = Some(parse_address(next_value(&mut args, usage)?)?)
+            }
+            "--that" => {
+                options.bootstrap.that = Some(parse_address(next_value(&mut args, usage)?)?)
+            }
+            "--call-graph" => {
+                call_graph_path = Some(PathBuf::from(next_value(&mut args, usage)?));
+            }
+            "--cfg" => {
+                cfg_path = Some(PathBuf::from(next_value(&mut args, usage)?));
+            }
+            "--watch" => watch = true,
+            _ if arg.starts_with('-') => bail!(usage()),
+            _ => {
+                ensure!(input_path.is_none(), usage());
+                input_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+    let input_path = input_path.ok_or_else(|| eyre!(usage()))?;
+
+    let mut params = create_params(input_path, None, options)?;
+    params.call_graph_path = call_graph_path;
+    params.cfg_path = cfg_path;
+    params.prune_report = prune_report;
+    params.watch = watch;
+    Ok(params)
+}
+
+fn next_value(
+    args: &mut impl Iterator<Item = String>,
+    usage: impl Fn() -> String,
+) -> Result<String> {
+    args.next().ok_or_else(|| eyre!(usage()))
+}
+
+fn parse_address(value: String) -> Result<Imm> {
+    value
+        .parse()
+        .ok()
+        .and_then(Imm::try_new)
+        .ok_or_else(|| eyre!("invalid address: {}", value))
 }
 
-fn create_params(input_path: PathBuf, output_path: Option<PathBuf>) -> Result<Params> {
+fn create_params(
+    input_path: PathBuf,
+    output_path: Option<PathBuf>,
+    options: TranslateOptions,
+) -> Result<Params> {
     let output_path = output_path.unwrap_or_else(|| {
         if input_path.is_dir() {
             let mut output_name = input_path
                 .components()
-                .last()
+                .next_back()
                 .unwrap()
                 .as_os_str()
                 .to_owned();
@@ -66,9 +209,43 @@ fn create_params(input_path: PathBuf, output_path: Option<PathBuf>) -> Result<Pa
     Ok(Params {
         input_path,
         output_path,
+        options,
+        call_graph_path: None,
+        cfg_path: None,
+        prune_report: false,
+        watch: false,
     })
 }
 
+#[derive(Debug, Clone, Copy)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            _ => Self::Dot,
+        }
+    }
+}
+
+fn write_graph_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<()> {
+    let mut writer = FileWriter::open(path)
+        .wrap_err_with(|| format!("failed to create output file: {}", path.display()))?;
+    write(writer.writer())
+        .wrap_err_with(|| format!("failed to write output file: {}", path.display()))?;
+    writer
+        .persist()
+        .wrap_err_with(|| format!("failed to persist output file: {}", path.display()))?;
+    Ok(())
+}
+
 fn write_output_file(mut writer: impl Write, insts: &[Statement]) -> Result<()> {
     for inst in insts {
         writeln!(writer, "{}", inst)?;
@@ -92,7 +92,7 @@ $(TARGET_AST_XML): $(TARGET_DIR)/.jack.analyzed
 $(TARGET_TYPED_AST_XML): $(TARGET_DIR)/.jack.analyzed
 
 $(TARGET_DIR)/.jack.analyzed: $(TARGET_JACK) $(JACK_ANALYZER) | $(TARGET_DIR)/
-	$(JACK_ANALYZER) $(TARGET_DIR)
+	$(JACK_ANALYZER) compile --emit token,ast,typed-ast,vm $(TARGET_DIR)
 	touch $@
 
 $(TARGET_DIR)/%.jack: $(OS_DIR)/%.jack | $(TARGET_DIR)/